@@ -1,7 +1,8 @@
 use crate::benchmark::{BenchmarkResult, DataPoint};
 use crate::bloom::fimpl::BloomFilterImpl;
+use crate::bloom::sized::SizedBloom;
 use crate::bloom::strategies::{SfHash64, SipHash, XxHash};
-use crate::bloom::HashStrategy;
+use crate::bloom::{BlockedBloomFilter, BloomFilter, Filter, HashStrategy, XorFilter};
 use crate::record::ByteStream;
 use std::time::Instant;
 
@@ -123,3 +124,100 @@ pub fn sfhash64_rp() -> Vec<BenchmarkResult> {
         "bloom-sfhash64-perf",
     )
 }
+
+/// Run benchmarks on filter layouts, sized for the number of keys they hold at
+/// 10 bits per key.
+fn run_layout<F>(
+    build: fn(&[ByteStream]) -> F,
+    fp_rate_title: &str,
+    perf_title: &str,
+) -> Vec<BenchmarkResult>
+where
+    F: Filter,
+{
+    // initialize containers
+    let mut fprate_result = BenchmarkResult {
+        title: fp_rate_title.to_string(),
+        data: vec![],
+    };
+    let mut perf_result = BenchmarkResult {
+        title: perf_title.to_string(),
+        data: vec![],
+    };
+
+    let scales = vec![10000, 40000, 100000, 400000, 1000000, 4000000];
+    for scale in scales {
+        // load data
+        let positives: Vec<ByteStream> = (0..scale)
+            .map(|i| ByteStream::from_slice(format!("Positive_{i}_suffix!").as_bytes()))
+            .collect();
+        let negatives: Vec<ByteStream> = (0..scale)
+            .map(|i| ByteStream::from_slice(format!("Negative_{i}_suffix!!").as_bytes()))
+            .collect();
+        let bf = build(&positives);
+
+        // count false positives, which are also timed as lookups
+        let loop_time = Instant::now();
+        let mut fp_count = 0;
+        for message in negatives.iter() {
+            if bf.query(message.as_ref()) {
+                fp_count += 1;
+            }
+        }
+        let loop_time = loop_time.elapsed().as_nanos();
+
+        // write data points
+        fprate_result.data.push(DataPoint {
+            x: scale as f64,
+            y: (fp_count as f64) / (scale as f64),
+        });
+        perf_result.data.push(DataPoint {
+            x: scale as f64,
+            y: scale as f64 / ((loop_time as f64) / 1.0e9),
+        });
+    }
+
+    vec![fprate_result, perf_result]
+}
+
+pub fn sized_layout_rp() -> Vec<BenchmarkResult> {
+    run_layout(
+        |keys| {
+            let mut bf = BloomFilter::new(keys.len(), 10);
+            for key in keys {
+                bf.insert(key.as_ref());
+            }
+            bf
+        },
+        "bloom-sized-fprate",
+        "bloom-sized-perf",
+    )
+}
+
+pub fn blocked_layout_rp() -> Vec<BenchmarkResult> {
+    run_layout(
+        |keys| {
+            let mut bf = BlockedBloomFilter::new(keys.len(), 10);
+            for key in keys {
+                bf.insert(key.as_ref());
+            }
+            bf
+        },
+        "bloom-blocked-fprate",
+        "bloom-blocked-perf",
+    )
+}
+
+pub fn xor_layout_rp() -> Vec<BenchmarkResult> {
+    run_layout(
+        |keys| {
+            XorFilter::build(
+                keys.iter()
+                    .map(|key| XorFilter::hash(key.as_ref()))
+                    .collect(),
+            )
+        },
+        "xor-fprate",
+        "xor-perf",
+    )
+}
@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::benchmark::{BenchmarkResult, DataPoint};
+use crate::memtable::rbtree::RBTree;
+use crate::memtable::MemTable;
+use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvMergeIterator, KvPointer};
+use crate::sstable::reader::SSTableReader;
+use crate::sstable::writer::SSTableWriter;
+
+fn get_tmp_filename(table_id: usize) -> PathBuf {
+    let mut tmp_dir = std::env::temp_dir();
+    tmp_dir.push(format!("_kleestor_kvmerge_bench_table_{table_id}.db"));
+    tmp_dir
+}
+
+/// Writes `tables` SSTables sharing `keys` keys round-robin. Every 16th key
+/// is also written to the table after its own, which it shadows.
+fn create_tables(tables: usize, keys: usize) -> Vec<SSTableReader> {
+    let global_offset = 1000000_usize;
+    let mut maps: Vec<RBTree<ByteStream, KvEntry>> = (0..tables).map(|_| RBTree::new()).collect();
+    for _i in 0..keys {
+        let i = global_offset + _i;
+        let key = format!("sample-key-{i}");
+        let value = format!("value-{i}-0123456789abcde-0123456789abcde-{i}");
+        let mut owners = vec![_i % tables];
+        if _i % 16 == 0 {
+            owners.push((_i + 1) % tables);
+        }
+        for table_id in owners {
+            maps[table_id].insert(
+                ByteStream::from_slice(key.as_bytes()),
+                KvEntry::new(KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(value.as_bytes()),
+                }),
+            );
+        }
+    }
+
+    let mut readers = vec![];
+    for (table_id, map) in maps.iter_mut().enumerate() {
+        let file = std::fs::File::create(get_tmp_filename(table_id)).unwrap();
+        SSTableWriter::new(file).write(map.iter_mut()).unwrap();
+        let file = std::fs::File::open(get_tmp_filename(table_id)).unwrap();
+        readers.push(SSTableReader::new(file).unwrap());
+    }
+    readers
+}
+
+/// Run merge benchmarks over increasing numbers of SSTables, as compactions
+/// of many runs do.
+pub fn run() -> Vec<BenchmarkResult> {
+    let params: Vec<usize> = vec![2, 4, 8, 16, 32, 64, 128, 256];
+    let keys = 500000_usize;
+
+    // prepare dataset outputs
+    let mut merge_tps_result = BenchmarkResult {
+        // merged records per second, by the number of tables
+        title: "kvmerge-sstables-tps".to_string(),
+        data: vec![],
+    };
+    let mut merge_rev_tps_result = BenchmarkResult {
+        title: "kvmerge-sstables-rev-tps".to_string(),
+        data: vec![],
+    };
+
+    // start working
+    for tables in &params {
+        let tables = *tables;
+        let readers = create_tables(tables, keys);
+
+        // merge all tables in ascending order
+        let duration = Instant::now();
+        let iters = readers.iter().map(|reader| reader.iter()).collect();
+        let mut preserve_data = 0_usize;
+        let mut count = 0_usize;
+        for item in KvMergeIterator::new(iters) {
+            match item.value() {
+                KvDataRef::Tombstone { .. } => preserve_data += 1,
+                KvDataRef::Value { value, .. } => preserve_data += value.len(),
+            };
+            count += 1;
+        }
+        assert_eq!(count, keys);
+
+        let duration = duration.elapsed().as_nanos() + (preserve_data as u128 % 233);
+        merge_tps_result.data.push(DataPoint {
+            x: tables as f64,
+            y: count as f64 / ((duration as f64) / 1.0e9),
+        });
+
+        // and in descending order
+        let duration = Instant::now();
+        let iters = readers.iter().map(|reader| reader.iter_rev()).collect();
+        let mut count = 0_usize;
+        for item in KvMergeIterator::new_descending(iters) {
+            match item.value() {
+                KvDataRef::Tombstone { .. } => preserve_data += 1,
+                KvDataRef::Value { value, .. } => preserve_data += value.len(),
+            };
+            count += 1;
+        }
+        assert_eq!(count, keys);
+
+        let duration = duration.elapsed().as_nanos() + (preserve_data as u128 % 233);
+        merge_rev_tps_result.data.push(DataPoint {
+            x: tables as f64,
+            y: count as f64 / ((duration as f64) / 1.0e9),
+        });
+
+        // cleanup
+        drop(readers);
+        for table_id in 0..tables {
+            let _ = std::fs::remove_file(get_tmp_filename(table_id));
+        }
+    }
+
+    // collect results
+    vec![merge_tps_result, merge_rev_tps_result]
+}
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::benchmark::{BenchmarkResult, DataPoint};
+use crate::lsmt::mgr::LsmTree;
+use crate::lsmt::options::Options;
+use crate::record::ByteStream;
+use futures::executor::block_on;
+
+fn get_tmp_dirname() -> PathBuf {
+    let mut tmp_dir = std::env::temp_dir();
+    tmp_dir.push("_kleestor_lsmt_bench");
+    tmp_dir
+}
+
+/// Inserts `writes` records from `threads` threads sharing one database, and
+/// returns the number of writes per second. With `overwrite` set, all threads
+/// write the same few keys.
+fn write_tps(threads: usize, writes: usize, overwrite: bool) -> f64 {
+    let _ = std::fs::remove_dir_all(get_tmp_dirname());
+    let db = LsmTree::open(get_tmp_dirname(), Options::default()).unwrap();
+
+    let duration = Instant::now();
+    std::thread::scope(|s| {
+        for t in 0..threads {
+            let db = &db;
+            s.spawn(move || {
+                for i in (t..writes).step_by(threads) {
+                    let i = if overwrite { i % 64 } else { i };
+                    let key = format!("sample-key-{i}");
+                    let value = format!("value-{t}-0123456789abcde-0123456789abcde-{i}");
+                    let key = ByteStream::from_slice(key.as_bytes());
+                    let value = ByteStream::from_slice(value.as_bytes());
+                    block_on(db.raw_insert(key, value)).unwrap();
+                }
+            });
+        }
+    });
+    let duration = duration.elapsed().as_nanos();
+
+    // cleanup
+    block_on(db.close()).unwrap();
+    let _ = std::fs::remove_dir_all(get_tmp_dirname());
+    writes as f64 / ((duration as f64) / 1.0e9)
+}
+
+/// Run write benchmarks on the LSM tree over increasing numbers of threads
+/// writing at the same time.
+pub fn run() -> Vec<BenchmarkResult> {
+    let params: Vec<usize> = vec![1, 2, 4, 8, 16];
+    let writes = 200000_usize;
+
+    // prepare dataset outputs
+    let mut insert_tps_result = BenchmarkResult {
+        // writes per second, by the number of threads
+        title: "lsmt-concurrent-insert-tps".to_string(),
+        data: vec![],
+    };
+    let mut overwrite_tps_result = BenchmarkResult {
+        title: "lsmt-concurrent-overwrite-tps".to_string(),
+        data: vec![],
+    };
+
+    // start working
+    for threads in &params {
+        let threads = *threads;
+        insert_tps_result.data.push(DataPoint {
+            x: threads as f64,
+            y: write_tps(threads, writes, false),
+        });
+        overwrite_tps_result.data.push(DataPoint {
+            x: threads as f64,
+            y: write_tps(threads, writes, true),
+        });
+    }
+
+    // collect results
+    vec![insert_tps_result, overwrite_tps_result]
+}
@@ -1,4 +1,6 @@
 mod bloomf;
+mod kvmerge;
+mod lsmt;
 mod memtable;
 mod nstree;
 mod sstable;
@@ -82,14 +84,26 @@ impl BenchmarkManager {
         self.add(nstree::btreeunsafe_seq_rw::<7>());
         self.add(nstree::splay_rand_rw());
         self.add(nstree::splay_seq_rw());
+        self.add(nstree::skiplist_rand_rw());
+        self.add(nstree::skiplist_seq_rw());
 
         self.add(memtable::run());
 
         self.add(sstable::run());
+        self.add(sstable::run_lz4());
+        self.add(sstable::run_zstd());
+        self.add(sstable::run_snappy());
+
+        self.add(kvmerge::run());
+
+        self.add(lsmt::run());
 
         self.add(bloomf::siphash_rp());
         self.add(bloomf::xxhash_rp());
         self.add(bloomf::sfhash64_rp());
+        self.add(bloomf::sized_layout_rp());
+        self.add(bloomf::blocked_layout_rp());
+        self.add(bloomf::xor_layout_rp());
     }
 
     /// Add records to the result.
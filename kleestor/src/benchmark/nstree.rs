@@ -3,6 +3,7 @@ use crate::memtable::btree::BTreeImpl;
 use crate::memtable::btree_builtin::BTreeBuiltin;
 use crate::memtable::btree_unsafe::BTreeUnsafe;
 use crate::memtable::rbtree::RBTree;
+use crate::memtable::skiplist::SkipList;
 use crate::memtable::splay::SplayTree;
 use crate::memtable::MemTable;
 use std::time::Instant;
@@ -121,6 +122,24 @@ pub fn rbtree_seq_rw() -> Vec<BenchmarkResult> {
     )
 }
 
+pub fn skiplist_rand_rw() -> Vec<BenchmarkResult> {
+    run(
+        Box::from(SkipList::new()),
+        "memtable-skiplist-rand-read",
+        "memtable-skiplist-rand-write",
+        TestMode::Random,
+    )
+}
+
+pub fn skiplist_seq_rw() -> Vec<BenchmarkResult> {
+    run(
+        Box::from(SkipList::new()),
+        "memtable-skiplist-seq-read",
+        "memtable-skiplist-seq-write",
+        TestMode::Sequential,
+    )
+}
+
 pub fn btreebuiltin_rand_rw() -> Vec<BenchmarkResult> {
     run(
         Box::from(BTreeBuiltin::new()),
@@ -5,8 +5,9 @@ use crate::benchmark::{BenchmarkResult, DataPoint};
 use crate::memtable::rbtree::RBTree;
 use crate::memtable::MemTable;
 use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer};
+use crate::sstable::compression::Compression;
 use crate::sstable::reader::SSTableReader;
-use crate::sstable::writer::SSTableWriter;
+use crate::sstable::writer::{SSTableWriter, WriterOptions};
 
 fn get_tmp_filename(run_id: i64) -> PathBuf {
     let mut tmp_dir = std::env::temp_dir();
@@ -14,7 +15,7 @@ fn get_tmp_filename(run_id: i64) -> PathBuf {
     tmp_dir
 }
 
-fn run_impl(name_prefix: &str) -> Vec<BenchmarkResult> {
+fn run_impl(name_prefix: &str, compression: Compression) -> Vec<BenchmarkResult> {
     // set parameters (run_id, max_size);
     let params: Vec<(i64, i64)> = vec![
         (0, 5000),
@@ -66,6 +67,11 @@ fn run_impl(name_prefix: &str) -> Vec<BenchmarkResult> {
         title: format!("{name_prefix}-seq-scan-speed").to_string(),
         data: vec![],
     };
+    let mut compression_ratio_result = BenchmarkResult {
+        // raw bytes per byte on disk
+        title: format!("{name_prefix}-compression-ratio").to_string(),
+        data: vec![],
+    };
 
     // start working
     for (run_id, counter_limit) in &params {
@@ -95,7 +101,13 @@ fn run_impl(name_prefix: &str) -> Vec<BenchmarkResult> {
         // write memtable to disk
         let duration = Instant::now();
         let file = std::fs::File::create(&get_tmp_filename(run_id)).unwrap();
-        let mut writer = SSTableWriter::new(file);
+        let mut writer = SSTableWriter::with_options(
+            file,
+            WriterOptions {
+                compression,
+                ..Default::default()
+            },
+        );
         writer.write(map.iter_mut()).unwrap();
         drop(writer);
 
@@ -109,10 +121,16 @@ fn run_impl(name_prefix: &str) -> Vec<BenchmarkResult> {
             y: total_bytes as f64 / ((duration as f64) / 1.0e9),
         });
 
+        let file_size = std::fs::metadata(get_tmp_filename(run_id)).unwrap().len();
+        compression_ratio_result.data.push(DataPoint {
+            x: total_bytes as f64,
+            y: total_bytes as f64 / file_size as f64,
+        });
+
         // read memtable from disk (run seq scan)
         let duration = Instant::now();
         let file = std::fs::File::open(&get_tmp_filename(run_id)).unwrap();
-        let mut reader = SSTableReader::new(file).unwrap();
+        let reader = SSTableReader::new(file).unwrap();
         let mut preserve_data = 0;
         for item in reader.iter() {
             match item.value() {
@@ -140,7 +158,7 @@ fn run_impl(name_prefix: &str) -> Vec<BenchmarkResult> {
         for _i in 0..counter_limit {
             let i = global_offset + _i;
             let key = format!("sample-key-{i}");
-            match reader.get(key.as_bytes()).unwrap() {
+            match reader.get(key.as_bytes()).unwrap().unwrap() {
                 KvData::Tombstone { .. } => preserve_data += 1,
                 KvData::Value { value, .. } => {
                     for ch in value.as_ref() {
@@ -165,7 +183,7 @@ fn run_impl(name_prefix: &str) -> Vec<BenchmarkResult> {
         for _i in 0..counter_limit {
             let i = global_offset + prime * _i % counter_limit;
             let key = format!("sample-key-{i}");
-            match reader.get(key.as_bytes()).unwrap() {
+            match reader.get(key.as_bytes()).unwrap().unwrap() {
                 KvData::Tombstone { .. } => preserve_data += 1,
                 KvData::Value { value, .. } => {
                     for ch in value.as_ref() {
@@ -201,10 +219,26 @@ fn run_impl(name_prefix: &str) -> Vec<BenchmarkResult> {
         rand_read_speed_result,
         seq_scan_tps_result,
         seq_scan_speed_result,
+        compression_ratio_result,
     ]
 }
 
 /// Run I/O performance benchmarks on SSTable.
 pub fn run() -> Vec<BenchmarkResult> {
-    run_impl("sstable")
+    run_impl("sstable", Compression::None)
+}
+
+/// Run I/O performance benchmarks on SSTable with LZ4 compressed blocks.
+pub fn run_lz4() -> Vec<BenchmarkResult> {
+    run_impl("sstable-lz4", Compression::Lz4)
+}
+
+/// Run I/O performance benchmarks on SSTable with Zstd compressed blocks.
+pub fn run_zstd() -> Vec<BenchmarkResult> {
+    run_impl("sstable-zstd", Compression::Zstd)
+}
+
+/// Run I/O performance benchmarks on SSTable with Snappy compressed blocks.
+pub fn run_snappy() -> Vec<BenchmarkResult> {
+    run_impl("sstable-snappy", Compression::Snappy)
 }
@@ -0,0 +1,141 @@
+use crate::bloom::sized::SizedBloom;
+use crate::bloom::{Filter, HashStrategy};
+use std::marker::PhantomData;
+use std::slice;
+
+/// Bytes in a block, which is the size of a cache line.
+pub const BLOCK_BYTES: usize = 64;
+
+/// Bits in a block.
+const BLOCK_BITS: u32 = BLOCK_BYTES as u32 * 8;
+
+/// A block aligned to the start of a cache line.
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+struct CacheLine([u8; BLOCK_BYTES]);
+
+/// Bloom filter split into cache-line sized blocks. The lower half of the hash
+/// of a key picks one block, and the upper half all positions inside of it, so
+/// that each insertion or query misses the cache at most once.
+///
+/// Keys crowd unevenly into blocks, which costs a slightly higher false
+/// positive rate than a [`SizedBloomFilter`](crate::bloom::sized::SizedBloomFilter)
+/// of the same size.
+pub struct BlockedBloomFilterImpl<Hasher>
+where
+    Hasher: HashStrategy<32, 2>,
+{
+    data: Vec<CacheLine>,
+
+    /// Number of positions set per key.
+    hashes: u32,
+
+    _marker: PhantomData<Hasher>,
+}
+
+/// The implementation is guaranteed to be endian-safe.
+impl<Hasher> BlockedBloomFilterImpl<Hasher>
+where
+    Hasher: HashStrategy<32, 2>,
+{
+    /// Creates bloom filter from memory slice, with the number of hashes it
+    /// was built with. The slice must hold a whole number of blocks.
+    pub fn from_slice(hashes: u32, region: &[u8]) -> Self {
+        let data = region
+            .chunks_exact(BLOCK_BYTES)
+            .map(|block| CacheLine(block.try_into().unwrap()))
+            .collect();
+        Self {
+            data,
+            hashes,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of positions set per key.
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// Query actual size in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len() * BLOCK_BYTES
+    }
+
+    /// Accesses the filter bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.data.as_ptr() as *const u8, self.size()) }
+    }
+
+    /// Block that a key falls into, mapping the lower half of its hash onto
+    /// the blocks without a division.
+    fn block_of(&self, hash: [u32; 2]) -> usize {
+        ((hash[0] as u64 * self.data.len() as u64) >> 32) as usize
+    }
+
+    /// Bit positions of a key inside its block, walking the block in steps of
+    /// the rotated upper half of its hash. Odd steps never revisit a position.
+    fn positions(hashes: u32, hash: [u32; 2]) -> impl Iterator<Item = usize> {
+        let step = hash[1].rotate_right(17) | 1;
+        (0..hashes).map(move |i| (hash[1].wrapping_add(i.wrapping_mul(step)) % BLOCK_BITS) as usize)
+    }
+}
+
+impl<Hasher> SizedBloom for BlockedBloomFilterImpl<Hasher>
+where
+    Hasher: HashStrategy<32, 2>,
+{
+    type Hasher = Hasher;
+
+    fn with_bits(bits: usize, hashes: u32) -> Self {
+        let blocks = usize::max(bits, 1).div_ceil(BLOCK_BITS as usize);
+        Self {
+            data: vec![CacheLine([0_u8; BLOCK_BYTES]); blocks],
+            hashes,
+            _marker: PhantomData,
+        }
+    }
+
+    fn insert_hash(&mut self, hash: [u32; 2]) {
+        let hashes = self.hashes;
+        let block = self.block_of(hash);
+        let block = &mut self.data[block].0;
+        for position in Self::positions(hashes, hash) {
+            block[position >> 3] |= 1_u8 << (position & 0x07);
+        }
+    }
+
+    fn query_hash(&self, hash: [u32; 2]) -> bool {
+        let block = &self.data[self.block_of(hash)].0;
+        Self::positions(self.hashes, hash)
+            .all(|position| block[position >> 3] & (1_u8 << (position & 0x07)) != 0)
+    }
+}
+
+impl<Hasher> Filter for BlockedBloomFilterImpl<Hasher>
+where
+    Hasher: HashStrategy<32, 2> + Send + Sync,
+{
+    fn query(&self, message: &[u8]) -> bool {
+        SizedBloom::query(self, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockedBloomFilterImpl, BLOCK_BYTES};
+    use crate::bloom::sized::tests::check_layout;
+    use crate::bloom::strategies::SfHash64;
+
+    #[test]
+    fn sized_by_keys() {
+        let scale = 20000;
+        // a little above 1% false positives
+        let bf = check_layout(scale, 40, |bf: &BlockedBloomFilterImpl<SfHash64<32, 2>>| {
+            BlockedBloomFilterImpl::from_slice(bf.hashes(), bf.as_slice())
+        });
+        assert_eq!(bf.size() % BLOCK_BYTES, 0);
+        assert!(bf.size() >= scale * 10 / 8 && bf.size() < scale * 10 / 8 + BLOCK_BYTES);
+        assert_eq!(bf.hashes(), 7);
+    }
+}
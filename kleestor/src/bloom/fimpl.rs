@@ -1,4 +1,4 @@
-use crate::bloom::HashStrategy;
+use crate::bloom::{Filter, HashStrategy};
 use std::{io::Write, marker::PhantomData};
 
 /// Common implementation for bloom filters on different hash sizes and hash
@@ -80,6 +80,11 @@ where
         1_usize << (ML - 3)
     }
 
+    /// Accesses the filter bytes, as written by [`write`].
+    pub fn as_slice(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
     /// Write bytes to disk.
     pub fn write(&self, file: &mut std::fs::File) -> std::io::Result<usize> {
         file.write(self.data.as_slice())
@@ -94,6 +99,17 @@ where
     }
 }
 
+impl<Hasher, const ML: usize, const K: usize> Filter for BloomFilterImpl<Hasher, ML, K>
+where
+    [(); K]: Sized,
+    [(); 1 << (ML - 3)]: Sized,
+    Hasher: HashStrategy<ML, K> + Send + Sync,
+{
+    fn query(&self, message: &[u8]) -> bool {
+        BloomFilterImpl::query(self, message)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::BloomFilterImpl;
@@ -1,8 +1,14 @@
+pub mod blocked;
 pub mod fimpl;
+pub mod sized;
 pub mod strategies;
+pub mod xor;
 
+use self::blocked::BlockedBloomFilterImpl;
 use self::fimpl::BloomFilterImpl;
+use self::sized::SizedBloomFilter;
 use self::strategies::SfHash64;
+use self::xor::XorFilterImpl;
 
 /// A hash strategy that produces K positions for a bloom filter on a span of
 /// 2^ML slots in total.
@@ -13,5 +19,22 @@ where
     fn hash(message: &[u8]) -> [u32; K];
 }
 
+/// Answers whether a key might have been added to a set. Filters are shared
+/// by all threads reading a table.
+pub trait Filter: Send + Sync {
+    /// A `false` result indicates a definite 'not exist', while a `true` might
+    /// inflict a false positive.
+    fn query(&self, message: &[u8]) -> bool;
+}
+
 /// Default bloom filter constructor.
-pub type BloomFilter = BloomFilterImpl<SfHash64<24, 2>, 24, 2>;
+pub type BloomFilter = SizedBloomFilter<SfHash64<32, 2>>;
+
+/// Bloom filter probing a single cache line per key.
+pub type BlockedBloomFilter = BlockedBloomFilterImpl<SfHash64<32, 2>>;
+
+/// Static filter built once from all keys of a table.
+pub type XorFilter = XorFilterImpl<SfHash64<32, 2>>;
+
+/// Bloom filter of format v1 tables, which is always 2 MiB large.
+pub type LegacyBloomFilter = BloomFilterImpl<SfHash64<24, 2>, 24, 2>;
@@ -0,0 +1,180 @@
+use crate::bloom::{Filter, HashStrategy};
+use std::marker::PhantomData;
+
+/// Largest number of hashes per key of a [`SizedBloom`].
+pub const MAX_HASHES: u32 = 30;
+
+/// Bloom filters whose size and number of hashes are chosen at runtime, from
+/// the number of keys they are built for and the bits to spend on each of
+/// them. Layouts only differ in where the positions of a key are.
+///
+/// Every key is hashed only once into two 32-bit halves, from which all
+/// positions are derived.
+pub trait SizedBloom: Sized {
+    type Hasher: HashStrategy<32, 2>;
+
+    /// Creates new empty bloom filter of at least `bits` bits, setting
+    /// `hashes` positions per key.
+    fn with_bits(bits: usize, hashes: u32) -> Self;
+
+    /// Inserts key into bloom filter by its [`hash`](SizedBloom::hash).
+    fn insert_hash(&mut self, hash: [u32; 2]);
+
+    /// Queries if a key exists in this bloom filter by its hash.
+    fn query_hash(&self, hash: [u32; 2]) -> bool;
+
+    /// Creates new empty bloom filter for `keys` keys, with `bits_per_key`
+    /// bits for each of them.
+    fn new(keys: usize, bits_per_key: usize) -> Self {
+        // false positives are the rarest with bits_per_key * ln(2) hashes
+        let hashes = (bits_per_key as f64 * 0.69).round() as u32;
+        Self::with_bits(keys * bits_per_key, hashes.clamp(1, MAX_HASHES))
+    }
+
+    /// Hashes a key, which may then be inserted with
+    /// [`insert_hash`](SizedBloom::insert_hash).
+    fn hash(message: &[u8]) -> [u32; 2] {
+        Self::Hasher::hash(message)
+    }
+
+    /// Inserts key into bloom filter.
+    fn insert(&mut self, message: &[u8]) {
+        self.insert_hash(Self::hash(message));
+    }
+
+    /// Queries if a key exists in this bloom filter.
+    ///
+    /// A `false` result indicates a definite 'not exist', while a `true` might
+    /// inflict a false positive.
+    fn query(&self, message: &[u8]) -> bool {
+        self.query_hash(Self::hash(message))
+    }
+}
+
+/// Bloom filter spreading the positions of a key over all of its bits by
+/// double hashing.
+pub struct SizedBloomFilter<Hasher>
+where
+    Hasher: HashStrategy<32, 2>,
+{
+    data: Vec<u8>,
+
+    /// Number of positions set per key.
+    hashes: u32,
+
+    _marker: PhantomData<Hasher>,
+}
+
+/// The implementation is guaranteed to be endian-safe.
+impl<Hasher> SizedBloomFilter<Hasher>
+where
+    Hasher: HashStrategy<32, 2>,
+{
+    /// Creates bloom filter from memory slice, with the number of hashes it
+    /// was built with.
+    pub fn from_slice(hashes: u32, region: &[u8]) -> Self {
+        Self {
+            data: region.to_vec(),
+            hashes,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of positions set per key.
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// Query actual size in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Accesses the filter bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    /// Bit positions of a key, walking the filter in steps of the upper half
+    /// of its hash.
+    fn positions(&self, hash: [u32; 2]) -> impl Iterator<Item = usize> {
+        let bits = self.data.len() as u64 * 8;
+        let [base, step] = hash.map(|half| half as u64);
+        (0..self.hashes as u64).map(move |i| ((base + i * step) % bits) as usize)
+    }
+}
+
+impl<Hasher> SizedBloom for SizedBloomFilter<Hasher>
+where
+    Hasher: HashStrategy<32, 2>,
+{
+    type Hasher = Hasher;
+
+    fn with_bits(bits: usize, hashes: u32) -> Self {
+        Self {
+            data: vec![0_u8; usize::max(bits, 64).div_ceil(8)],
+            hashes,
+            _marker: PhantomData,
+        }
+    }
+
+    fn insert_hash(&mut self, hash: [u32; 2]) {
+        for position in self.positions(hash) {
+            self.data[position >> 3] |= 1_u8 << (position & 0x07);
+        }
+    }
+
+    fn query_hash(&self, hash: [u32; 2]) -> bool {
+        self.positions(hash)
+            .all(|position| self.data[position >> 3] & (1_u8 << (position & 0x07)) != 0)
+    }
+}
+
+impl<Hasher> Filter for SizedBloomFilter<Hasher>
+where
+    Hasher: HashStrategy<32, 2> + Send + Sync,
+{
+    fn query(&self, message: &[u8]) -> bool {
+        SizedBloom::query(self, message)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{SizedBloom, SizedBloomFilter};
+    use crate::bloom::strategies::SfHash64;
+
+    /// Fills a filter of `scale` keys with 10 bits per key, and checks that
+    /// it has no false negatives and less than `1 / fp_inverse` of false
+    /// positives once reloaded through `reload`.
+    pub fn check_layout<Bloom: SizedBloom>(
+        scale: usize,
+        fp_inverse: usize,
+        reload: impl Fn(&Bloom) -> Bloom,
+    ) -> Bloom {
+        let mut bf = Bloom::new(scale, 10);
+        for i in 0..scale {
+            bf.insert(format!("Positive_{i}_suffix!").as_bytes());
+        }
+        let bf = reload(&bf);
+        for i in 0..scale {
+            assert!(bf.query(format!("Positive_{i}_suffix!").as_bytes()));
+        }
+        let fp_count = (0..scale)
+            .filter(|i| bf.query(format!("Negative_{i}_suffix!!").as_bytes()))
+            .count();
+        assert!(fp_count < scale / fp_inverse, "{fp_count} false positives");
+        bf
+    }
+
+    #[test]
+    fn sized_by_keys() {
+        let scale = 20000;
+        // about 1% false positives
+        let bf = check_layout(scale, 50, |bf: &SizedBloomFilter<SfHash64<32, 2>>| {
+            SizedBloomFilter::from_slice(bf.hashes(), bf.as_slice())
+        });
+        assert_eq!(bf.size(), scale * 10 / 8);
+        assert_eq!(bf.hashes(), 7);
+    }
+}
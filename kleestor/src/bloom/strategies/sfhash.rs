@@ -78,6 +78,8 @@ macro_rules! match_bytes {
 
 /// An sfHash64 implementation without seed intervention.
 ///
+/// The buffer need not be aligned, words are read unaligned.
+///
 /// This algorithm is not portable between machines of different endiannesses.
 #[inline(always)]
 unsafe fn sfhash64(buffer: &[u8], len: u64) -> u64 {
@@ -89,7 +91,7 @@ unsafe fn sfhash64(buffer: &[u8], len: u64) -> u64 {
     // small key hashes (< 256 bits) should be dealt with with priority
     if len < 32 {
         while ptr != end2 {
-            v = *ptr;
+            v = ptr.read_unaligned();
             h3 ^= mix(v);
             wrapped_mul_inpl!(h3, MAGIC_SHIFT_1);
             ptr = ptr.offset(1);
@@ -120,7 +122,12 @@ unsafe fn sfhash64(buffer: &[u8], len: u64) -> u64 {
         let vec_mul = Simd::from([MAGIC_SHIFT_1, MAGIC_SHIFT_2, MAGIC_SHIFT_3, MAGIC_SHIFT_4]);
 
         while ptr != end1 {
-            let mut vv = Simd::from([*ptr, *ptr.offset(1), *ptr.offset(2), *ptr.offset(3)]);
+            let mut vv = Simd::from([
+                ptr.read_unaligned(),
+                ptr.offset(1).read_unaligned(),
+                ptr.offset(2).read_unaligned(),
+                ptr.offset(3).read_unaligned(),
+            ]);
             vv ^= vv.shr(vec_shr_23);
             hv ^= vv ^ vv.shr(vec_shr_47);
             hv *= vec_mul;
@@ -137,7 +144,7 @@ unsafe fn sfhash64(buffer: &[u8], len: u64) -> u64 {
 
     // batch hash 8 bytes at a time, up to 24 bytes
     while ptr != end2 {
-        v = *ptr;
+        v = ptr.read_unaligned();
         h ^= mix(v);
         wrapped_mul_inpl!(h, MAGIC_SHIFT_1);
         ptr = ptr.offset(1);
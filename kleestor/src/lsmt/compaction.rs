@@ -0,0 +1,453 @@
+use crate::lsmt::mgr::SSLoc;
+use crate::record::{ByteStream, KvDataRef, KvPointer};
+use std::marker::PhantomData;
+
+/// A compaction merges a set of tables into new runs on one tier.
+pub struct CompactionTask {
+    /// Tables to merge.
+    pub inputs: Vec<SSLoc>,
+
+    /// Tier that receives the merged runs.
+    pub output_tier: u32,
+
+    /// No data older than the output exists, so tombstones shadow nothing and
+    /// may be dropped.
+    pub drop_tombstones: bool,
+
+    /// The output is split into several runs, each holding about this many
+    /// bytes of keys and values.
+    pub max_output_size: usize,
+}
+
+/// What a compaction strategy gets to know about a table.
+pub struct TableInfo {
+    pub loc: SSLoc,
+
+    /// Bytes taken by records in the table.
+    pub size: usize,
+
+    /// Smallest key in the table.
+    pub min_key: ByteStream,
+
+    /// Largest key in the table.
+    pub max_key: ByteStream,
+}
+
+impl TableInfo {
+    /// Checks if the key ranges of both tables intersect.
+    fn overlaps(&self, min_key: &[u8], max_key: &[u8]) -> bool {
+        self.min_key.as_ref() <= max_key && min_key <= self.max_key.as_ref()
+    }
+
+    /// Gets the smallest and the largest key over all given tables.
+    fn key_range<'a>(tables: &[&'a TableInfo]) -> (&'a [u8], &'a [u8]) {
+        let min_key = tables
+            .iter()
+            .map(|table| table.min_key.as_ref())
+            .min()
+            .unwrap();
+        let max_key = tables
+            .iter()
+            .map(|table| table.max_key.as_ref())
+            .max()
+            .unwrap();
+        (min_key, max_key)
+    }
+}
+
+/// Decides which tables are merged together, trading read amplification for
+/// write amplification.
+pub trait CompactionStrategy: Send {
+    /// Picks the next compaction to run, if any. Tables are given in the order
+    /// of [`SSLoc`], i.e. by tier, and from the newest to the oldest run.
+    fn pick(&self, tables: &[TableInfo]) -> Option<CompactionTask>;
+}
+
+/// Size-tiered compaction. Flushes create runs on tier 0, and once a tier has
+/// collected `threshold` runs they are merged into a single run on the next
+/// tier.
+///
+/// Every run is written exactly once per tier, which keeps write
+/// amplification low at the cost of having several overlapping runs per tier.
+pub struct TieredCompaction {
+    /// Number of runs that triggers a compaction of a tier.
+    threshold: usize,
+}
+
+impl TieredCompaction {
+    pub fn new(threshold: usize) -> Self {
+        assert!(threshold >= 2);
+        Self { threshold }
+    }
+}
+
+impl CompactionStrategy for TieredCompaction {
+    /// Picks the lowest tier that reached the threshold.
+    fn pick(&self, tables: &[TableInfo]) -> Option<CompactionTask> {
+        let mut begin = 0_usize;
+        while begin < tables.len() {
+            let tier = tables[begin].loc.tier;
+            let end = begin + tables[begin..].partition_point(|table| table.loc.tier == tier);
+            if end - begin >= self.threshold {
+                return Some(CompactionTask {
+                    inputs: tables[begin..end].iter().map(|table| table.loc).collect(),
+                    output_tier: tier + 1,
+                    drop_tombstones: end == tables.len(),
+                    max_output_size: usize::MAX,
+                });
+            }
+            begin = end;
+        }
+        None
+    }
+}
+
+/// Leveled compaction. Flushes create overlapping runs on level (tier) 0,
+/// while every other level is a set of runs with disjoint key ranges, so that
+/// a lookup visits at most one run per level.
+///
+/// Level 0 is merged into level 1 once it holds `l0_threshold` runs. Level `n`
+/// may hold `base_size * multiplier ^ (n - 1)` bytes, and past that one of its
+/// runs is merged into the overlapping runs of the next level. This keeps read
+/// amplification low at the cost of rewriting data several times per level.
+pub struct LeveledCompaction {
+    /// Number of runs on level 0 that triggers a compaction into level 1.
+    l0_threshold: usize,
+
+    /// Target size of level 1 in bytes.
+    base_size: usize,
+
+    /// Growth of the target size from one level to the next.
+    multiplier: usize,
+
+    /// Output runs are split once they hold about this many bytes.
+    target_file_size: usize,
+}
+
+impl LeveledCompaction {
+    pub fn new(
+        l0_threshold: usize,
+        base_size: usize,
+        multiplier: usize,
+        target_file_size: usize,
+    ) -> Self {
+        assert!(l0_threshold >= 1);
+        assert!(multiplier >= 2);
+        Self {
+            l0_threshold,
+            base_size,
+            multiplier,
+            target_file_size,
+        }
+    }
+
+    /// Target size of the given level (level 1 or above).
+    fn target_size(&self, level: u32) -> f64 {
+        self.base_size as f64 * (self.multiplier as f64).powi(level as i32 - 1)
+    }
+
+    /// Builds a task merging `inputs` on `level` with their overlapping runs on
+    /// the next level.
+    fn merge_down<'a>(
+        &self,
+        tables: &'a [TableInfo],
+        mut inputs: Vec<&'a TableInfo>,
+        level: u32,
+    ) -> CompactionTask {
+        let (min_key, max_key) = TableInfo::key_range(&inputs);
+        inputs.extend(
+            tables
+                .iter()
+                .filter(|table| table.loc.tier == level + 1 && table.overlaps(min_key, max_key)),
+        );
+
+        // runs pulled in from the next level may reach past the range above,
+        // and older data anywhere in the merged range lives further down
+        let (min_key, max_key) = TableInfo::key_range(&inputs);
+        let drop_tombstones = !tables
+            .iter()
+            .any(|table| table.loc.tier > level + 1 && table.overlaps(min_key, max_key));
+        CompactionTask {
+            inputs: inputs.iter().map(|table| table.loc).collect(),
+            output_tier: level + 1,
+            drop_tombstones,
+            max_output_size: self.target_file_size,
+        }
+    }
+}
+
+impl CompactionStrategy for LeveledCompaction {
+    fn pick(&self, tables: &[TableInfo]) -> Option<CompactionTask> {
+        let level_of = |level: u32| tables.iter().filter(move |table| table.loc.tier == level);
+
+        // level 0 runs overlap each other, so they are merged down at once
+        if level_of(0).count() >= self.l0_threshold {
+            return Some(self.merge_down(tables, level_of(0).collect(), 0));
+        }
+
+        // find the level that exceeds its target size the most
+        let deepest = tables.iter().map(|table| table.loc.tier).max()?;
+        let mut best: Option<(u32, f64)> = None;
+        for level in 1..=deepest {
+            let size: usize = level_of(level).map(|table| table.size).sum();
+            let score = size as f64 / self.target_size(level);
+            if score > 1.0 && best.is_none_or(|(_, best)| score > best) {
+                best = Some((level, score));
+            }
+        }
+        let (level, _) = best?;
+
+        // and the run that would rewrite the least data of the next level
+        // relative to its own size
+        let overlap_score = |table: &TableInfo| {
+            let overlap: usize = level_of(level + 1)
+                .filter(|other| other.overlaps(table.min_key.as_ref(), table.max_key.as_ref()))
+                .map(|other| other.size)
+                .sum();
+            overlap as f64 / table.size.max(1) as f64
+        };
+        let input = level_of(level)
+            .min_by(|a, b| overlap_score(a).total_cmp(&overlap_score(b)))
+            .unwrap();
+        Some(self.merge_down(tables, vec![input], level))
+    }
+}
+
+/// Ends an iterator after it yielded about `limit` bytes of keys and values,
+/// so that a single merge can be written to several tables.
+pub struct SizeLimitIterator<Pointer, Iter>
+where
+    Pointer: KvPointer,
+    Iter: Iterator<Item = Pointer>,
+{
+    iter: Iter,
+
+    /// Bytes left before the iterator ends.
+    remaining: usize,
+}
+
+impl<Pointer, Iter> SizeLimitIterator<Pointer, Iter>
+where
+    Pointer: KvPointer,
+    Iter: Iterator<Item = Pointer>,
+{
+    pub fn new(iter: Iter, limit: usize) -> Self {
+        Self {
+            iter,
+            remaining: limit,
+        }
+    }
+}
+
+impl<Pointer, Iter> Iterator for SizeLimitIterator<Pointer, Iter>
+where
+    Pointer: KvPointer,
+    Iter: Iterator<Item = Pointer>,
+{
+    type Item = Pointer;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let item = self.iter.next()?;
+        let size = item.key().len()
+            + match item.value() {
+                KvDataRef::Tombstone { .. } => 0,
+                KvDataRef::Value { value, .. } => value.len(),
+            };
+        self.remaining = self.remaining.saturating_sub(size.max(1));
+        Some(item)
+    }
+}
+
+/// Feeds merged records of existing tables into a new table.
+///
+/// Records read from tables are flagged as cached, which the writer would
+/// skip, so they are handed over as fresh records. Tombstones are optionally
+/// dropped on the way.
+pub struct CompactionIterator<Pointer, Iter>
+where
+    Pointer: KvPointer,
+    Iter: Iterator<Item = Pointer>,
+{
+    iter: Iter,
+    drop_tombstones: bool,
+
+    _marker: PhantomData<Pointer>,
+}
+
+impl<Pointer, Iter> CompactionIterator<Pointer, Iter>
+where
+    Pointer: KvPointer,
+    Iter: Iterator<Item = Pointer>,
+{
+    pub fn new(iter: Iter, drop_tombstones: bool) -> Self {
+        Self {
+            iter,
+            drop_tombstones,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Pointer, Iter> Iterator for CompactionIterator<Pointer, Iter>
+where
+    Pointer: KvPointer,
+    Iter: Iterator<Item = Pointer>,
+{
+    type Item = CompactionPointer<Pointer>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = self.iter.next()?;
+            if self.drop_tombstones {
+                if let KvDataRef::Tombstone { .. } = item.value() {
+                    continue;
+                }
+            }
+            return Some(CompactionPointer { _item: item });
+        }
+    }
+}
+
+/// Pointer yielded by [`CompactionIterator`].
+pub struct CompactionPointer<Pointer: KvPointer> {
+    _item: Pointer,
+}
+
+impl<Pointer: KvPointer> KvPointer for CompactionPointer<Pointer> {
+    fn key(&self) -> &[u8] {
+        self._item.key()
+    }
+
+    fn value(&self) -> KvDataRef {
+        match self._item.value() {
+            KvDataRef::Tombstone { .. } => KvDataRef::Tombstone { cached: false },
+            KvDataRef::Value { value, .. } => KvDataRef::Value {
+                cached: false,
+                value,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CompactionIterator, CompactionStrategy, LeveledCompaction, TableInfo, TieredCompaction,
+    };
+    use crate::lsmt::mgr::SSLoc;
+    use crate::memtable::rbtree::RBTree;
+    use crate::memtable::MemTable;
+    use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvMergeIterator, KvPointer};
+
+    fn table(tier: u32, run: u32, size: usize, min_key: &str, max_key: &str) -> TableInfo {
+        TableInfo {
+            loc: SSLoc { tier, run },
+            size,
+            min_key: ByteStream::from_slice(min_key.as_bytes()),
+            max_key: ByteStream::from_slice(max_key.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn tiered_picks_full_tier() {
+        let loc = |tier, run| SSLoc { tier, run };
+        let t = |tier, run| table(tier, run, 0, "", "");
+        let strategy = TieredCompaction::new(3);
+
+        let tables = vec![t(0, 9), t(0, 8), t(1, 5), t(1, 4), t(2, 1)];
+        assert!(strategy.pick(&tables).is_none());
+
+        let tables = vec![t(0, 9), t(0, 8), t(1, 7), t(1, 5), t(1, 4)];
+        let task = strategy.pick(&tables).unwrap();
+        assert!(task.inputs == vec![loc(1, 7), loc(1, 5), loc(1, 4)]);
+        assert_eq!(task.output_tier, 2);
+        assert!(task.drop_tombstones);
+
+        let tables = vec![t(0, 9), t(0, 8), t(0, 7), t(1, 5)];
+        let task = strategy.pick(&tables).unwrap();
+        assert_eq!(task.inputs.len(), 3);
+        assert!(!task.drop_tombstones);
+    }
+
+    #[test]
+    fn leveled_picks_least_overlap() {
+        let loc = |tier, run| SSLoc { tier, run };
+        let strategy = LeveledCompaction::new(2, 100, 10, 50);
+
+        // level 0 merges into every overlapping run of level 1
+        let tables = vec![
+            table(0, 9, 10, "c", "e"),
+            table(0, 8, 10, "d", "f"),
+            table(1, 5, 40, "a", "b"),
+            table(1, 4, 40, "e", "g"),
+            table(2, 1, 40, "x", "z"),
+        ];
+        let task = strategy.pick(&tables).unwrap();
+        assert!(task.inputs == vec![loc(0, 9), loc(0, 8), loc(1, 4)]);
+        assert_eq!(task.output_tier, 1);
+        assert!(task.drop_tombstones);
+
+        // level 1 is within its target size
+        assert!(strategy.pick(&tables[1..]).is_none());
+
+        // level 1 is too large, and "k".."m" overlaps the fewest bytes below
+        let tables = vec![
+            table(1, 7, 60, "a", "c"),
+            table(1, 6, 60, "k", "m"),
+            table(2, 3, 500, "a", "b"),
+            table(2, 2, 100, "l", "l"),
+            table(3, 1, 100, "m", "z"),
+        ];
+        let task = strategy.pick(&tables).unwrap();
+        assert!(task.inputs == vec![loc(1, 6), loc(2, 2)]);
+        assert_eq!(task.output_tier, 2);
+        assert_eq!(task.max_output_size, 50);
+        assert!(!task.drop_tombstones);
+    }
+
+    /// Tombstones of runs pulled in from the next level are kept while they
+    /// still shadow older data below that level.
+    #[test]
+    fn leveled_keeps_pulled_in_tombstones() {
+        let loc = |tier, run| SSLoc { tier, run };
+        let strategy = LeveledCompaction::new(2, 100, 10, 50);
+
+        // "l".."p" is pulled in by "k".."m", and reaches "o" on level 3
+        let tables = vec![
+            table(1, 7, 60, "a", "c"),
+            table(1, 6, 60, "k", "m"),
+            table(2, 3, 500, "a", "b"),
+            table(2, 2, 100, "l", "p"),
+            table(3, 1, 100, "o", "z"),
+        ];
+        let task = strategy.pick(&tables).unwrap();
+        assert!(task.inputs == vec![loc(1, 6), loc(2, 2)]);
+        assert!(!task.drop_tombstones);
+
+        // "o" stays deleted once merged
+        let mut runs = [RBTree::<ByteStream, KvEntry>::new(), RBTree::new()];
+        runs[0].insert(
+            ByteStream::from_slice(b"k"),
+            KvEntry::new(KvData::Value {
+                cached: true,
+                value: ByteStream::from_slice(b"value"),
+            }),
+        );
+        runs[1].insert(
+            ByteStream::from_slice(b"o"),
+            KvEntry::new(KvData::Tombstone { cached: true }),
+        );
+        let iters = runs.iter_mut().map(|run| run.iter()).collect();
+        let merged = CompactionIterator::new(KvMergeIterator::new(iters), task.drop_tombstones);
+        let records: Vec<(Vec<u8>, bool)> = merged
+            .map(|item| {
+                let deleted = matches!(item.value(), KvDataRef::Tombstone { .. });
+                (item.key().to_vec(), deleted)
+            })
+            .collect();
+        assert!(records == vec![(b"k".to_vec(), false), (b"o".to_vec(), true)]);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::lsmt::mgr::SSLoc;
+use crate::lsmt::wal::{LogReader, LogWriter};
+use crate::utils::varint::VarUint64;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+/// A manifest is rewritten from a snapshot once it grows past this many bytes.
+const MANIFEST_ROLL_SIZE: usize = 4 << 20;
+
+/// Tables making up the database at some point in time.
+pub struct Version {
+    /// Live tables in the order of [`SSLoc`].
+    pub tables: BTreeSet<SSLoc>,
+
+    /// Run number assigned to the next table written to disk.
+    pub next_run: u32,
+}
+
+/// Change of the live tables made by a single flush or compaction.
+pub struct VersionEdit {
+    /// Tables that became live.
+    pub added: Vec<SSLoc>,
+
+    /// Tables that are no longer live and may be deleted.
+    pub removed: Vec<SSLoc>,
+
+    /// Run number assigned to the next table written to disk.
+    pub next_run: u32,
+}
+
+/// Log of [`VersionEdit`]s, which is the authority on the tables that make up
+/// the database. Files are laid out as:
+///
+///     <dir>/CURRENT               -- name of the manifest in use
+///     <dir>/MANIFEST-{seq}        -- a snapshot followed by edits
+///
+/// A table is logged as added only after it was fully written, and is deleted
+/// only after it was logged as removed. Tables not known to the manifest are
+/// left over from an interrupted flush or compaction.
+///
+/// The manifest in use is switched by renaming a new `CURRENT` over the old
+/// one, so a crash leaves either the old or the new manifest in effect.
+pub struct Manifest {
+    /// Directory holding the manifest.
+    dir: PathBuf,
+
+    /// Manifest currently being appended to.
+    writer: LogWriter,
+
+    /// Sequence number of the manifest in use.
+    seq: u64,
+
+    /// Tables live as of the last logged edit.
+    version: Version,
+}
+
+impl Manifest {
+    /// Replays the manifest pointed to by `CURRENT`. Returns `None` if the
+    /// directory has no manifest yet.
+    pub fn recover(dir: &Path) -> Result<Option<Version>> {
+        let current = match fs::read_to_string(dir.join("CURRENT")) {
+            Ok(current) => current,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let name = current.trim_end();
+        if Self::parse_file_name(name).is_none() {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid CURRENT file"));
+        }
+
+        let mut version = Version {
+            tables: BTreeSet::new(),
+            next_run: 0,
+        };
+        let mut reader = LogReader::open(&dir.join(name))?;
+        while let Some(payload) = reader.next()? {
+            Self::apply(&mut version, &Self::decode(payload)?);
+        }
+        Ok(Some(version))
+    }
+
+    /// Starts a new manifest with `version` as its snapshot and switches
+    /// `CURRENT` over to it. Older manifests are removed.
+    pub fn create(dir: &Path, version: Version) -> Result<Self> {
+        let seq = match Self::list_manifests(dir)?.last() {
+            Some(last) => last + 1,
+            None => 0,
+        };
+        let writer = Self::write_snapshot(dir, seq, &version)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            writer,
+            seq,
+            version,
+        })
+    }
+
+    /// Durably logs `edit`. Tables removed by the edit may be deleted once
+    /// this returns.
+    pub fn log(&mut self, edit: &VersionEdit) -> Result<()> {
+        self.writer.append(&Self::encode(edit))?;
+        Self::apply(&mut self.version, edit);
+
+        if self.writer.size() >= MANIFEST_ROLL_SIZE {
+            self.writer = Self::write_snapshot(&self.dir, self.seq + 1, &self.version)?;
+            self.seq += 1;
+        }
+        Ok(())
+    }
+
+    /// Writes a manifest holding nothing but `version`, points `CURRENT` to
+    /// it and removes all other manifests.
+    fn write_snapshot(dir: &Path, seq: u64, version: &Version) -> Result<LogWriter> {
+        let name = Self::file_name(seq);
+        let mut writer = LogWriter::create(&dir.join(&name), true)?;
+        writer.append(&Self::encode(&VersionEdit {
+            added: version.tables.iter().copied().collect(),
+            removed: Vec::new(),
+            next_run: version.next_run,
+        }))?;
+
+        let tmp_path = dir.join("CURRENT.tmp");
+        let mut current = File::create(&tmp_path)?;
+        current.write_all(format!("{name}\n").as_bytes())?;
+        current.sync_all()?;
+        fs::rename(&tmp_path, dir.join("CURRENT"))?;
+        File::open(dir)?.sync_all()?;
+
+        for old in Self::list_manifests(dir)? {
+            if old != seq {
+                fs::remove_file(dir.join(Self::file_name(old)))?;
+            }
+        }
+        Ok(writer)
+    }
+
+    fn apply(version: &mut Version, edit: &VersionEdit) {
+        for loc in &edit.removed {
+            version.tables.remove(loc);
+        }
+        version.tables.extend(edit.added.iter().copied());
+        version.next_run = version.next_run.max(edit.next_run);
+    }
+
+    fn file_name(seq: u64) -> String {
+        format!("MANIFEST-{seq:06}")
+    }
+
+    fn parse_file_name(name: &str) -> Option<u64> {
+        name.strip_prefix("MANIFEST-")?.parse::<u64>().ok()
+    }
+
+    /// Lists sequence numbers of all manifests in ascending order.
+    fn list_manifests(dir: &Path) -> Result<Vec<u64>> {
+        let mut manifests = Vec::<u64>::new();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            if let Some(seq) = name.to_str().and_then(Self::parse_file_name) {
+                manifests.push(seq);
+            }
+        }
+        manifests.sort();
+        Ok(manifests)
+    }
+
+    /// Serializes an edit as:
+    ///
+    ///     [next run] [added count] ([tier] [run])... [removed count] ([tier] [run])...
+    ///
+    /// where every number is a varuint64.
+    fn encode(edit: &VersionEdit) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        let mut scratch = [0_u8; 9];
+        let mut push_varu64 = |buffer: &mut Vec<u8>, value: u64| {
+            let len = VarUint64::as_slice(value, &mut scratch);
+            buffer.extend_from_slice(&scratch[0..len]);
+        };
+
+        push_varu64(&mut buffer, edit.next_run as u64);
+        for tables in [&edit.added, &edit.removed] {
+            push_varu64(&mut buffer, tables.len() as u64);
+            for loc in tables {
+                push_varu64(&mut buffer, loc.tier as u64);
+                push_varu64(&mut buffer, loc.run as u64);
+            }
+        }
+        buffer
+    }
+
+    /// Inverse of [`Manifest::encode`].
+    fn decode(payload: &[u8]) -> Result<VersionEdit> {
+        let mut offset = 0_usize;
+        let mut read_u32 = || {
+            let remaining = payload.len() - offset;
+            let value = VarUint64::read_and_seek(&payload[offset..], &mut offset, remaining)?;
+            u32::try_from(value)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "manifest value out of range"))
+        };
+
+        let next_run = read_u32()?;
+        let mut tables = [Vec::<SSLoc>::new(), Vec::<SSLoc>::new()];
+        for list in &mut tables {
+            let count = read_u32()?;
+            for _ in 0..count {
+                let tier = read_u32()?;
+                let run = read_u32()?;
+                list.push(SSLoc { tier, run });
+            }
+        }
+        let [added, removed] = tables;
+        Ok(VersionEdit {
+            added,
+            removed,
+            next_run,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Manifest, Version, VersionEdit};
+    use crate::lsmt::mgr::SSLoc;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn replays_edits() {
+        let mut dir = std::env::temp_dir();
+        dir.push("_kleestor_lsmt_manifest_replays_edits");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let loc = |tier, run| SSLoc { tier, run };
+
+        assert!(Manifest::recover(&dir).unwrap().is_none());
+        let version = Version {
+            tables: BTreeSet::from([loc(0, 1), loc(0, 0)]),
+            next_run: 2,
+        };
+        let mut manifest = Manifest::create(&dir, version).unwrap();
+        manifest
+            .log(&VersionEdit {
+                added: vec![loc(1, 2)],
+                removed: vec![loc(0, 1), loc(0, 0)],
+                next_run: 3,
+            })
+            .unwrap();
+        manifest
+            .log(&VersionEdit {
+                added: vec![loc(0, 3)],
+                removed: vec![],
+                next_run: 4,
+            })
+            .unwrap();
+        drop(manifest);
+
+        let version = Manifest::recover(&dir).unwrap().unwrap();
+        assert!(version.tables.iter().copied().collect::<Vec<_>>() == vec![loc(0, 3), loc(1, 2)]);
+        assert_eq!(version.next_run, 4);
+
+        // switching over leaves a single manifest behind
+        Manifest::create(&dir, version).unwrap();
+        let names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"MANIFEST-000001".to_string()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,26 +1,86 @@
+use crate::lsmt::compaction::{CompactionStrategy, LeveledCompaction, TableInfo, TieredCompaction};
+use crate::lsmt::manifest::{Manifest, Version, VersionEdit};
+use crate::lsmt::options::{CompactionStyle, Options};
+use crate::lsmt::scan::{ScanCursor, ScanIterator, ScanSource, ScanTable, Snapshot};
 use crate::lsmt::transimpl::{Transaction, TransactionMgrImpl};
-use crate::memtable::rbtree::RBTree;
+use crate::lsmt::wal::Wal;
+use crate::lsmt::worker::{Job, Outcome, Worker};
+use crate::memtable::skiplist::SkipList;
 use crate::memtable::MemTable;
 use crate::record::{ByteStream, KvData, KvEntry};
+use crate::sstable::cache::BlockCache;
 use crate::sstable::reader::SSTableReader;
-use crate::utils;
-use crate::utils::futures::RwLock;
+use crate::utils::futures::{oneshot, Mutex, RwLock};
+use std::cell::UnsafeCell;
 use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Result as IoResult};
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicBool, AtomicU32, AtomicU64};
+use std::sync::Arc;
 
+/// A log-structured merge tree persisted under a single directory.
+///
+/// The directory is laid out as follows:
+///
+///     <path>/
+///         CURRENT                 -- name of the manifest in use
+///         MANIFEST-{seq}          -- log of tables added and removed
+///         {tier}-{run}.sst        -- one sorted string table per [`SSLoc`]
+///         {tier}-{run}.sst.tmp    -- table being written, removed on open
+///         wal/{seq}.log           -- write-ahead log segments of lv0 and lv1
+///
+/// Only tables recorded in the manifest are part of the database, see
+/// [`Manifest`].
+///
+/// Reads and writes outside transactions only share the database, so that any
+/// number of them may run at once. Transactions take it exclusively.
 pub struct LsmTree {
+    /// Database directory.
+    path: PathBuf,
+
+    /// Options the database was opened with.
+    options: Options,
+
     /// Transaction manager.
     trans: TransactionMgrImpl,
 
-    /// Level 0 of the LSM tree, a read-write mapping.
-    lv0: RBTree<ByteStream, KvEntry>,
+    /// Level 0 of the LSM tree, a read-write mapping. Scans and cursors keep
+    /// their own reference to it, see [`Snapshot`].
+    lv0: UnsafeCell<Arc<SkipList<ByteStream, KvEntry>>>,
+
+    /// Every write is appended here before reaching lv0, and is replayed into
+    /// lv0 when the database is opened again. The lock is only held while
+    /// appending, and is taken after `freeze_lock` but before any of the level
+    /// locks.
+    wal: Mutex<Wal>,
+
+    /// Writes outside transactions waiting to be logged, see [`LsmTree::log`].
+    log_queue: Mutex<Vec<QueuedWrite>>,
 
-    /// A read-write lock denying conflict access to lv0 structure.
+    /// Sequence number given to the next write logged outside transactions.
+    next_seq: AtomicU64,
+
+    /// Held shared by writers from logging a write until it reached lv0, and
+    /// exclusively while lv0 is frozen, so that no write is logged into the
+    /// segments of one tree but inserted into another.
+    freeze_lock: RwLock<()>,
+
+    /// A read-write lock denying conflict access to lv0 structure. New keys
+    /// are inserted into lv0 under a shared hold, while records of existing
+    /// keys are only replaced under an exclusive one. Level locks are never
+    /// held beyond a single call.
+    ///
+    /// Whenever more than one of the level locks are held, they are acquired
+    /// in the order of lv0, lv1 and lvrest.
     lv0_lock: RwLock<()>,
 
-    /// Level 1 contains a series of red-black trees pending flush to level 2.
+    /// Level 1 contains a series of skip lists pending flush to level 2.
     /// New trees must be pushed to the front (i.e. lower index means newer
     /// data).
-    lv1: Vec<RBTree<ByteStream, KvEntry>>,
+    lv1: UnsafeCell<Vec<FrozenTree>>,
 
     /// Removal (or insertion) of level 1 structures should be exclusive. The
     /// granularity may be arbitrarily large, as long as it does not block
@@ -28,13 +88,493 @@ pub struct LsmTree {
     lv1_lock: RwLock<()>,
 
     /// More levels incoming.
-    lvrest: Vec<(SSLoc, SSTableReader)>,
+    lvrest: UnsafeCell<Vec<(SSLoc, Arc<SSTableReader>)>>,
 
     /// Also need to lock lvrest when merging.
     lvrest_lock: RwLock<()>,
+
+    /// Jobs handed to the worker. Held while deciding on jobs and installing
+    /// their outcomes, and taken before any of the level locks.
+    jobs: Mutex<Jobs>,
+
+    /// Set once lv0 is frozen, the worker finished a job or a transaction
+    /// finished, i.e. when there may be trees to flush or outcomes to install.
+    /// Writers leave `jobs` alone unless this is set or lv0 is full.
+    jobs_due: Arc<AtomicBool>,
+
+    /// Run number assigned to the next table written to disk. Runs are unique
+    /// across all tiers so that file names never get reused.
+    next_run: Arc<AtomicU32>,
+
+    /// Decompressed data blocks of all tables, shared with every other database
+    /// opened with the same cache.
+    block_cache: Arc<BlockCache>,
+}
+
+// Levels are only reached under their locks, and everything else that is
+// written while the database is shared sits behind a lock or is atomic.
+unsafe impl Send for LsmTree {}
+unsafe impl Sync for LsmTree {}
+
+/// A write waiting to be logged, along with where to report its sequence
+/// number.
+type QueuedWrite = (ByteStream, KvData, oneshot::Sender<IoResult<u64>>);
+
+/// Bookkeeping of the jobs run by the worker.
+struct Jobs {
+    /// Flushes frozen trees and compacts tables off the write path.
+    worker: Worker,
+
+    /// Records every change made to lvrest. Edits are logged while holding
+    /// an exclusive lock of `lvrest_lock`.
+    manifest: Manifest,
+
+    /// Decides which tables in lvrest are merged together.
+    compaction: Box<dyn CompactionStrategy>,
+
+    /// Whether the oldest tree in lv1 is being flushed by the worker.
+    flushing: bool,
+
+    /// Whether lvrest changed since the compaction strategy was last asked.
+    compaction_due: bool,
+
+    /// Whether a compaction is running in the worker. Only one runs at a
+    /// time, so that no table is picked twice.
+    compacting: bool,
+
+    /// Error of the last job installed, unless it succeeded. Failed jobs are
+    /// picked again, so this is cleared once one of them succeeds.
+    failure: Option<Error>,
+}
+
+impl Jobs {
+    /// Fails if the last job installed did.
+    fn status(&self) -> IoResult<()> {
+        match &self.failure {
+            Some(err) => Err(Error::new(err.kind(), err.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A tree frozen into lv1.
+struct FrozenTree {
+    tree: Arc<SkipList<ByteStream, KvEntry>>,
+
+    /// Timestamps of ongoing transactions which may have written into the
+    /// tree. The tree is not flushed until all of them are finished, since
+    /// aborting reverts their writes in place.
+    pins: Vec<u64>,
 }
 
 impl LsmTree {
+    /// Opens the database at `path`, creating it if allowed by `options`.
+    ///
+    /// Live SSTables are read from the manifest and sorted into `lvrest` from
+    /// the newest to the oldest, while tables unknown to the manifest are
+    /// removed. Writes that were not persisted to tables are recovered from
+    /// the write-ahead log.
+    ///
+    /// Directories written before the manifest existed have every table found
+    /// in them taken as live.
+    pub fn open<P: AsRef<Path>>(path: P, options: Options) -> IoResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            if !options.create_if_missing {
+                return Err(Error::new(ErrorKind::NotFound, "database does not exist"));
+            }
+            fs::create_dir_all(&path)?;
+        } else if !path.is_dir() {
+            return Err(Error::new(ErrorKind::InvalidInput, "not a directory"));
+        }
+
+        // collect table files and leftovers from interrupted writes
+        let mut tables = Vec::<(SSLoc, PathBuf)>::new();
+        let mut leftovers = Vec::<PathBuf>::new();
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            if name.ends_with(".tmp") {
+                leftovers.push(entry.path());
+            } else if let Some(loc) = SSLoc::from_file_name(name) {
+                tables.push((loc, entry.path()));
+            }
+        }
+        let recovered = Manifest::recover(&path)?;
+        if options.error_if_exists && (recovered.is_some() || !tables.is_empty()) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "database already exists",
+            ));
+        }
+        for leftover in leftovers {
+            fs::remove_file(leftover)?;
+        }
+
+        // settle on the live tables and drop everything else
+        let version = match recovered {
+            Some(version) => version,
+            None => Version {
+                tables: tables.iter().map(|(loc, _)| *loc).collect(),
+                next_run: tables.iter().map(|(loc, _)| loc.run + 1).max().unwrap_or(0),
+            },
+        };
+        for (loc, file_path) in &tables {
+            if !version.tables.contains(loc) {
+                fs::remove_file(file_path)?;
+            }
+        }
+
+        // load tables, newest first
+        let block_cache = options.block_cache.clone();
+        let mut lvrest = Vec::<(SSLoc, Arc<SSTableReader>)>::new();
+        for loc in &version.tables {
+            let file = match File::open(path.join(loc.file_name())) {
+                Ok(file) => file,
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    return Err(Error::new(ErrorKind::InvalidData, "missing table"))
+                }
+                Err(err) => return Err(err),
+            };
+            let table = SSTableReader::with_cache(file, block_cache.clone())?;
+            lvrest.push((*loc, Arc::new(table)));
+        }
+        let next_run = Arc::new(AtomicU32::new(version.next_run));
+        let manifest = Manifest::create(&path, version)?;
+
+        // replay write-ahead log
+        let mut lv0 = SkipList::<ByteStream, KvEntry>::new();
+        let wal = Wal::open(
+            &path.join("wal"),
+            options.wal_segment_size,
+            options.wal_sync,
+            |ts, key, record| {
+                // the same rule as in transactions, where writes from an older
+                // transaction never override those from a newer one
+                if let Some(entry) = lv0.get(&key) {
+                    if ts != 0 && entry.ts_write > ts {
+                        return;
+                    }
+                }
+                let entry = lv0.insert_internal(key.as_ref(), &record);
+                if ts != 0 {
+                    entry.ts_write = ts;
+                }
+            },
+        )?;
+
+        let compaction: Box<dyn CompactionStrategy> = match options.compaction_style {
+            CompactionStyle::Tiered => Box::new(TieredCompaction::new(options.tier_threshold)),
+            CompactionStyle::Leveled => Box::new(LeveledCompaction::new(
+                options.tier_threshold,
+                options.level_base_size,
+                options.level_size_multiplier,
+                options.target_file_size,
+            )),
+        };
+        // tables just loaded may call for a compaction
+        let jobs_due = Arc::new(AtomicBool::new(true));
+        let worker = Worker::spawn(
+            path.clone(),
+            options.writer_options(),
+            block_cache.clone(),
+            next_run.clone(),
+            jobs_due.clone(),
+        );
+        Ok(Self {
+            path,
+            options,
+            trans: TransactionMgrImpl::new(),
+            lv0: UnsafeCell::new(Arc::new(lv0)),
+            wal: Mutex::new(wal),
+            log_queue: Mutex::new(Vec::new()),
+            next_seq: AtomicU64::new(1),
+            freeze_lock: RwLock::new(()),
+            lv0_lock: RwLock::new(()),
+            lv1: UnsafeCell::new(Vec::new()),
+            lv1_lock: RwLock::new(()),
+            lvrest: UnsafeCell::new(lvrest),
+            lvrest_lock: RwLock::new(()),
+            jobs: Mutex::new(Jobs {
+                worker,
+                manifest,
+                compaction,
+                flushing: false,
+                compaction_due: true,
+                compacting: false,
+                failure: None,
+            }),
+            jobs_due,
+            next_run,
+            block_cache,
+        })
+    }
+
+    /// Gets the cache of data blocks of all tables, e.g. to read its hit and
+    /// miss counters.
+    pub fn block_cache(&self) -> &BlockCache {
+        &self.block_cache
+    }
+
+    /// Persists every in-memory level to disk, lets pending compactions finish
+    /// and releases the database. Trees pinned by ongoing transactions are
+    /// left to the write-ahead log.
+    ///
+    /// Fails if a flush or compaction fails, or if the last one run in the
+    /// background did, in which case writes not persisted to tables are left
+    /// to the write-ahead log as well.
+    pub async fn close(self) -> IoResult<()> {
+        self.freeze().await?;
+        let mut jobs = self.jobs.lock().await;
+        self.collect(&mut jobs).await;
+        loop {
+            self.schedule(&mut jobs).await;
+            if !jobs.flushing && !jobs.compacting {
+                return jobs.status();
+            }
+            self.wait_finished(&mut jobs).await?;
+            jobs.status()?;
+        }
+    }
+
+    /// Fails if the last flush or compaction run in the background did. Such
+    /// jobs are retried, and writes are only turned down while frozen trees
+    /// pile up behind a failing flush, see [`LsmTree::raw_insert`].
+    pub async fn status(&self) -> IoResult<()> {
+        let mut jobs = self.jobs.lock().await;
+        self.collect(&mut jobs).await;
+        jobs.status()
+    }
+
+    /// Moves lv0 to the front of lv1 and starts over with an empty lv0. An
+    /// empty lv0 is kept.
+    ///
+    /// Ongoing transactions may still revert their writes into the frozen
+    /// tree, so it is pinned by them and not flushed until they are finished.
+    pub async fn freeze(&self) -> IoResult<()> {
+        self.freeze_from(0).await
+    }
+
+    /// Freezes lv0 if it holds at least `size` bytes. Writers racing to
+    /// freeze a full lv0 check its size again, so that only one of them
+    /// freezes it.
+    async fn freeze_from(&self, size: usize) -> IoResult<()> {
+        let _freeze = self.freeze_lock.write().await;
+        let mut wal = self.wal.lock().await;
+        let _lock0 = self.lv0_lock.write().await;
+        let lv0 = unsafe { &mut *self.lv0.get() };
+        if lv0.iter_ref().next().is_none() || lv0.approximate_memory_usage() < size {
+            return Ok(());
+        }
+        let _lock1 = self.lv1_lock.write().await;
+        wal.seal()?;
+        let tree = mem::replace(lv0, Arc::new(SkipList::new()));
+        let lv1 = unsafe { &mut *self.lv1.get() };
+        lv1.insert(
+            0,
+            FrozenTree {
+                tree,
+                pins: self.trans.ongoing().collect(),
+            },
+        );
+        self.jobs_due.store(true, atomic::Ordering::Release);
+        Ok(())
+    }
+
+    /// Freezes lv0 once it outgrows the configured memtable size, and hands
+    /// frozen trees and compactions to the worker. Writes only wait for the
+    /// worker while too many frozen trees are pending, so that memory use
+    /// stays bounded under sustained writes.
+    ///
+    /// This is run before a write is applied, and only fails if there is no
+    /// room for it, i.e. if lv0 cannot be frozen or a flush fails while too
+    /// many frozen trees are pending. Other failures of the worker are only
+    /// recorded, see [`LsmTree::status`].
+    ///
+    /// Writes leave the jobs alone unless lv0 is full or `jobs_due` is set, so
+    /// that writers do not line up behind each other.
+    async fn make_room(&self) -> IoResult<()> {
+        let full = '_lv0: {
+            let _lock = self.lv0_lock.read().await;
+            let lv0 = unsafe { &*self.lv0.get() };
+            lv0.approximate_memory_usage() >= self.options.memtable_size
+        };
+        // frozen trees only pile up once lv0 is frozen, so there is nothing
+        // to wait for either
+        if !full && !self.jobs_due.load(atomic::Ordering::Acquire) {
+            return Ok(());
+        }
+        if full {
+            self.freeze_from(self.options.memtable_size).await?;
+        }
+        let mut jobs = self.jobs.lock().await;
+        // cleared before collecting, so that no outcome is left behind
+        self.jobs_due.swap(false, atomic::Ordering::AcqRel);
+        self.collect(&mut jobs).await;
+        self.schedule(&mut jobs).await;
+        // trees pinned by transactions are not waited for, as the writer may
+        // be the very transaction pinning them
+        while jobs.flushing {
+            let frozen = '_lv1: {
+                let _lock = self.lv1_lock.read().await;
+                unsafe { (*self.lv1.get()).len() }
+            };
+            if frozen <= self.options.max_frozen_memtables {
+                break;
+            }
+            self.wait_finished(&mut jobs).await?;
+            if !jobs.flushing {
+                // the flush finished, and failed unless cleared
+                jobs.status()?;
+            }
+            self.schedule(&mut jobs).await;
+        }
+        Ok(())
+    }
+
+    /// Hands the oldest tree in lv1 to the worker unless it is pinned by an
+    /// ongoing transaction, and a compaction if the strategy picks one.
+    ///
+    /// Only one flush runs at a time, so that tables are installed in the
+    /// order their trees were frozen, even if a flush fails and is retried.
+    async fn schedule(&self, jobs: &mut Jobs) {
+        '_flush: {
+            if jobs.flushing {
+                break '_flush;
+            }
+            let _lock = self.lv1_lock.read().await;
+            let lv1 = unsafe { &*self.lv1.get() };
+            let Some(frozen) = lv1.last() else {
+                break '_flush;
+            };
+            if frozen.pins.iter().any(|ts| self.trans.is_ongoing(*ts)) {
+                break '_flush;
+            }
+            let loc = SSLoc {
+                tier: 0,
+                run: self.next_run.fetch_add(1, atomic::Ordering::AcqRel),
+            };
+            let tree = frozen.tree.clone();
+            jobs.worker.submit(Job::Flush { loc, tree });
+            jobs.flushing = true;
+        }
+
+        if jobs.compacting || !jobs.compaction_due {
+            return;
+        }
+        let _lock = self.lvrest_lock.read().await;
+        let lvrest = unsafe { &*self.lvrest.get() };
+        jobs.compaction_due = false;
+        let tables: Vec<TableInfo> = lvrest
+            .iter()
+            .filter_map(|(loc, table)| {
+                // tables without any keys overlap nothing, and are left alone
+                let (min_key, max_key) = table.key_range()?;
+                Some(TableInfo {
+                    loc: *loc,
+                    size: table.data_size(),
+                    min_key,
+                    max_key,
+                })
+            })
+            .collect();
+        if let Some(task) = jobs.compaction.pick(&tables) {
+            // lvrest is ordered from the newest to the oldest, which is just
+            // the priority the merger expects
+            let inputs = lvrest
+                .iter()
+                .filter(|(loc, _)| task.inputs.contains(loc))
+                .map(|(_, table)| table.clone())
+                .collect();
+            jobs.worker.submit(Job::Compact { task, inputs });
+            jobs.compacting = true;
+        }
+    }
+
+    /// Installs the outcomes of all jobs the worker has finished so far,
+    /// recording whether they failed.
+    async fn collect(&self, jobs: &mut Jobs) {
+        while let Some(outcome) = jobs.worker.try_finished() {
+            jobs.failure = self.install(jobs, outcome).await.err();
+        }
+    }
+
+    /// Waits for the worker to finish a job and installs its outcome,
+    /// recording whether it failed. Only fails if the worker stopped.
+    async fn wait_finished(&self, jobs: &mut Jobs) -> IoResult<()> {
+        match jobs.worker.finished().await {
+            Some(outcome) => {
+                jobs.failure = self.install(jobs, outcome).await.err();
+                Ok(())
+            }
+            None => Err(Error::other("background worker stopped")),
+        }
+    }
+
+    /// Makes tables written by the worker part of the database.
+    ///
+    /// A flushed table replaces the oldest tree in lv1, which remains readable
+    /// until then so that readers never miss its content. Merged runs replace
+    /// their inputs at once, so readers either see all inputs or the merged
+    /// runs. Failed jobs leave the database as it was, and are picked again
+    /// by the next schedule.
+    async fn install(&self, jobs: &mut Jobs, outcome: Outcome) -> IoResult<()> {
+        match outcome {
+            Outcome::Flushed { loc, table } => {
+                jobs.flushing = false;
+                '_install: {
+                    let Some(table) = table? else {
+                        break '_install;
+                    };
+                    let _lock = self.lvrest_lock.write().await;
+                    let lvrest = unsafe { &mut *self.lvrest.get() };
+                    jobs.manifest.log(&VersionEdit {
+                        added: vec![loc],
+                        removed: Vec::new(),
+                        next_run: self.next_run.load(atomic::Ordering::Acquire),
+                    })?;
+                    let index = lvrest.partition_point(|(other, _)| *other < loc);
+                    lvrest.insert(index, (loc, table));
+                    jobs.compaction_due = true;
+                }
+                '_retire: {
+                    let _lock = self.lv1_lock.write().await;
+                    unsafe { (*self.lv1.get()).pop() };
+                }
+                self.wal.lock().await.release()
+            }
+            Outcome::Compacted { task, outputs } => {
+                jobs.compacting = false;
+                jobs.compaction_due = true;
+                let outputs = outputs?;
+                '_swap: {
+                    let _lock = self.lvrest_lock.write().await;
+                    let lvrest = unsafe { &mut *self.lvrest.get() };
+                    jobs.manifest.log(&VersionEdit {
+                        added: outputs.iter().map(|(loc, _)| *loc).collect(),
+                        removed: task.inputs.clone(),
+                        next_run: self.next_run.load(atomic::Ordering::Acquire),
+                    })?;
+                    lvrest.retain(|(loc, _)| !task.inputs.contains(loc));
+                    for (loc, table) in outputs {
+                        let index = lvrest.partition_point(|(other, _)| *other < loc);
+                        lvrest.insert(index, (loc, Arc::new(table)));
+                    }
+                }
+                // outputs were synced into the directory as they were written,
+                // and inputs left behind are removed by the next open
+                for input in &task.inputs {
+                    let _ = fs::remove_file(self.path.join(input.file_name()));
+                }
+                Ok(())
+            }
+        }
+    }
+
     /// Create transaction.
     pub async fn tr_create(&mut self, ts: u64) -> TransactionToken {
         unsafe {
@@ -51,43 +591,48 @@ impl LsmTree {
     ) -> Result<(), ()> {
         unsafe {
             // access entry in tree
-            let entry = {
-                let _lock = self.lv0_lock.read().await;
-                match self.lv0.get(key) {
-                    None => return Ok(()), // doesn't even exist in l0, safely read
-                    Some(ent) => ent,
-                }
-            };
             let trans = &mut *token._trans;
+            let entry = match self.tr_entry(trans.ts, key).await {
+                None => return Ok(()), // doesn't even exist in memory, safely read
+                Some((_, ent)) => &mut *ent,
+            };
             self.trans.read_lock(trans, entry).await
         }
     }
 
     /// Locks transaction resource as read-write at given key. It is strongly
     /// advised that you lock r/w even if the resource is accessed as write
+    ///
+    /// A key missing in lv0 gets a cached copy of its current record there,
+    /// which keeps shadowing right if the transaction aborts.
     pub async fn tr_lock_rw(
         &mut self,
         token: &TransactionToken,
         key: &ByteStream,
     ) -> Result<(), ()> {
+        let ts = unsafe { (*token._trans).ts };
+        let missing = self.tr_entry(ts, key).await.is_none();
+        let placeholder = match missing {
+            false => None,
+            true => Some(
+                match self.get_below_lv0(key.as_ref()).await.map_err(|_| ())? {
+                    Some(KvData::Value { value, .. }) => KvData::Value {
+                        cached: true,
+                        value,
+                    },
+                    _ => KvData::Tombstone { cached: true },
+                },
+            ),
+        };
         unsafe {
-            // access entry in tree
-            let entry = {
-                let _lock = self.lv0_lock.read().await;
-                // try and find existing pair
-                match self.lv0.get(key) {
-                    Some(ent) => ent,
-                    None => {
-                        // insert new pair
-                        self.lv0.insert(
-                            ByteStream::from(key),
-                            KvEntry::new(KvData::Value {
-                                cached: false,
-                                value: ByteStream::new(),
-                            }),
-                        );
-                        // and return the inserted
-                        self.lv0.get(key).unwrap()
+            // access entry in tree, or insert new pair into lv0 and return it
+            let entry = match self.tr_entry(ts, key).await {
+                Some((_, ent)) => &mut *ent,
+                None => {
+                    let _lock = self.lv0_lock.read().await;
+                    let placeholder = placeholder.unwrap_or(KvData::Tombstone { cached: true });
+                    match self.lv0.get_mut().try_insert(key.as_ref(), &placeholder, 0) {
+                        Ok(ent) | Err(ent) => &mut *ent,
                     }
                 }
             };
@@ -104,14 +649,121 @@ impl LsmTree {
         }
     }
 
+    /// Writes a value within a transaction. The key must have been locked as
+    /// read-write with [`LsmTree::tr_lock_rw`] beforehand.
+    ///
+    /// The write only reaches the write-ahead log when the transaction commits.
+    pub async fn tr_write(
+        &mut self,
+        token: &TransactionToken,
+        key: &ByteStream,
+        value: ByteStream,
+    ) -> Result<(), ()> {
+        let record = KvData::Value {
+            cached: false,
+            value,
+        };
+        self.tr_put(token, key, record).await
+    }
+
+    /// Removes a key within a transaction by writing a tombstone, which hides
+    /// all older versions of the key. The same locking rules as for
+    /// [`LsmTree::tr_write`] apply.
+    pub async fn tr_remove(
+        &mut self,
+        token: &TransactionToken,
+        key: &ByteStream,
+    ) -> Result<(), ()> {
+        let record = KvData::Tombstone { cached: false };
+        self.tr_put(token, key, record).await
+    }
+
+    /// Writes a record within a transaction.
+    async fn tr_put(
+        &mut self,
+        token: &TransactionToken,
+        key: &ByteStream,
+        record: KvData,
+    ) -> Result<(), ()> {
+        unsafe {
+            // access entry in tree, keeping the record along with it
+            let trans = &mut *token._trans;
+            let (tree, entry) = match self.tr_entry(trans.ts, key).await {
+                None => return Err(()), // was never locked
+                Some((tree, ent)) => (&*tree, &mut *ent),
+            };
+            let stored = tree.arena_record(&record);
+            self.trans.write(trans, entry, stored).await?;
+            trans.writes.push((ByteStream::from(key), record));
+            Ok(())
+        }
+    }
+
+    /// Finds the entry transactions lock for a key, and the tree holding it.
+    ///
+    /// lv0 is searched first, followed by the trees in lv1 from the newest to
+    /// the oldest, except for the one being flushed. Entries of the latter
+    /// are no longer locked by anyone, and are shadowed by new ones in lv0.
+    /// A frozen tree holding the entry is pinned by the transaction.
+    async fn tr_entry(
+        &mut self,
+        ts: u64,
+        key: &ByteStream,
+    ) -> Option<(*const SkipList<ByteStream, KvEntry>, *mut KvEntry)> {
+        let _lock0 = self.lv0_lock.read().await;
+        if let Some(entry) = self.lv0.get_mut().get_ptr(key) {
+            return Some((Arc::as_ptr(self.lv0.get_mut()), entry));
+        }
+        let _lock1 = self.lv1_lock.read().await;
+        let lv1 = self.lv1.get_mut();
+        let pending = lv1.len() - self.jobs.get_mut().flushing as usize;
+        for frozen in &mut lv1[..pending] {
+            if let Some(entry) = frozen.tree.get_ptr(key) {
+                if !frozen.pins.contains(&ts) {
+                    frozen.pins.push(ts);
+                }
+                // entries are only written while locked by transactions
+                return Some((Arc::as_ptr(&frozen.tree), entry));
+            }
+        }
+        None
+    }
+
     /// Commit transaction. You should no longer be holding anything related to
     /// this transaction anymore (which explains why it's been consumed).
-    pub async fn tr_commit(&mut self, token: TransactionToken) -> () {
+    ///
+    /// Writes of the transaction are logged before it is marked as committed.
+    /// If there is no room for them or logging fails, the transaction is
+    /// aborted instead.
+    pub async fn tr_commit(&mut self, token: TransactionToken) -> IoResult<()> {
         unsafe {
             let trans = &mut *token._trans;
+            let logged = '_wal: {
+                if trans.writes.is_empty() {
+                    break '_wal Ok(());
+                }
+                if let Err(err) = self.make_room().await {
+                    break '_wal Err(err);
+                }
+                let records: Vec<(&[u8], &KvData)> = trans
+                    .writes
+                    .iter()
+                    .map(|(key, record)| (key.as_ref(), record))
+                    .collect();
+                self.wal.lock().await.append(trans.ts, &records)
+            };
+            if let Err(err) = logged {
+                self.trans.abort(trans).await;
+                self.trans.remove_trans(trans).await;
+                self.jobs_due.store(true, atomic::Ordering::Release);
+                return Err(err);
+            }
             self.trans.commit(trans).await;
             self.trans.remove_trans(trans).await;
         }
+        // frozen trees pinned by the transaction may be flushed now
+        self.jobs_due.store(true, atomic::Ordering::Release);
+        Ok(())
     }
 
     /// Abort transaction. You should no longer be holding anything related to
@@ -122,61 +774,284 @@ impl LsmTree {
             self.trans.abort(trans).await;
             self.trans.remove_trans(trans).await;
         }
+        self.jobs_due.store(true, atomic::Ordering::Release);
     }
 
-    /// Access a value outside a transaction.
-    pub async fn raw_get(&mut self, key: &[u8]) -> Option<ByteStream> {
+    /// Access a value outside a transaction. Any number of reads may run at
+    /// once, as they only share the database. Fails if the value is in a
+    /// corrupt block of a table.
+    pub async fn raw_get(&self, key: &[u8]) -> IoResult<Option<ByteStream>> {
         // crappy design of memtables...
         let key_bs = ByteStream::from(key);
 
         // lookup lv0
         '_lv0: {
             let _lock = self.lv0_lock.read().await;
-            match self.lv0.get(&key_bs) {
-                Some(entry) => match &entry.record {
-                    KvData::Tombstone { .. } => return None,
-                    KvData::Value { value, .. } => return Some(ByteStream::from(value)),
-                },
-                None => (),
+            let lv0 = unsafe { &*self.lv0.get() };
+            if let Some(entry) = lv0.get_ref(&key_bs) {
+                return match &entry.record {
+                    KvData::Tombstone { .. } => Ok(None),
+                    KvData::Value { value, .. } => Ok(Some(ByteStream::from(value))),
+                };
             }
         }
+        match self.get_below_lv0(key).await? {
+            Some(KvData::Value { value, .. }) => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Iterates over the keys within `range` in ascending order, yielding at
+    /// most `limit` records. Removed keys are skipped, and tables whose range
+    /// filter rules out the range are not read at all.
+    ///
+    /// The iterator keeps a [`Snapshot`] of all levels, which are free to
+    /// change meanwhile.
+    pub async fn scan<'k, R>(&self, range: R, limit: usize) -> ScanIterator<'_>
+    where
+        R: RangeBounds<&'k [u8]>,
+    {
+        let snapshot = self.snapshot().await;
+        let lower = match range.start_bound() {
+            Bound::Included(key) | Bound::Excluded(key) => Some(*key),
+            Bound::Unbounded => None,
+        };
+
+        // sources are ordered from the newest to the oldest, and are dropped
+        // by the iterator before the snapshot
+        let mut sources = Vec::<ScanSource>::new();
+        let (trees, tables) = unsafe { snapshot.contents() };
+        for table in trees {
+            sources.push(ScanSource::Tree(match lower {
+                Some(key) => table.lower_bound_iter(key),
+                None => table.iter_ref(),
+            }));
+        }
+        let (start, end) = (
+            range.start_bound().map(|k| *k),
+            range.end_bound().map(|k| *k),
+        );
+        for table in tables {
+            // tables without any keys in range are not read at all
+            if !table.may_contain_range(start, end) {
+                continue;
+            }
+            sources.push(ScanSource::Table(match lower {
+                Some(key) => table.seek(key),
+                None => table.iter(),
+            }));
+        }
+
+        let start = start.map(ByteStream::from_slice);
+        let end = end.map(ByteStream::from_slice);
+        ScanIterator::new(sources, start, end, limit, snapshot)
+    }
+
+    /// Iterates over the keys starting with `prefix` in ascending order,
+    /// yielding at most `limit` records. Tables whose prefix filter rules out
+    /// the prefix are not read at all.
+    ///
+    /// The iterator keeps a [`Snapshot`] of all levels, which are free to
+    /// change meanwhile.
+    pub async fn prefix_scan(&self, prefix: &[u8], limit: usize) -> ScanIterator<'_> {
+        let snapshot = self.snapshot().await;
+
+        // sources are ordered from the newest to the oldest, and are dropped
+        // by the iterator before the snapshot
+        let mut sources = Vec::<ScanSource>::new();
+        let (trees, tables) = unsafe { snapshot.contents() };
+        for table in trees {
+            sources.push(ScanSource::Tree(table.lower_bound_iter(prefix)));
+        }
+        for table in tables {
+            if table.may_contain_prefix(prefix) {
+                sources.push(ScanSource::Table(table.seek(prefix)));
+            }
+        }
+
+        // keys with the prefix are less than its successor
+        let start = Bound::Included(ByteStream::from_slice(prefix));
+        let end = match prefix.iter().rposition(|byte| *byte != 0xff) {
+            Some(last) => {
+                let mut end = prefix[..=last].to_vec();
+                end[last] += 1;
+                Bound::Excluded(ByteStream::from_vec(end))
+            }
+            None => Bound::Unbounded,
+        };
+        ScanIterator::new(sources, start, end, limit, snapshot)
+    }
+
+    /// Opens a cursor moving back and forth over all keys, which is invalid
+    /// until sought to a key. Removed keys are skipped.
+    ///
+    /// The cursor keeps a [`Snapshot`] of all levels, which are free to
+    /// change meanwhile, so that the database may be written while paging
+    /// through it.
+    pub async fn cursor(&self) -> ScanCursor<'_> {
+        let snapshot = self.snapshot().await;
+
+        // trees and tables are ordered from the newest to the oldest, and are
+        // dropped by the cursor before the snapshot
+        let (trees, tables) = unsafe { snapshot.contents() };
+        let trees = trees.into_iter().map(ScanTable::Tree);
+        let tables = trees.chain(tables.into_iter().map(ScanTable::Table));
+        ScanCursor::new(tables.collect(), snapshot)
+    }
+
+    /// Takes the trees and tables of all levels, locking the levels only
+    /// until they are taken.
+    async fn snapshot(&self) -> Snapshot {
+        let _lock0 = self.lv0_lock.read().await;
+        let _lock1 = self.lv1_lock.read().await;
+        let _lockrest = self.lvrest_lock.read().await;
+        let (lv0, lv1, lvrest) =
+            unsafe { (&*self.lv0.get(), &*self.lv1.get(), &*self.lvrest.get()) };
+        let frozen = lv1.iter().map(|frozen| frozen.tree.clone());
+        let trees = [lv0.clone()].into_iter().chain(frozen).collect();
+        let tables = lvrest.iter().map(|(_loc, table)| table.clone()).collect();
+        Snapshot::new(trees, tables)
+    }
+
+    /// Looks up the newest record of a key in lv1 and lvrest. Tombstones are
+    /// returned as well, as they hide all older versions.
+    async fn get_below_lv0(&self, key: &[u8]) -> IoResult<Option<KvData>> {
+        let key_bs = ByteStream::from(key);
+
         // lookup lv1
         '_lv1: {
             let _lock = self.lv1_lock.read().await;
-            for table in &self.lv1 {
-                // rbtree actually needs const ref only
-                match unsafe { utils::const_as_mut(table) }.get(&key_bs) {
-                    Some(entry) => match &entry.record {
-                        KvData::Tombstone { .. } => return None,
-                        KvData::Value { value, .. } => return Some(ByteStream::from(value)),
-                    },
-                    None => (),
-                };
+            let lv1 = unsafe { &*self.lv1.get() };
+            for frozen in lv1 {
+                if let Some(entry) = frozen.tree.get_ref(&key_bs) {
+                    return Ok(Some(entry.record.clone()));
+                }
             }
         }
         // lookup sstables
         '_lvrest: {
             let _lock = self.lvrest_lock.read().await;
-            for (_loc, ss) in &mut self.lvrest {
-                match ss.get(key) {
-                    Some(crate::record::KvData::Tombstone { .. }) => return None,
-                    Some(crate::record::KvData::Value { value, .. }) => return Some(value),
-                    None => (),
-                };
+            let lvrest = unsafe { &*self.lvrest.get() };
+            for (_loc, ss) in lvrest {
+                if let Some(record) = ss.get(key)? {
+                    return Ok(Some(record));
+                }
             }
         }
-        None
+        Ok(None)
     }
 
     /// Modify value outside a transaction. This will break existing references
-    /// to this value.
-    pub async fn raw_insert(&mut self, key: ByteStream, value: ByteStream) -> () {
+    /// to this value. Any number of writers may insert at once.
+    ///
+    /// A write that fails is not applied. Writes only fail if the key is locked
+    /// by an ongoing transaction, if logging fails, or if they would have to
+    /// wait for frozen trees to be flushed while flushing fails.
+    pub async fn raw_insert(&self, key: ByteStream, value: ByteStream) -> IoResult<()> {
         let record = KvData::Value {
             cached: false,
             value,
         };
-        let _lock = self.lv0_lock.write().await;
-        self.lv0.insert_internal(key, record);
+        self.raw_put(key, record).await
+    }
+
+    /// Remove value outside a transaction, writing a tombstone that hides all
+    /// older versions of the key. This will break existing references to this
+    /// value.
+    pub async fn raw_remove(&self, key: ByteStream) -> IoResult<()> {
+        self.raw_put(key, KvData::Tombstone { cached: false }).await
+    }
+
+    /// Writes a record outside a transaction.
+    ///
+    /// The log is only held while appending, so writes may reach lv0 in a
+    /// different order than they were logged. A record is therefore only
+    /// replaced by writes logged after it, as in the log.
+    async fn raw_put(&self, key: ByteStream, record: KvData) -> IoResult<()> {
+        self.make_room().await?;
+        '_lv0: {
+            let _freeze = self.freeze_lock.read().await;
+            // the transaction would neither see the write nor have it logged
+            // before its own
+            if self.is_locked(&key).await {
+                return Err(Error::new(
+                    ErrorKind::ResourceBusy,
+                    "key is locked by a transaction",
+                ));
+            }
+            let seq = self.log(&key, &record).await?;
+            let lock0 = self.lv0_lock.read().await;
+            let lv0 = unsafe { &*self.lv0.get() };
+            if lv0.try_insert(key.as_ref(), &record, seq).is_ok() {
+                break '_lv0;
+            }
+            // racing writers of the same key are kept apart, while readers
+            // holding the old entry go on reading it
+            drop(lock0);
+            let _lock = self.lv0_lock.write().await;
+            let lv0 = unsafe { &*self.lv0.get() };
+            if lv0.get_ref(&key).is_some_and(|entry| entry.seq < seq) {
+                unsafe { lv0.replace(key.as_ref(), &record, seq) };
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if an ongoing transaction locked the entry of a key, i.e. the one
+    /// found by [`LsmTree::tr_entry`]. Transactions take the database
+    /// exclusively, so this holds until the caller lets go of it.
+    async fn is_locked(&self, key: &ByteStream) -> bool {
+        let locked = |entry: &KvEntry| {
+            self.trans.is_ongoing(entry.ts_read) || self.trans.is_ongoing(entry.ts_write)
+        };
+        '_lv0: {
+            let _lock = self.lv0_lock.read().await;
+            let lv0 = unsafe { &*self.lv0.get() };
+            if let Some(entry) = lv0.get_ref(key) {
+                return locked(entry);
+            }
+        }
+        let _lock = self.lv1_lock.read().await;
+        let lv1 = unsafe { &*self.lv1.get() };
+        lv1.iter()
+            .find_map(|frozen| frozen.tree.get_ref(key))
+            .is_some_and(locked)
+    }
+
+    /// Logs a write made outside a transaction, returning its sequence number.
+    ///
+    /// Writers queue up their writes, and whoever gets hold of the log next
+    /// appends all queued writes as one record (group commit). Writers whose
+    /// writes were appended by another one only wait for the outcome.
+    async fn log(&self, key: &ByteStream, record: &KvData) -> IoResult<u64> {
+        let (sender, receiver) = oneshot::channel();
+        let write = (ByteStream::from(key), record.clone(), sender);
+        self.log_queue.lock().await.push(write);
+        '_append: {
+            let mut wal = self.wal.lock().await;
+            let batch = mem::take(&mut *self.log_queue.lock().await);
+            if batch.is_empty() {
+                break '_append;
+            }
+            let records: Vec<(&[u8], &KvData)> = batch
+                .iter()
+                .map(|(key, record, _)| (key.as_ref(), record))
+                .collect();
+            let logged = wal.append(0, &records);
+            let first = self
+                .next_seq
+                .fetch_add(batch.len() as u64, atomic::Ordering::Relaxed);
+            for (seq, (_, _, sender)) in (first..).zip(batch) {
+                let _ = sender.send(match &logged {
+                    Ok(()) => Ok(seq),
+                    Err(err) => Err(Error::new(err.kind(), err.to_string())),
+                });
+            }
+        }
+        match receiver.await {
+            Ok(logged) => logged,
+            Err(_) => Err(Error::other("write dropped before it was logged")),
+        }
     }
 }
 
@@ -187,28 +1062,46 @@ pub struct TransactionToken {
 
 /// Location of an SSTable. When comparing [`SSLoc`]s, the smaller one is
 /// always the newer one.
-#[derive(PartialEq, Eq, Ord)]
-struct SSLoc {
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SSLoc {
     /// Tier. The larger it gets, the older it is.
-    tier: u32,
+    pub tier: u32,
 
     /// Run. In one tier, the larger the run is, the newer it is.
-    run: u32,
+    pub run: u32,
+}
+
+impl SSLoc {
+    /// Name of the table file within the database directory.
+    pub fn file_name(&self) -> String {
+        format!("{:06}-{:010}.sst", self.tier, self.run)
+    }
+
+    /// Parses a file name produced by [`SSLoc::file_name`].
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let stem = name.strip_suffix(".sst")?;
+        let (tier, run) = stem.split_once('-')?;
+        Some(Self {
+            tier: tier.parse().ok()?,
+            run: run.parse().ok()?,
+        })
+    }
+}
+
+impl Ord for SSLoc {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.tier.cmp(&other.tier), self.run.cmp(&other.run)) {
+            (Ordering::Less, _) => Ordering::Less,
+            (Ordering::Equal, Ordering::Greater) => Ordering::Less,
+            (Ordering::Equal, Ordering::Equal) => Ordering::Equal,
+            (Ordering::Equal, Ordering::Less) => Ordering::Greater,
+            (Ordering::Greater, _) => Ordering::Greater,
+        }
+    }
 }
 
 impl PartialOrd for SSLoc {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let result = match (
-            self.tier.partial_cmp(&other.tier),
-            self.run.partial_cmp(&other.run),
-        ) {
-            (Some(Ordering::Less), _) => Ordering::Less,
-            (Some(Ordering::Equal), Some(Ordering::Greater)) => Ordering::Less,
-            (Some(Ordering::Equal), Some(Ordering::Equal)) => Ordering::Equal,
-            (Some(Ordering::Equal), Some(Ordering::Less)) => Ordering::Greater,
-            (Some(Ordering::Greater), _) => Ordering::Greater,
-            _ => unreachable!(),
-        };
-        Some(result)
+        Some(self.cmp(other))
     }
 }
@@ -1,2 +1,962 @@
-mod mgr;
+mod compaction;
+mod manifest;
+pub mod mgr;
+pub mod options;
+mod scan;
 mod transimpl;
+mod wal;
+mod worker;
+
+#[cfg(test)]
+mod tests {
+    use super::mgr::LsmTree;
+    use super::mgr::SSLoc;
+    use super::options::{CompactionStyle, Options};
+    use super::scan::{ScanCursor, ScanIterator};
+    use crate::record::ByteStream;
+    use crate::sstable::cache::BlockCache;
+    use crate::sstable::prefix::PrefixExtractor;
+    use crate::sstable::reader::{
+        SSTableReader, SSTableReaderCursor, SSTableReaderIterator, SSTableReaderReverseIterator,
+    };
+    use futures::executor::block_on;
+    use std::fs::OpenOptions;
+    use std::io::{ErrorKind, Write};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn get_db_path(name: &str) -> PathBuf {
+        let mut tmp_dir = std::env::temp_dir();
+        tmp_dir.push(format!("_kleestor_lsmt_{name}"));
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+        tmp_dir
+    }
+
+    fn kv(i: i64) -> (ByteStream, ByteStream) {
+        let key = format!("sample-key-{i}");
+        let value = format!("value-{i}-{i}-{i}");
+        (
+            ByteStream::from_slice(key.as_bytes()),
+            ByteStream::from_slice(value.as_bytes()),
+        )
+    }
+
+    /// Data written before `close` must be found again after reopening.
+    #[test]
+    fn reopen_recovers_tables() {
+        let path = get_db_path("reopen_recovers_tables");
+
+        for round in 0..3 {
+            let db = LsmTree::open(&path, Options::default()).unwrap();
+            for i in round * 100..(round + 1) * 100 {
+                let (key, value) = kv(i);
+                block_on(db.raw_insert(key, value)).unwrap();
+            }
+            block_on(db.close()).unwrap();
+        }
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..300 {
+            let (key, value) = kv(i);
+            let found = block_on(db.raw_get(key.as_ref())).unwrap();
+            assert!(found.unwrap() == value);
+        }
+        assert!(block_on(db.raw_get(b"sample-key-300")).unwrap().is_none());
+        block_on(db.close()).unwrap();
+
+        let options = Options {
+            error_if_exists: true,
+            ..Options::default()
+        };
+        assert!(LsmTree::open(&path, options).is_err());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Only tables recorded in the manifest are live, so a table left over by
+    /// an interrupted flush is removed, and a lost table is reported.
+    #[test]
+    fn manifest_tracks_tables() {
+        let path = get_db_path("manifest_tracks_tables");
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..100 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        block_on(db.close()).unwrap();
+
+        // a stray table shadowing everything must not be picked up
+        let table = path.join(SSLoc { tier: 0, run: 0 }.file_name());
+        let orphan = path.join(SSLoc { tier: 0, run: 999 }.file_name());
+        std::fs::copy(&table, &orphan).unwrap();
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        assert!(!orphan.exists());
+        let (key, value) = kv(42);
+        assert!(block_on(db.raw_get(key.as_ref())).unwrap().unwrap() == value);
+        block_on(db.close()).unwrap();
+
+        std::fs::remove_file(&table).unwrap();
+        assert!(LsmTree::open(&path, Options::default()).is_err());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Trees holding nothing but records cached by transactions are dropped
+    /// without writing a table.
+    #[test]
+    fn skips_flushing_cached_trees() {
+        let path = get_db_path("skips_flushing_cached_trees");
+        let count_tables = || {
+            std::fs::read_dir(&path)
+                .unwrap()
+                .filter_map(|entry| entry.unwrap().file_name().into_string().ok())
+                .filter(|name| SSLoc::from_file_name(name).is_some())
+                .count()
+        };
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..100 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        block_on(db.close()).unwrap();
+        assert_eq!(count_tables(), 1);
+
+        // locking caches the record in lv0, which aborting leaves behind
+        let mut db = LsmTree::open(&path, Options::default()).unwrap();
+        let token = block_on(db.tr_create(10));
+        let (key, value) = kv(42);
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        block_on(db.tr_abort(token));
+        block_on(db.close()).unwrap();
+        assert_eq!(count_tables(), 1);
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        assert!(block_on(db.raw_get(key.as_ref())).unwrap().unwrap() == value);
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Tombstones hide older versions in every level, and survive flushes and
+    /// reopening.
+    #[test]
+    fn removes_shadow_older_versions() {
+        let path = get_db_path("removes_shadow_older_versions");
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..100 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        block_on(db.close()).unwrap();
+
+        let mut db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in (0..100).step_by(2) {
+            let (key, _) = kv(i);
+            block_on(db.raw_remove(key)).unwrap();
+        }
+        let (key, _) = kv(1);
+        let token = block_on(db.tr_create(10));
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        block_on(db.tr_wait(&token)).unwrap();
+        block_on(db.tr_remove(&token, &key)).unwrap();
+        block_on(db.tr_commit(token)).unwrap();
+        // an aborted removal leaves the key intact
+        let (key, _) = kv(3);
+        let token = block_on(db.tr_create(11));
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        block_on(db.tr_wait(&token)).unwrap();
+        block_on(db.tr_remove(&token, &key)).unwrap();
+        block_on(db.tr_abort(token));
+
+        let check = |db: &mut LsmTree| {
+            for i in 0..100 {
+                let (key, value) = kv(i);
+                let found = block_on(db.raw_get(key.as_ref())).unwrap();
+                match i % 2 == 0 || i == 1 {
+                    true => assert!(found.is_none()),
+                    false => assert!(found.unwrap() == value),
+                }
+            }
+        };
+        check(&mut db);
+        block_on(db.freeze()).unwrap();
+        check(&mut db);
+        block_on(db.close()).unwrap();
+
+        let mut db = LsmTree::open(&path, Options::default()).unwrap();
+        check(&mut db);
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Scans see the newest version of every key across all levels, and stop
+    /// at the bounds and the limit.
+    #[test]
+    fn scans_merge_levels() {
+        let path = get_db_path("scans_merge_levels");
+        let key = |i: usize| format!("key-{i:04}");
+
+        // oldest versions on disk, newer ones frozen in lv1 and newest in lv0
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..300 {
+            let value = ByteStream::from_slice(format!("table-{i}").as_bytes());
+            block_on(db.raw_insert(ByteStream::from_slice(key(i).as_bytes()), value)).unwrap();
+        }
+        block_on(db.close()).unwrap();
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in (0..300).step_by(3) {
+            let value = ByteStream::from_slice(format!("frozen-{i}").as_bytes());
+            block_on(db.raw_insert(ByteStream::from_slice(key(i).as_bytes()), value)).unwrap();
+        }
+        block_on(db.freeze()).unwrap();
+        for i in (0..300).step_by(5) {
+            block_on(db.raw_remove(ByteStream::from_slice(key(i).as_bytes()))).unwrap();
+        }
+
+        let expected = |i: usize| match (i % 5, i % 3) {
+            (0, _) => None,
+            (_, 0) => Some(format!("frozen-{i}")),
+            _ => Some(format!("table-{i}")),
+        };
+        let scanned = |db: &LsmTree, start: &[u8], end: &[u8], limit: usize| {
+            block_on(db.scan(start..end, limit))
+                .map(|(key, value)| {
+                    let key = String::from_utf8(key.as_ref().to_vec()).unwrap();
+                    let value = String::from_utf8(value.as_ref().to_vec()).unwrap();
+                    (key, value)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let want: Vec<_> = (0..300)
+            .filter_map(|i| Some((key(i), expected(i)?)))
+            .collect();
+        assert!(block_on(db.scan(.., usize::MAX)).count() == want.len());
+        // scans only share the database, so that they may be open at once
+        let mut first = block_on(db.scan(.., usize::MAX));
+        let mut second = block_on(db.scan(.., usize::MAX));
+        assert!(first.next() == second.next() && first.count() == second.count());
+        let found = scanned(&db, b"key-0042", b"key-0250", usize::MAX);
+        let want_range: Vec<_> = (42..250)
+            .filter_map(|i| Some((key(i), expected(i)?)))
+            .collect();
+        assert!(found == want_range);
+        let found = scanned(&db, b"key-0042", b"key-9999", 10);
+        assert!(found == want_range[..10]);
+        let found = scanned(&db, b"key-0042x", b"key-0044", usize::MAX);
+        assert!(found == vec![(key(43), expected(43).unwrap())]);
+        assert!(scanned(&db, b"zzz", b"zzzz", usize::MAX).is_empty());
+
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Prefix scans see the keys with the prefix on every level, whether or
+    /// not the tables have prefix filters.
+    #[test]
+    fn prefix_scans_merge_levels() {
+        let path = get_db_path("prefix_scans_merge_levels");
+        let key = |t: usize, i: usize| format!("tenant-{t}/entity/{i:03}");
+        let insert = |db: &LsmTree, t: usize, i: usize| {
+            let value = ByteStream::from_slice(format!("value-{t}-{i}").as_bytes());
+            block_on(db.raw_insert(ByteStream::from_slice(key(t, i).as_bytes()), value)).unwrap();
+        };
+
+        // tables without prefix filters, then tables with them
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for t in 0..10 {
+            for i in 0..100 {
+                insert(&db, t, i);
+            }
+        }
+        block_on(db.close()).unwrap();
+        let options = || Options {
+            prefix_extractor: Some(PrefixExtractor::Delimited {
+                delimiter: b'/',
+                count: 1,
+            }),
+            ..Options::default()
+        };
+        let db = LsmTree::open(&path, options()).unwrap();
+        for t in 10..20 {
+            for i in 0..100 {
+                insert(&db, t, i);
+            }
+        }
+        block_on(db.close()).unwrap();
+
+        let db = LsmTree::open(&path, options()).unwrap();
+        for i in 100..110 {
+            insert(&db, 15, i);
+        }
+        block_on(db.raw_remove(ByteStream::from_slice(key(15, 0).as_bytes()))).unwrap();
+
+        let scanned = |db: &LsmTree, prefix: &str, limit: usize| {
+            block_on(db.prefix_scan(prefix.as_bytes(), limit))
+                .map(|(key, _)| String::from_utf8(key.as_ref().to_vec()).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert!(scanned(&db, "tenant-3/", usize::MAX).len() == 100);
+        let found = scanned(&db, "tenant-15/", usize::MAX);
+        assert!(found == (1..110).map(|i| key(15, i)).collect::<Vec<_>>());
+        assert!(scanned(&db, "tenant-1", usize::MAX).len() == 100 + 900 + 109);
+        assert!(scanned(&db, "tenant-15/", 5).len() == 5);
+        assert!(scanned(&db, "tenant-99/", usize::MAX).is_empty());
+
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Tables of a database share the block cache given by its options, which
+    /// serves repeated reads of the same blocks. Databases opened with the
+    /// default options share the cache of the whole process.
+    #[test]
+    fn block_cache_serves_reads() {
+        let path = get_db_path("block_cache_serves_reads");
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..1000 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        block_on(db.close()).unwrap();
+
+        let cache = Arc::new(BlockCache::new(1 << 20));
+        let options = Options {
+            block_cache: cache.clone(),
+            ..Options::default()
+        };
+        let db = LsmTree::open(&path, options).unwrap();
+        assert!(std::ptr::eq(db.block_cache(), &*cache));
+        let mut counters = Vec::new();
+        for _ in 0..2 {
+            counters.push((cache.hits(), cache.misses()));
+            for i in 0..1000 {
+                let (key, value) = kv(i);
+                let found = block_on(db.raw_get(key.as_ref())).unwrap().unwrap();
+                assert!(found.as_ref() == value.as_ref());
+            }
+        }
+        counters.push((cache.hits(), cache.misses()));
+        // blocks of the reopened table are loaded first, and then served
+        assert!(counters[1].1 > counters[0].1);
+        assert!(counters[2].0 - counters[1].0 >= 1000);
+        assert!(cache.usage() > 0 && cache.usage() <= cache.capacity());
+
+        // databases not given a cache use the shared one
+        let other_path = get_db_path("block_cache_serves_reads_other");
+        let other = LsmTree::open(&other_path, Options::default()).unwrap();
+        assert!(std::ptr::eq(other.block_cache(), &*BlockCache::global()));
+        block_on(other.close()).unwrap();
+        std::fs::remove_dir_all(&other_path).unwrap();
+
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Cursors move both ways over the newest versions of keys on every
+    /// level, turning around at any key.
+    #[test]
+    fn cursor_moves_both_ways() {
+        let path = get_db_path("cursor_moves_both_ways");
+        let key = |i: usize| ByteStream::from_slice(format!("key-{i:04}").as_bytes());
+        let value = |v: &str, i: usize| ByteStream::from_slice(format!("{v}-{i}").as_bytes());
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..300 {
+            block_on(db.raw_insert(key(i), value("old", i))).unwrap();
+        }
+        block_on(db.close()).unwrap();
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 100..200 {
+            block_on(db.raw_insert(key(i), value("new", i))).unwrap();
+        }
+        for i in 150..160 {
+            block_on(db.raw_remove(key(i))).unwrap();
+        }
+
+        let mut cursor = block_on(db.cursor());
+        assert!(!cursor.valid());
+        cursor.seek_to_first();
+        assert!(cursor.key() == Some(key(0).as_ref()));
+        cursor.seek(b"key-0149");
+        assert!(cursor.value() == Some(value("new", 149).as_ref()));
+        cursor.next();
+        assert!(cursor.key() == Some(key(160).as_ref()));
+        cursor.prev();
+        cursor.prev();
+        assert!(cursor.key() == Some(key(148).as_ref()));
+        cursor.seek(b"key-0150");
+        assert!(cursor.key() == Some(key(160).as_ref()));
+        cursor.seek_to_last();
+        assert!(cursor.value() == Some(value("old", 299).as_ref()));
+        cursor.next();
+        assert!(!cursor.valid());
+        cursor.prev();
+        assert!(!cursor.valid());
+
+        // pages of keys in either direction
+        let mut forward = Vec::<Vec<u8>>::new();
+        cursor.seek_to_first();
+        while cursor.valid() {
+            forward.push(cursor.key().unwrap().to_vec());
+            cursor.next();
+        }
+        let mut backward = Vec::<Vec<u8>>::new();
+        cursor.seek_to_last();
+        while cursor.valid() {
+            backward.push(cursor.key().unwrap().to_vec());
+            cursor.prev();
+        }
+        backward.reverse();
+        assert!(forward.len() == 290 && forward == backward);
+        drop(cursor);
+
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Cursors leave the levels unlocked, so that their task may overwrite
+    /// the keys it pages over and keep freezing and flushing lv0 meanwhile.
+    #[test]
+    fn cursor_allows_writes() {
+        let path = get_db_path("cursor_allows_writes");
+        let options = Options {
+            memtable_size: 8 << 10,
+            max_frozen_memtables: 0,
+            ..Options::default()
+        };
+        let key = |i: usize| ByteStream::from_slice(format!("key-{i:04}").as_bytes());
+        let value = |v: &str, i: usize| ByteStream::from_slice(format!("{v}-{i}").as_bytes());
+
+        let db = LsmTree::open(&path, options).unwrap();
+        for i in 0..100 {
+            block_on(db.raw_insert(key(i), value("old", i))).unwrap();
+        }
+        let mut cursor = block_on(db.cursor());
+        cursor.seek_to_first();
+        let mut seen = 0;
+        while cursor.valid() {
+            assert!(cursor.key() == Some(key(seen).as_ref()));
+            assert!(cursor.value() == Some(value("old", seen).as_ref()));
+            block_on(db.raw_insert(key(seen), value("new", seen))).unwrap();
+            // keys before the cursor fill up lv0 again and again
+            for i in 0..20 {
+                let filler = ByteStream::from_slice(format!("filler-{seen}-{i}").as_bytes());
+                block_on(db.raw_insert(filler, value("filler", i))).unwrap();
+            }
+            cursor.next();
+            seen += 1;
+        }
+        assert!(seen == 100);
+        drop(cursor);
+        let mut cursor = block_on(db.cursor());
+        cursor.seek(b"key-0042");
+        assert!(cursor.value() == Some(value("new", 42).as_ref()));
+        drop(cursor);
+        for i in 0..100 {
+            let found = block_on(db.raw_get(key(i).as_ref())).unwrap();
+            assert!(found.unwrap() == value("new", i));
+        }
+
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Writes that never made it into a table are replayed from the log,
+    /// ignoring a record torn in the middle of being written.
+    #[test]
+    fn wal_recovers_writes() {
+        let path = get_db_path("wal_recovers_writes");
+
+        let mut db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..100 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        // committed transaction is logged
+        let token = block_on(db.tr_create(10));
+        let (key, value) = kv(100);
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        block_on(db.tr_wait(&token)).unwrap();
+        block_on(db.tr_write(&token, &key, value)).unwrap();
+        block_on(db.tr_commit(token)).unwrap();
+        // aborted transaction is not
+        let token = block_on(db.tr_create(11));
+        let (key, value) = kv(101);
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        block_on(db.tr_wait(&token)).unwrap();
+        block_on(db.tr_write(&token, &key, value)).unwrap();
+        block_on(db.tr_abort(token));
+        // crash without closing
+        drop(db);
+
+        let mut segment = path.clone();
+        segment.push("wal");
+        segment.push(format!("{:016}.log", 0));
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[0x7f, 0x01, 0x02]).unwrap();
+        drop(file);
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..=100 {
+            let (key, value) = kv(i);
+            let found = block_on(db.raw_get(key.as_ref())).unwrap();
+            assert!(found.unwrap() == value);
+        }
+        assert!(block_on(db.raw_get(b"sample-key-101")).unwrap().is_none());
+        block_on(db.close()).unwrap();
+
+        // log is truncated once its content is persisted
+        let mut wal = path.clone();
+        wal.push("wal");
+        assert_eq!(std::fs::read_dir(&wal).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// A crash may leave zeros or stale bytes after the last record, which end
+    /// the log, while a broken record followed by intact ones fails to open.
+    #[test]
+    fn wal_ignores_torn_tails() {
+        let path = get_db_path("wal_ignores_torn_tails");
+        let mut segment = path.join("wal");
+        segment.push(format!("{:016}.log", 0));
+
+        // zeros, and a torn record followed by stale bytes
+        let mut garbled = vec![0x10, 0xde, 0xad, 0xbe, 0xef];
+        garbled.extend_from_slice(b"klee-");
+        garbled.extend_from_slice(&[0xff; 32]);
+        for tail in [vec![0_u8; 4096], garbled] {
+            let db = LsmTree::open(&path, Options::default()).unwrap();
+            for i in 0..100 {
+                let (key, value) = kv(i);
+                block_on(db.raw_insert(key, value)).unwrap();
+            }
+            drop(db);
+            let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+            file.write_all(&tail).unwrap();
+            drop(file);
+
+            let db = LsmTree::open(&path, Options::default()).unwrap();
+            for i in 0..100 {
+                let (key, value) = kv(i);
+                assert!(block_on(db.raw_get(key.as_ref())).unwrap().unwrap() == value);
+            }
+            block_on(db.close()).unwrap();
+            std::fs::remove_dir_all(&path).unwrap();
+        }
+
+        // the payload of the first record is damaged
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..100 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        drop(db);
+        let mut bytes = std::fs::read(&segment).unwrap();
+        bytes[8] ^= 0xff;
+        std::fs::write(&segment, &bytes).unwrap();
+        assert!(LsmTree::open(&path, Options::default()).is_err());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Only the segment written last may end with a torn record, which is cut
+    /// off when replayed. Broken records in earlier segments fail to open.
+    #[test]
+    fn wal_checks_sealed_segments() {
+        let path = get_db_path("wal_checks_sealed_segments");
+        let segment = |seq: u64| path.join("wal").join(format!("{seq:016}.log"));
+        let tear = |seq: u64| {
+            let mut file = OpenOptions::new().append(true).open(segment(seq)).unwrap();
+            file.write_all(&[0x7f, 0x01, 0x02]).unwrap();
+        };
+
+        // the torn tail is gone once replayed, and later crashes recover
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..100 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        drop(db);
+        tear(0);
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 100..200 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        drop(db);
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..200 {
+            let (key, value) = kv(i);
+            assert!(block_on(db.raw_get(key.as_ref())).unwrap().unwrap() == value);
+        }
+        drop(db);
+
+        // a record running past the end of an earlier segment is corruption
+        tear(1);
+        assert!(LsmTree::open(&path, Options::default()).is_err());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Raw writes keep off keys locked by ongoing transactions, so that the
+    /// log replays to what was read before.
+    #[test]
+    fn raw_writes_respect_transactions() {
+        let path = get_db_path("raw_writes_respect_transactions");
+        let write = |db: &LsmTree, key: &[u8], value: &[u8]| {
+            let (key, value) = (ByteStream::from_slice(key), ByteStream::from_slice(value));
+            block_on(db.raw_insert(key, value))
+        };
+        let read = |db: &LsmTree, key: &[u8]| {
+            let found = block_on(db.raw_get(key)).unwrap();
+            found.map(|value| value.as_ref().to_vec())
+        };
+
+        let mut db = LsmTree::open(&path, Options::default()).unwrap();
+        write(&db, b"sample-key", b"raw-1").unwrap();
+        let key = ByteStream::from_slice(b"sample-key");
+        let token = block_on(db.tr_create(10));
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        let err = write(&db, b"sample-key", b"raw-2").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+        block_on(db.tr_wait(&token)).unwrap();
+        let written = ByteStream::from_slice(b"written-by-10");
+        block_on(db.tr_write(&token, &key, written)).unwrap();
+        block_on(db.tr_commit(token)).unwrap();
+        assert!(read(&db, b"sample-key").unwrap() == b"written-by-10");
+        write(&db, b"sample-key", b"raw-3").unwrap();
+        assert!(read(&db, b"sample-key").unwrap() == b"raw-3");
+
+        // keys missing in lv0 are locked through their placeholders
+        let key = ByteStream::from_slice(b"other-key");
+        let token = block_on(db.tr_create(11));
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        let err = block_on(db.raw_remove(ByteStream::from_slice(b"other-key"))).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+        block_on(db.tr_abort(token));
+        assert!(read(&db, b"other-key").is_none());
+        write(&db, b"other-key", b"raw-4").unwrap();
+        assert!(read(&db, b"other-key").unwrap() == b"raw-4");
+        // crash without closing
+        drop(db);
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        assert!(read(&db, b"sample-key").unwrap() == b"raw-3");
+        assert!(read(&db, b"other-key").unwrap() == b"raw-4");
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Trees frozen while transactions are ongoing stay in memory until they
+    /// finish, and their entries remain lockable and revertible.
+    #[test]
+    fn freezes_during_transactions() {
+        let path = get_db_path("freezes_during_transactions");
+        let tables = |path: &PathBuf| {
+            std::fs::read_dir(path)
+                .unwrap()
+                .filter(|entry| {
+                    let name = entry.as_ref().unwrap().file_name();
+                    name.to_str().unwrap().ends_with(".sst")
+                })
+                .count()
+        };
+
+        let mut db = LsmTree::open(&path, Options::default()).unwrap();
+        let (key, value) = kv(1);
+        block_on(db.raw_insert(key, value)).unwrap();
+        let (key, value) = kv(1);
+        let token = block_on(db.tr_create(10));
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        block_on(db.tr_wait(&token)).unwrap();
+        let written = ByteStream::from_slice(b"written-by-10");
+        block_on(db.tr_write(&token, &key, written)).unwrap();
+
+        // the frozen trees are pinned, so no table is written for them
+        block_on(db.freeze()).unwrap();
+        for i in 2..4 {
+            let (other, other_value) = kv(i);
+            block_on(db.raw_insert(other, other_value)).unwrap();
+            block_on(db.freeze()).unwrap();
+        }
+        assert_eq!(tables(&path), 0);
+        block_on(db.tr_abort(token));
+        assert!(block_on(db.raw_get(key.as_ref())).unwrap().unwrap() == value);
+
+        // a later transaction locks the entry within the frozen tree
+        let token = block_on(db.tr_create(11));
+        block_on(db.tr_lock_rw(&token, &key)).unwrap();
+        block_on(db.tr_wait(&token)).unwrap();
+        let written = ByteStream::from_slice(b"written-by-11");
+        block_on(db.tr_write(&token, &key, written)).unwrap();
+        block_on(db.tr_commit(token)).unwrap();
+        let found = block_on(db.raw_get(key.as_ref())).unwrap().unwrap();
+        assert!(found.as_ref() == b"written-by-11");
+        block_on(db.close()).unwrap();
+        assert!(tables(&path) > 0);
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        let found = block_on(db.raw_get(key.as_ref())).unwrap().unwrap();
+        assert!(found.as_ref() == b"written-by-11");
+        for i in 2..4 {
+            let (other, other_value) = kv(i);
+            assert!(block_on(db.raw_get(other.as_ref())).unwrap().unwrap() == other_value);
+        }
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Sustained writes rotate lv0 and flush frozen trees into tables, while
+    /// every write stays readable.
+    #[test]
+    fn flushes_under_sustained_writes() {
+        let path = get_db_path("flushes_under_sustained_writes");
+        let options = Options {
+            memtable_size: 16 << 10,
+            max_frozen_memtables: 1,
+            ..Options::default()
+        };
+
+        let db = LsmTree::open(&path, options).unwrap();
+        for i in 0..2000 {
+            let (key, value) = kv(i);
+            block_on(db.raw_insert(key, value)).unwrap();
+        }
+        let tables = std::fs::read_dir(&path)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_str().unwrap().ends_with(".sst")
+            })
+            .count();
+        assert!(tables > 1);
+        for i in 0..2000 {
+            let (key, value) = kv(i);
+            let found = block_on(db.raw_get(key.as_ref())).unwrap();
+            assert!(found.unwrap() == value);
+        }
+        // only logs of lv0 and the one frozen tree are retained
+        let mut wal = path.clone();
+        wal.push("wal");
+        assert!(std::fs::read_dir(&wal).unwrap().count() <= 2);
+
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Failed flushes are reported by `status` and `close` rather than by the
+    /// writes that happen to run next. Writes are only turned down once
+    /// frozen trees pile up behind them, and are not applied then.
+    #[test]
+    fn reports_background_failures() {
+        let path = get_db_path("reports_background_failures");
+        let options = Options {
+            max_frozen_memtables: 1,
+            ..Options::default()
+        };
+
+        let db = LsmTree::open(&path, options).unwrap();
+        // tables cannot be written with directories in their way
+        let blocked: Vec<PathBuf> = (0..16)
+            .map(|run| path.join(format!("{}.tmp", SSLoc { tier: 0, run }.file_name())))
+            .collect();
+        for dir in &blocked {
+            std::fs::create_dir(dir).unwrap();
+        }
+        let (key, value) = kv(0);
+        block_on(db.raw_insert(key, value)).unwrap();
+        block_on(db.freeze()).unwrap();
+        let (key, value) = kv(1);
+        block_on(db.raw_insert(key, value)).unwrap();
+        while block_on(db.status()).is_ok() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        block_on(db.freeze()).unwrap();
+        let (key, value) = kv(2);
+        assert!(block_on(db.raw_insert(ByteStream::from(&key), value)).is_err());
+        assert!(block_on(db.raw_get(key.as_ref())).unwrap().is_none());
+        assert!(block_on(db.close()).is_err());
+
+        // everything written is recovered from the log
+        for dir in &blocked {
+            std::fs::remove_dir(dir).unwrap();
+        }
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..2 {
+            let (key, value) = kv(i);
+            assert!(block_on(db.raw_get(key.as_ref())).unwrap().unwrap() == value);
+        }
+        assert!(block_on(db.raw_get(kv(2).0.as_ref())).unwrap().is_none());
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Writers sharing the database insert at the same time, while lv0 keeps
+    /// getting frozen and flushed. Writes racing for the same key end up as
+    /// they were logged, so that the log replays the same values.
+    #[test]
+    fn concurrent_inserts() {
+        let path = get_db_path("concurrent_inserts");
+        let options = Options {
+            memtable_size: 16 << 10,
+            ..Options::default()
+        };
+
+        let db = LsmTree::open(&path, options).unwrap();
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let db = &db;
+                s.spawn(move || {
+                    for i in 0..250 {
+                        let (key, value) = kv(t * 250 + i);
+                        block_on(db.raw_insert(key, value)).unwrap();
+                        // every thread overwrites the same few keys
+                        let (key, _) = kv(2000 + i % 10);
+                        let value = format!("shared-{t}-{i}");
+                        block_on(db.raw_insert(key, ByteStream::from_slice(value.as_bytes())))
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        for i in 0..2000 {
+            let (key, value) = kv(i);
+            let found = block_on(db.raw_get(key.as_ref())).unwrap();
+            assert!(found.unwrap() == value);
+        }
+        let shared: Vec<ByteStream> = (2000..2010)
+            .map(|i| block_on(db.raw_get(kv(i).0.as_ref())).unwrap().unwrap())
+            .collect();
+        // crash without closing
+        drop(db);
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..2000 {
+            let (key, value) = kv(i);
+            let found = block_on(db.raw_get(key.as_ref())).unwrap();
+            assert!(found.unwrap() == value);
+        }
+        for (i, value) in (2000..2010).zip(shared) {
+            let found = block_on(db.raw_get(kv(i).0.as_ref())).unwrap();
+            assert!(found.unwrap() == value);
+        }
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Compaction bounds the number of tables and keeps the newest versions.
+    #[test]
+    fn tiered_compaction_keeps_newest() {
+        let path = get_db_path("tiered_compaction_keeps_newest");
+        let options = Options {
+            memtable_size: 8 << 10,
+            max_frozen_memtables: 0,
+            tier_threshold: 2,
+            ..Options::default()
+        };
+
+        let db = LsmTree::open(&path, options).unwrap();
+        for round in 0..4 {
+            for i in 0..500 {
+                let (key, _) = kv(i);
+                let value = format!("round-{round}-{i}");
+                block_on(db.raw_insert(key, ByteStream::from_slice(value.as_bytes()))).unwrap();
+            }
+        }
+        block_on(db.close()).unwrap();
+
+        let tables = std::fs::read_dir(&path)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_str().unwrap().ends_with(".sst")
+            })
+            .count();
+        assert!(tables <= 8, "{tables} tables left");
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..500 {
+            let (key, _) = kv(i);
+            let value = format!("round-3-{i}");
+            let found = block_on(db.raw_get(key.as_ref())).unwrap().unwrap();
+            assert!(found.as_ref() == value.as_bytes());
+        }
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Leveled compaction keeps the runs below tier 0 disjoint.
+    #[test]
+    fn leveled_compaction_keeps_levels_disjoint() {
+        let path = get_db_path("leveled_compaction_keeps_levels_disjoint");
+        let options = Options {
+            memtable_size: 8 << 10,
+            max_frozen_memtables: 0,
+            compaction_style: CompactionStyle::Leveled,
+            tier_threshold: 2,
+            level_base_size: 16 << 10,
+            level_size_multiplier: 4,
+            target_file_size: 4 << 10,
+            ..Options::default()
+        };
+
+        let db = LsmTree::open(&path, options).unwrap();
+        for round in 0..4 {
+            for i in 0..500 {
+                let (key, _) = kv(i);
+                let value = format!("round-{round}-{i}");
+                block_on(db.raw_insert(key, ByteStream::from_slice(value.as_bytes()))).unwrap();
+            }
+        }
+        block_on(db.close()).unwrap();
+
+        // tables on every tier but the first cover disjoint key ranges
+        let mut tables = Vec::<(SSLoc, Vec<u8>, Vec<u8>)>::new();
+        for entry in std::fs::read_dir(&path).unwrap() {
+            let entry = entry.unwrap();
+            let name = entry.file_name();
+            let Some(loc) = SSLoc::from_file_name(name.to_str().unwrap()) else {
+                continue;
+            };
+            let file = std::fs::File::open(entry.path()).unwrap();
+            let table = SSTableReader::new(file).unwrap();
+            let (min_key, max_key) = table.key_range().unwrap();
+            tables.push((loc, min_key.as_ref().to_vec(), max_key.as_ref().to_vec()));
+        }
+        assert!(tables.iter().any(|(loc, _, _)| loc.tier >= 1));
+        for (i, (loc, min_key, max_key)) in tables.iter().enumerate() {
+            for (other, other_min, other_max) in &tables[i + 1..] {
+                if loc.tier == 0 || loc.tier != other.tier {
+                    continue;
+                }
+                assert!(max_key < other_min || other_max < min_key);
+            }
+        }
+
+        let db = LsmTree::open(&path, Options::default()).unwrap();
+        for i in 0..500 {
+            let (key, _) = kv(i);
+            let value = format!("round-3-{i}");
+            let found = block_on(db.raw_get(key.as_ref())).unwrap().unwrap();
+            assert!(found.as_ref() == value.as_bytes());
+        }
+        block_on(db.close()).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Scans may be held across awaits and moved to other tasks.
+    #[test]
+    fn scans_are_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<SSTableReaderIterator<'static>>();
+        assert_send::<SSTableReaderReverseIterator<'static>>();
+        assert_send::<SSTableReaderCursor<'static>>();
+        assert_send::<ScanIterator<'static>>();
+        assert_send::<ScanCursor<'static>>();
+    }
+}
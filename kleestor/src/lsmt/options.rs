@@ -0,0 +1,115 @@
+use crate::sstable::cache::BlockCache;
+use crate::sstable::compression::Compression;
+use crate::sstable::prefix::PrefixExtractor;
+use crate::sstable::writer::{FilterPolicy, WriterOptions};
+use std::sync::Arc;
+
+/// Strategies to compact on-disk tables with.
+pub enum CompactionStyle {
+    /// Merges whole tiers once they collect enough runs, see
+    /// [`TieredCompaction`](crate::lsmt::compaction::TieredCompaction).
+    Tiered,
+
+    /// Keeps disjoint runs on every level but the first, see
+    /// [`LeveledCompaction`](crate::lsmt::compaction::LeveledCompaction).
+    Leveled,
+}
+
+/// Tunables for opening an [`LsmTree`](crate::lsmt::mgr::LsmTree).
+pub struct Options {
+    /// Creates the database directory if it does not exist yet.
+    pub create_if_missing: bool,
+
+    /// Refuses to open a directory that already contains a database.
+    pub error_if_exists: bool,
+
+    /// Write-ahead log segments roll over after growing past this many bytes.
+    pub wal_segment_size: usize,
+
+    /// Calls `fsync` on the write-ahead log after every write. Without this a
+    /// crash of the operating system may lose the most recent writes.
+    pub wal_sync: bool,
+
+    /// lv0 is frozen into lv1 once it holds about this many bytes.
+    pub memtable_size: usize,
+
+    /// Writes wait for frozen trees to be flushed to disk while more than this
+    /// many of them are pending in lv1.
+    pub max_frozen_memtables: usize,
+
+    /// Trades read amplification for write amplification.
+    pub compaction_style: CompactionStyle,
+
+    /// A tier is compacted into the next one once it holds this many runs.
+    /// With leveled compaction this only applies to tier 0.
+    pub tier_threshold: usize,
+
+    /// Leveled compaction: tier 1 may hold this many bytes, and every further
+    /// tier [`level_size_multiplier`](Self::level_size_multiplier) times the
+    /// size of the one above.
+    pub level_base_size: usize,
+
+    /// Leveled compaction: growth of the size limit from one tier to the next.
+    pub level_size_multiplier: usize,
+
+    /// Leveled compaction: tables are split once they hold about this many
+    /// bytes of keys and values.
+    pub target_file_size: usize,
+
+    /// Codec that data blocks of newly written tables are compressed with.
+    pub compression: Compression,
+
+    /// Layout of the filter of newly written tables.
+    pub filter_policy: FilterPolicy,
+
+    /// Newly written tables get a bloom filter of the key prefixes taken by
+    /// this, which lets [`prefix_scan`](crate::lsmt::mgr::LsmTree::prefix_scan)
+    /// skip them.
+    pub prefix_extractor: Option<PrefixExtractor>,
+
+    /// Bits of the bloom filter of newly written tables spent on each key.
+    pub bloom_bits_per_key: usize,
+
+    /// Cache of decompressed data blocks of all tables, which is shared with
+    /// every database given the same one. Blocks stored uncompressed are read
+    /// from the mapped files and never cached.
+    ///
+    /// The cache shared by the whole process is used unless a cache of
+    /// another capacity is created with [`BlockCache::new`].
+    pub block_cache: Arc<BlockCache>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            wal_segment_size: 16 << 20,
+            wal_sync: false,
+            memtable_size: 4 << 20,
+            max_frozen_memtables: 2,
+            compaction_style: CompactionStyle::Tiered,
+            tier_threshold: 4,
+            level_base_size: 64 << 20,
+            level_size_multiplier: 10,
+            target_file_size: 8 << 20,
+            compression: Compression::Lz4,
+            filter_policy: FilterPolicy::Bloom,
+            prefix_extractor: None,
+            bloom_bits_per_key: 10,
+            block_cache: BlockCache::global(),
+        }
+    }
+}
+
+impl Options {
+    /// Tunables of newly written tables.
+    pub fn writer_options(&self) -> WriterOptions {
+        WriterOptions {
+            compression: self.compression,
+            filter: self.filter_policy,
+            prefix_extractor: self.prefix_extractor,
+            bloom_bits_per_key: self.bloom_bits_per_key,
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use crate::memtable::skiplist::{SkipList, SkipListCursor, SkipListIterator, SkipListPointer};
+use crate::record::{ByteStream, KvDataRef, KvEntry, KvMergeIterator, KvPointer};
+use crate::sstable::reader::{
+    SSTableReader, SSTableReaderCursor, SSTableReaderIterator, SSTableReaderPointer,
+};
+use std::io::Result as IoResult;
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Trees and tables of all levels of an [`LsmTree`], kept alive by a scan or
+/// cursor so that the levels are only locked while taking them.
+///
+/// Trees frozen or flushed and tables compacted away meanwhile stay readable.
+/// Keys inserted into lv0 afterwards may or may not be seen.
+///
+/// [`LsmTree`]: crate::lsmt::mgr::LsmTree
+pub struct Snapshot {
+    /// In-memory trees, ordered from the newest to the oldest.
+    trees: Vec<Arc<SkipList<ByteStream, KvEntry>>>,
+
+    /// SSTables, ordered from the newest to the oldest.
+    tables: Vec<Arc<SSTableReader>>,
+}
+
+impl Snapshot {
+    pub fn new(
+        trees: Vec<Arc<SkipList<ByteStream, KvEntry>>>,
+        tables: Vec<Arc<SSTableReader>>,
+    ) -> Self {
+        Self { trees, tables }
+    }
+
+    /// Accesses the trees and tables, which stay in place when the snapshot
+    /// is moved. The snapshot must outlive the references, e.g. by being
+    /// handed to the scan or cursor reading them.
+    pub unsafe fn contents<'a>(
+        &self,
+    ) -> (
+        Vec<&'a SkipList<ByteStream, KvEntry>>,
+        Vec<&'a SSTableReader>,
+    ) {
+        let trees = self.trees.iter().map(|tree| &*Arc::as_ptr(tree)).collect();
+        let tables = self
+            .tables
+            .iter()
+            .map(|table| &*Arc::as_ptr(table))
+            .collect();
+        (trees, tables)
+    }
+}
+
+/// Sorted source of records taking part in a scan, which is either an
+/// in-memory tree or an SSTable.
+pub enum ScanSource<'a> {
+    Tree(SkipListIterator<ByteStream, KvEntry>),
+    Table(SSTableReaderIterator<'a>),
+}
+
+impl<'a> Iterator for ScanSource<'a> {
+    type Item = ScanPointer<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            ScanSource::Tree(iter) => iter.next().map(ScanPointer::Tree),
+            ScanSource::Table(iter) => iter.next().map(ScanPointer::Table),
+        }
+    }
+}
+
+impl<'a> ScanSource<'a> {
+    /// Fails if the source stopped at a corrupt block of its table. Trees
+    /// never fail.
+    pub fn status(&self) -> IoResult<()> {
+        match self {
+            ScanSource::Tree(_) => Ok(()),
+            ScanSource::Table(iter) => iter.status(),
+        }
+    }
+}
+
+/// Pointer yielded by [`ScanSource`].
+pub enum ScanPointer<'a> {
+    Tree(SkipListPointer<ByteStream, KvEntry>),
+    Table(SSTableReaderPointer<'a>),
+}
+
+impl<'a> KvPointer for ScanPointer<'a> {
+    fn key(&self) -> &[u8] {
+        match self {
+            ScanPointer::Tree(item) => item.key().as_ref(),
+            ScanPointer::Table(item) => item.key(),
+        }
+    }
+
+    fn value(&self) -> KvDataRef {
+        match self {
+            ScanPointer::Tree(item) => item.value(),
+            ScanPointer::Table(item) => item.value(),
+        }
+    }
+}
+
+/// Ordered iterator over a key range of an [`LsmTree`], created by
+/// [`LsmTree::scan`].
+///
+/// Sources are merged from the newest to the oldest, so only the newest
+/// version of every key is seen. Removed keys are skipped. The trees and
+/// tables read are kept in a [`Snapshot`] until the iterator is dropped.
+///
+/// [`LsmTree`]: crate::lsmt::mgr::LsmTree
+/// [`LsmTree::scan`]: crate::lsmt::mgr::LsmTree::scan
+pub struct ScanIterator<'a> {
+    /// Merged sources, every one of which starts at the lower bound.
+    iter: KvMergeIterator<'a, ScanPointer<'a>, ScanSource<'a>>,
+
+    /// Lower bound, which only needs to be checked if excluded.
+    start: Bound<ByteStream>,
+
+    /// Upper bound.
+    end: Bound<ByteStream>,
+
+    /// Number of records left to yield.
+    remaining: usize,
+
+    /// Trees and tables being scanned, dropped after the sources.
+    _snapshot: Snapshot,
+}
+
+impl<'a> ScanIterator<'a> {
+    pub fn new(
+        sources: Vec<ScanSource<'a>>,
+        start: Bound<ByteStream>,
+        end: Bound<ByteStream>,
+        limit: usize,
+        snapshot: Snapshot,
+    ) -> Self {
+        Self {
+            iter: KvMergeIterator::new(sources),
+            start,
+            end,
+            remaining: limit,
+            _snapshot: snapshot,
+        }
+    }
+
+    /// Fails if the scan ended early at a corrupt block of a table, in which
+    /// case records of that table may have been left out.
+    pub fn status(&self) -> IoResult<()> {
+        self.iter
+            .iterators()
+            .iter()
+            .try_for_each(ScanSource::status)
+    }
+}
+
+impl<'a> Iterator for ScanIterator<'a> {
+    type Item = (ByteStream, ByteStream);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.remaining > 0 {
+            let item = self.iter.next()?;
+            let key = item.key();
+            let past_end = match &self.end {
+                Bound::Included(end) => key > end.as_ref(),
+                Bound::Excluded(end) => key >= end.as_ref(),
+                Bound::Unbounded => false,
+            };
+            if past_end {
+                self.remaining = 0;
+                break;
+            }
+            if let Bound::Excluded(start) = &self.start {
+                if key == start.as_ref() {
+                    continue;
+                }
+            }
+            if let KvDataRef::Value { value, .. } = item.value() {
+                self.remaining -= 1;
+                return Some((ByteStream::from_slice(key), ByteStream::from_slice(value)));
+            }
+        }
+        None
+    }
+}
+
+/// In-memory tree or SSTable that a [`ScanCursor`] reads from, which is made
+/// into a [`CursorSource`] whenever the cursor is sought.
+#[derive(Clone, Copy)]
+pub enum ScanTable<'a> {
+    Tree(&'a SkipList<ByteStream, KvEntry>),
+    Table(&'a SSTableReader),
+}
+
+impl<'a> ScanTable<'a> {
+    /// Source at the first key that is not less than `key`, or off the end
+    /// after the last key without one.
+    fn cursor(&self, key: Option<&[u8]>) -> CursorSource<'a> {
+        match *self {
+            ScanTable::Tree(tree) => CursorSource::Tree(tree.cursor(key)),
+            ScanTable::Table(table) => CursorSource::Table(table.cursor(key)),
+        }
+    }
+}
+
+/// Tree or table that a [`ScanCursor`] reads from, positioned at one of its
+/// records or off one end of them.
+enum CursorSource<'a> {
+    Tree(SkipListCursor<ByteStream, KvEntry>),
+    Table(SSTableReaderCursor<'a>),
+}
+
+impl<'a> CursorSource<'a> {
+    /// Key of the record the source is at.
+    fn key(&self) -> Option<&[u8]> {
+        match self {
+            CursorSource::Tree(cursor) => cursor.key().map(|key| key.as_ref()),
+            CursorSource::Table(cursor) => cursor.current().map(|item| item.key()),
+        }
+    }
+
+    /// Points to the record the source is at.
+    fn current(&self) -> Option<ScanPointer<'a>> {
+        match self {
+            CursorSource::Tree(cursor) => cursor.current().map(ScanPointer::Tree),
+            CursorSource::Table(cursor) => cursor.current().cloned().map(ScanPointer::Table),
+        }
+    }
+
+    /// Moves one record in the given direction.
+    fn step(&mut self, backward: bool) {
+        match (self, backward) {
+            (CursorSource::Tree(cursor), false) => cursor.next(),
+            (CursorSource::Tree(cursor), true) => cursor.prev(),
+            (CursorSource::Table(cursor), false) => cursor.next(),
+            (CursorSource::Table(cursor), true) => cursor.prev(),
+        }
+    }
+
+    /// Fails if the source stopped at a corrupt block of its table. Trees
+    /// never fail.
+    fn status(&self) -> IoResult<()> {
+        match self {
+            CursorSource::Tree(_) => Ok(()),
+            CursorSource::Table(cursor) => cursor.status(),
+        }
+    }
+}
+
+/// Cursor moving back and forth over the keys of an [`LsmTree`], created by
+/// [`LsmTree::cursor`]. It starts out invalid until it is moved to a key with
+/// one of the `seek` methods.
+///
+/// Like a [`ScanIterator`], the cursor sees the newest version of every key
+/// and skips removed keys. Every tree and table is read through a source of
+/// its own that moves both ways, which is only positioned anew on seeking.
+/// Sources are few, so the next key is picked by comparing all of them.
+/// The trees and tables read are kept in a [`Snapshot`] until the cursor is
+/// dropped, so that the database may be written meanwhile.
+///
+/// [`LsmTree`]: crate::lsmt::mgr::LsmTree
+/// [`LsmTree::cursor`]: crate::lsmt::mgr::LsmTree::cursor
+pub struct ScanCursor<'a> {
+    /// Trees and tables, ordered from the newest to the oldest.
+    tables: Vec<ScanTable<'a>>,
+
+    /// Sources of the trees and tables, in the same order. Every source is at
+    /// its first record past the current key in the direction the cursor last
+    /// moved in, or off the end in that direction.
+    sources: Vec<CursorSource<'a>>,
+
+    /// Whether the cursor last moved backwards.
+    backward: bool,
+
+    /// Key and value the cursor is at.
+    current: Option<(ByteStream, ByteStream)>,
+
+    /// Trees and tables being read, dropped after the sources.
+    _snapshot: Snapshot,
+}
+
+impl<'a> ScanCursor<'a> {
+    pub fn new(tables: Vec<ScanTable<'a>>, snapshot: Snapshot) -> Self {
+        Self {
+            tables,
+            sources: Vec::new(),
+            backward: false,
+            current: None,
+            _snapshot: snapshot,
+        }
+    }
+
+    /// Tells if the cursor is at a key. Moving past either end of the keys
+    /// invalidates the cursor.
+    pub fn valid(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Key the cursor is at.
+    pub fn key(&self) -> Option<&[u8]> {
+        self.current.as_ref().map(|(key, _)| key.as_ref())
+    }
+
+    /// Value of the key the cursor is at.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.current.as_ref().map(|(_, value)| value.as_ref())
+    }
+
+    /// Fails if the cursor ran into a corrupt block of a table since it was
+    /// last sought, in which case it may have skipped records of that table.
+    pub fn status(&self) -> IoResult<()> {
+        self.sources.iter().try_for_each(CursorSource::status)
+    }
+
+    /// Moves to the first key that is not less than `key`.
+    pub fn seek(&mut self, key: &[u8]) {
+        self.reposition(Some(key));
+        self.backward = false;
+        self.advance();
+    }
+
+    /// Moves to the first key.
+    pub fn seek_to_first(&mut self) {
+        self.seek(&[]);
+    }
+
+    /// Moves to the last key.
+    pub fn seek_to_last(&mut self) {
+        self.reposition(None);
+        for source in &mut self.sources {
+            source.step(true);
+        }
+        self.backward = true;
+        self.advance();
+    }
+
+    /// Moves to the next key. Nothing happens to an invalid cursor.
+    pub fn next(&mut self) {
+        self.step(false);
+    }
+
+    /// Moves to the previous key. Nothing happens to an invalid cursor.
+    pub fn prev(&mut self) {
+        self.step(true);
+    }
+
+    /// Positions all sources at the first key that is not less than `key`,
+    /// or off the end after the last key without one.
+    fn reposition(&mut self, key: Option<&[u8]>) {
+        self.sources = self.tables.iter().map(|table| table.cursor(key)).collect();
+    }
+
+    /// Moves one key in the given direction, turning the sources around at
+    /// the current key if the cursor last moved the other way.
+    fn step(&mut self, backward: bool) {
+        let key = match &self.current {
+            Some((key, _)) => key,
+            None => return,
+        };
+        if backward != self.backward {
+            // sources off the end come back to their last record, and those
+            // not past the current key are moved beyond it
+            for source in &mut self.sources {
+                if source.key().is_none() {
+                    source.step(backward);
+                }
+                while let Some(found) = source.key() {
+                    match backward {
+                        false if found > key.as_ref() => break,
+                        true if found < key.as_ref() => break,
+                        _ => source.step(backward),
+                    }
+                }
+            }
+            self.backward = backward;
+        }
+        self.advance();
+    }
+
+    /// Moves to the next record from the sources that is not removed, in the
+    /// direction the cursor last moved in. The newest source wins among those
+    /// at the same key, and all of them move past it.
+    fn advance(&mut self) {
+        self.current = None;
+        loop {
+            let mut next: Option<usize> = None;
+            for (index, source) in self.sources.iter().enumerate() {
+                let key = match source.key() {
+                    Some(key) => key,
+                    None => continue,
+                };
+                let closer = match next.and_then(|next| self.sources[next].key()) {
+                    Some(best) if self.backward => key > best,
+                    Some(best) => key < best,
+                    None => true,
+                };
+                if closer {
+                    next = Some(index);
+                }
+            }
+            let item = match next.and_then(|next| self.sources[next].current()) {
+                Some(item) => item,
+                None => return,
+            };
+            let key = ByteStream::from_slice(item.key());
+            let value = match item.value() {
+                KvDataRef::Value { value, .. } => Some(ByteStream::from_slice(value)),
+                KvDataRef::Tombstone { .. } => None,
+            };
+            let backward = self.backward;
+            for source in &mut self.sources {
+                if source.key() == Some(key.as_ref()) {
+                    source.step(backward);
+                }
+            }
+            if let Some(value) = value {
+                self.current = Some((key, value));
+                return;
+            }
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use crate::record::{ByteStream, KvData, KvEntry};
-use crate::utils::const_as_mut;
 use crate::utils::futures::{Mutex, Notify};
 use std::cmp::max;
 use std::collections::BTreeMap;
@@ -39,6 +38,10 @@ pub struct Transaction {
     /// The redo log must be reverted in reverse order.
     pub redo: Vec<(*mut KvEntry, u64, Option<KvData>)>,
 
+    /// Keys and values written by this transaction, which are appended to the
+    /// write-ahead log upon commit.
+    pub writes: Vec<(ByteStream, KvData)>,
+
     /// Transaction dependencies.
     pub deps: Vec<u64>,
 
@@ -69,12 +72,31 @@ pub enum TransactionState {
 }
 
 impl TransactionMgrImpl {
+    /// Creates a manager with no ongoing transactions.
+    pub fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+            ongoing_trans: BTreeMap::new(),
+        }
+    }
+
+    /// Timestamps of the transactions still registered in the manager.
+    pub fn ongoing(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ongoing_trans.keys().copied()
+    }
+
+    /// Whether the transaction created at `ts` is still registered.
+    pub fn is_ongoing(&self, ts: u64) -> bool {
+        self.ongoing_trans.contains_key(&ts)
+    }
+
     /// Creates a transaction.
     pub async unsafe fn create(&mut self, ts: u64) -> &mut Transaction {
         let mut trans = Box::from(Transaction {
             ts: ts,
             lock: Mutex::new(()),
             redo: Vec::new(),
+            writes: Vec::new(),
             deps: Vec::new(),
             state: TransactionState::Idle,
             await_finish: Notify::new(),
@@ -223,9 +245,12 @@ impl TransactionMgrImpl {
             let _lock_m = self.lock.lock().await;
 
             for dep_id in &trans.deps {
-                if let Some(dep) = self.ongoing_trans.get(&dep_id) {
-                    let dep = const_as_mut(dep.as_ref());
+                if let Some(dep) = self.ongoing_trans.get_mut(dep_id) {
                     dep.await_clients.push(trans.ts);
+                }
+            }
+            for dep_id in &trans.deps {
+                if let Some(dep) = self.ongoing_trans.get(dep_id) {
                     notifiers.push(dep.await_finish.notified());
                 }
             }
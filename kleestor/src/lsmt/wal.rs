@@ -0,0 +1,387 @@
+use crate::record::{ByteStream, KvData};
+use crate::utils::crc32c::Crc32c;
+use crate::utils::varint::VarUint64;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+/// Appends framed records to a log file.
+///
+/// Every record is laid out as follows, so that a torn write at the end of a
+/// log can be told apart from corrupted data:
+///
+///     [payload length: varuint64] [crc32c of payload: u32] [payload]
+pub struct LogWriter {
+    /// File handle to append to.
+    handle: File,
+
+    /// Bytes written to the log so far.
+    size: usize,
+
+    /// Calls `fsync` after every record.
+    sync: bool,
+}
+
+impl LogWriter {
+    /// Creates an empty log at `path`, failing if the file already exists.
+    pub fn create(path: &Path, sync: bool) -> Result<Self> {
+        let handle = OpenOptions::new().write(true).create_new(true).open(path)?;
+        Ok(Self {
+            handle,
+            size: 0,
+            sync,
+        })
+    }
+
+    /// Appends one record to the log.
+    pub fn append(&mut self, payload: &[u8]) -> Result<()> {
+        let mut buffer = Vec::<u8>::with_capacity(payload.len() + 13);
+        buffer.resize(9, 0_u8);
+        let len = VarUint64::as_slice(payload.len() as u64, &mut buffer);
+        buffer.truncate(len);
+        buffer.extend_from_slice(&Crc32c::checksum(payload).to_le_bytes());
+        buffer.extend_from_slice(payload);
+
+        self.handle.write_all(&buffer)?;
+        if self.sync {
+            self.handle.sync_data()?;
+        }
+        self.size += buffer.len();
+        Ok(())
+    }
+
+    /// Forcefully persists all appended records.
+    pub fn sync(&mut self) -> Result<()> {
+        self.handle.sync_data()
+    }
+
+    /// Bytes written to the log so far.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Reads records written by [`LogWriter`] in order.
+pub struct LogReader {
+    /// Entire content of the log.
+    data: Vec<u8>,
+
+    /// Offset of the next record.
+    offset: usize,
+
+    /// Whether the log was closed by its writer, so that it cannot end with a
+    /// torn record.
+    sealed: bool,
+}
+
+impl LogReader {
+    /// Opens a log that may have been interrupted in the middle of a write.
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            data: fs::read(path)?,
+            offset: 0,
+            sealed: false,
+        })
+    }
+
+    /// Opens a log that was closed by its writer, where every record must be
+    /// intact.
+    pub fn open_sealed(path: &Path) -> Result<Self> {
+        Ok(Self {
+            sealed: true,
+            ..Self::open(path)?
+        })
+    }
+
+    /// Reads the next record. `None` is returned at the end of the log, which
+    /// includes a trailing record that was only partially written.
+    ///
+    /// A crash may leave the last record torn, followed by zeros or stale
+    /// bytes, so a record that is empty or fails its checksum ends the log as
+    /// long as no intact record follows it. Otherwise the log is corrupted,
+    /// which is reported as an error. Sealed logs have no torn records, so
+    /// any broken record in them is reported as well.
+    pub fn next(&mut self) -> Result<Option<&[u8]>> {
+        if self.offset == self.data.len() {
+            return Ok(None);
+        }
+        let (begin, end, intact) = match self.frame(self.offset) {
+            Some(frame) => frame,
+            None if self.sealed => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "log record runs past the end",
+                ))
+            }
+            None => return Ok(None),
+        };
+        if !intact {
+            if self.sealed || matches!(self.frame(end), Some((_, _, true))) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "log record checksum mismatch",
+                ));
+            }
+            return Ok(None);
+        }
+        self.offset = end;
+        Ok(Some(&self.data[begin..end]))
+    }
+
+    /// Length of the log up to the end of the last record read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Total length of the log, including a torn record at its end.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Locates the payload of the record at `offset`, and tells if it is
+    /// intact. `None` is returned for a record running past the end.
+    fn frame(&self, offset: usize) -> Option<(usize, usize, bool)> {
+        let rest = &self.data[offset..];
+        let (len_size, len) = VarUint64::read_offset(rest, rest.len()).ok()?;
+        let begin = offset.checked_add(len_size + 4)?;
+        let end = usize::try_from(len).ok()?.checked_add(begin)?;
+        if end > self.data.len() {
+            return None;
+        }
+        let checksum = u32::from_le_bytes(self.data[begin - 4..begin].try_into().unwrap());
+        let intact = end > begin && Crc32c::checksum(&self.data[begin..end]) == checksum;
+        Some((begin, end, intact))
+    }
+}
+
+/// Write-ahead log of the level 0 memtable, split into numbered segments.
+///
+/// Each record holds a batch of writes sharing one write timestamp (`0` for
+/// writes made outside transactions). Segments are laid out as:
+///
+///     <dir>/{seq}.log
+///
+/// A segment is never appended to after the log is reopened, so a torn record
+/// can only ever appear at the tail of the last segment, and is cut off once
+/// that segment is replayed. Broken records anywhere else are corruption.
+///
+/// Every memtable owns the segments from the one it started in up to the one
+/// the next memtable started in. Once a memtable is persisted its segments
+/// are released.
+pub struct Wal {
+    /// Directory holding all segments.
+    dir: PathBuf,
+
+    /// First segment of each memtable not yet persisted, from the oldest to
+    /// the newest. The last one belongs to lv0.
+    memtables: VecDeque<u64>,
+
+    /// Segment currently being appended to.
+    writer: LogWriter,
+
+    /// Sequence number of the active segment.
+    seq: u64,
+
+    /// Segments roll over once they grow past this many bytes.
+    segment_size: usize,
+
+    /// Calls `fsync` after every record.
+    sync: bool,
+}
+
+impl Wal {
+    /// Opens the log under `dir`, feeding every logged write to `apply` in the
+    /// order they were made, and starts a new segment for upcoming writes.
+    pub fn open<F>(dir: &Path, segment_size: usize, sync: bool, mut apply: F) -> Result<Self>
+    where
+        F: FnMut(u64, ByteStream, KvData),
+    {
+        fs::create_dir_all(dir)?;
+        let segments = Self::list_segments(dir)?;
+
+        for (index, seq) in segments.iter().enumerate() {
+            // only the segment written last may have been interrupted
+            let path = Self::segment_path(dir, *seq);
+            let mut reader = match index + 1 == segments.len() {
+                true => LogReader::open(&path)?,
+                false => LogReader::open_sealed(&path)?,
+            };
+            while let Some(payload) = reader.next()? {
+                let (ts, records) = Self::decode(payload)?;
+                for (key, record) in records {
+                    apply(ts, key, record);
+                }
+            }
+            // cut off the torn record, as the segment is sealed from now on
+            if reader.offset() < reader.size() {
+                let file = OpenOptions::new().write(true).open(&path)?;
+                file.set_len(reader.offset() as u64)?;
+                file.sync_data()?;
+            }
+        }
+
+        let seq = match segments.last() {
+            Some(last) => last + 1,
+            None => 0,
+        };
+        // everything replayed belongs to lv0
+        let memtables = VecDeque::from([*segments.first().unwrap_or(&seq)]);
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            memtables,
+            writer: Self::create_segment(dir, seq, sync)?,
+            seq,
+            segment_size,
+            sync,
+        })
+    }
+
+    /// Logs a batch of writes made with the write timestamp `ts`. The batch is
+    /// stored as a single record and is therefore replayed all or nothing.
+    pub fn append(&mut self, ts: u64, records: &[(&[u8], &KvData)]) -> Result<()> {
+        if self.writer.size() >= self.segment_size {
+            self.roll()?;
+        }
+        self.writer.append(&Self::encode(ts, records))
+    }
+
+    /// Starts logging into a new segment after lv0 got frozen, so that the
+    /// segments of the frozen tree can be released on their own.
+    pub fn seal(&mut self) -> Result<()> {
+        let seq = self.roll()?;
+        self.memtables.push_back(seq);
+        Ok(())
+    }
+
+    /// Releases the segments of the oldest memtable after it was persisted.
+    pub fn release(&mut self) -> Result<()> {
+        if self.memtables.len() > 1 {
+            self.memtables.pop_front();
+        }
+        let first = *self.memtables.front().unwrap();
+        self.remove_before(first)
+    }
+
+    /// Closes the active segment and starts a new one, returning the sequence
+    /// number of the new segment.
+    fn roll(&mut self) -> Result<u64> {
+        self.writer.sync()?;
+        let seq = self.seq + 1;
+        self.writer = Self::create_segment(&self.dir, seq, self.sync)?;
+        self.seq = seq;
+        Ok(seq)
+    }
+
+    /// Creates the segment `seq`. Its name is synced into the directory if
+    /// records are synced as well, so that synced records never sit in a
+    /// segment that a crash may lose.
+    fn create_segment(dir: &Path, seq: u64, sync: bool) -> Result<LogWriter> {
+        let writer = LogWriter::create(&Self::segment_path(dir, seq), sync)?;
+        if sync {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(writer)
+    }
+
+    /// Deletes every segment preceding `seq`. Segments created since are
+    /// synced into the directory first, so that no crash leaves the log
+    /// without them.
+    fn remove_before(&mut self, seq: u64) -> Result<()> {
+        File::open(&self.dir)?.sync_all()?;
+        for old in Self::list_segments(&self.dir)? {
+            if old < seq && old != self.seq {
+                fs::remove_file(Self::segment_path(&self.dir, old))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+        dir.join(format!("{seq:016}.log"))
+    }
+
+    /// Lists sequence numbers of all segments in ascending order.
+    fn list_segments(dir: &Path) -> Result<Vec<u64>> {
+        let mut segments = Vec::<u64>::new();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let seq = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".log"))
+                .and_then(|stem| stem.parse::<u64>().ok());
+            if let Some(seq) = seq {
+                segments.push(seq);
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// Serializes a batch as:
+    ///
+    ///     [ts] [count] ([flag] [key length] [key] [value length] [value])...
+    ///
+    /// where every number is a varuint64 and flags follow the SSTable
+    /// convention (`0` for values and `1` for tombstones).
+    fn encode(ts: u64, records: &[(&[u8], &KvData)]) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        let mut scratch = [0_u8; 9];
+        let mut push_varu64 = |buffer: &mut Vec<u8>, value: u64| {
+            let len = VarUint64::as_slice(value, &mut scratch);
+            buffer.extend_from_slice(&scratch[0..len]);
+        };
+
+        push_varu64(&mut buffer, ts);
+        push_varu64(&mut buffer, records.len() as u64);
+        for (key, record) in records {
+            let (flag, value): (u64, &[u8]) = match record {
+                KvData::Tombstone { .. } => (0b00000001, &[]),
+                KvData::Value { value, .. } => (0b00000000, value.as_ref()),
+            };
+            push_varu64(&mut buffer, flag);
+            push_varu64(&mut buffer, key.len() as u64);
+            buffer.extend_from_slice(key);
+            push_varu64(&mut buffer, value.len() as u64);
+            buffer.extend_from_slice(value);
+        }
+        buffer
+    }
+
+    /// Inverse of [`Wal::encode`].
+    fn decode(payload: &[u8]) -> Result<(u64, Vec<(ByteStream, KvData)>)> {
+        let mut offset = 0_usize;
+        let read_varu64 = |offset: &mut usize| {
+            VarUint64::read_and_seek(&payload[*offset..], offset, payload.len() - *offset)
+        };
+        let read_slice = |offset: &mut usize, len: u64| {
+            let len = len as usize;
+            if payload.len() - *offset < len {
+                return Err(Error::new(ErrorKind::InvalidData, "out of bounds"));
+            }
+            *offset += len;
+            Ok(&payload[*offset - len..*offset])
+        };
+
+        let ts = read_varu64(&mut offset)?;
+        let count = read_varu64(&mut offset)?;
+        let mut records = Vec::<(ByteStream, KvData)>::new();
+        for _ in 0..count {
+            let flag = read_varu64(&mut offset)?;
+            let key_len = read_varu64(&mut offset)?;
+            let key = ByteStream::from_slice(read_slice(&mut offset, key_len)?);
+            let value_len = read_varu64(&mut offset)?;
+            let value = read_slice(&mut offset, value_len)?;
+            let record = match flag {
+                0b00000000 => KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(value),
+                },
+                0b00000001 => KvData::Tombstone { cached: false },
+                _ => return Err(Error::new(ErrorKind::InvalidData, "invalid log flag")),
+            };
+            records.push((key, record));
+        }
+        Ok((ts, records))
+    }
+}
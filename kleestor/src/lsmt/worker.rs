@@ -0,0 +1,219 @@
+use crate::lsmt::compaction::{CompactionIterator, CompactionTask, SizeLimitIterator};
+use crate::lsmt::mgr::SSLoc;
+use crate::memtable::skiplist::{SkipList, SkipListPointer};
+use crate::record::{ByteStream, KvDataRef, KvEntry, KvMergeIterator, KvPointer};
+use crate::sstable::cache::BlockCache;
+use crate::sstable::reader::SSTableReader;
+use crate::sstable::writer::{SSTableWriter, WriterOptions};
+use std::fs::{self, File};
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Work handed to the background thread.
+pub enum Job {
+    /// Writes a frozen tree into a new table at `loc`.
+    Flush {
+        loc: SSLoc,
+        tree: Arc<SkipList<ByteStream, KvEntry>>,
+    },
+
+    /// Merges `inputs`, ordered from the newest to the oldest, into new runs.
+    Compact {
+        task: CompactionTask,
+        inputs: Vec<Arc<SSTableReader>>,
+    },
+}
+
+/// Result of a [`Job`], which is installed by the owner of the database.
+pub enum Outcome {
+    /// The table is `None` if the tree held nothing to persist.
+    Flushed {
+        loc: SSLoc,
+        table: IoResult<Option<Arc<SSTableReader>>>,
+    },
+    Compacted {
+        task: CompactionTask,
+        outputs: IoResult<Vec<(SSLoc, SSTableReader)>>,
+    },
+}
+
+/// A thread writing tables off the write path. Jobs are run one at a time in
+/// the order they were submitted, so frozen trees are flushed from the oldest
+/// to the newest.
+///
+/// Tables written by the worker only become part of the database once their
+/// [`Outcome`] is installed. Dropping the worker waits for the job at hand
+/// and discards the rest.
+pub struct Worker {
+    jobs: Option<UnboundedSender<Job>>,
+    outcomes: UnboundedReceiver<Outcome>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Starts a worker writing tables under `path`. Run numbers of new tables
+    /// are taken from `next_run`, and `finished` is set whenever a job is
+    /// finished.
+    pub fn spawn(
+        path: PathBuf,
+        options: WriterOptions,
+        cache: Arc<BlockCache>,
+        next_run: Arc<AtomicU32>,
+        finished: Arc<AtomicBool>,
+    ) -> Self {
+        let (jobs, mut pending) = mpsc::unbounded_channel::<Job>();
+        let (done, outcomes) = mpsc::unbounded_channel::<Outcome>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = thread::spawn(move || {
+            while let Some(job) = pending.blocking_recv() {
+                if stopped.load(Ordering::Acquire) {
+                    break;
+                }
+                let outcome = match job {
+                    Job::Flush { loc, tree } => Outcome::Flushed {
+                        loc,
+                        table: flush(&path, &loc, options, cache.clone(), &tree),
+                    },
+                    Job::Compact { task, inputs } => Outcome::Compacted {
+                        outputs: compact(&path, &task, &inputs, options, &cache, &next_run),
+                        task,
+                    },
+                };
+                if done.send(outcome).is_err() {
+                    break;
+                }
+                finished.store(true, Ordering::Release);
+            }
+        });
+        Self {
+            jobs: Some(jobs),
+            outcomes,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Queues a job behind those already submitted.
+    pub fn submit(&self, job: Job) {
+        if let Some(jobs) = &self.jobs {
+            // the thread only quits once the worker is dropped
+            let _ = jobs.send(job);
+        }
+    }
+
+    /// Takes the outcome of a finished job, if any.
+    pub fn try_finished(&mut self) -> Option<Outcome> {
+        self.outcomes.try_recv().ok()
+    }
+
+    /// Waits for the next job to finish.
+    pub async fn finished(&mut self) -> Option<Outcome> {
+        self.outcomes.recv().await
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        self.jobs = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Writes a frozen tree into the table at `loc`. Records cached from older
+/// levels are not persisted, so a tree holding nothing else leaves no table
+/// behind, rather than one without any key range.
+fn flush(
+    path: &Path,
+    loc: &SSLoc,
+    options: WriterOptions,
+    cache: Arc<BlockCache>,
+    tree: &SkipList<ByteStream, KvEntry>,
+) -> IoResult<Option<Arc<SSTableReader>>> {
+    let persisted = |item: &SkipListPointer<ByteStream, KvEntry>| match item.value() {
+        KvDataRef::Tombstone { cached, .. } | KvDataRef::Value { cached, .. } => !cached,
+    };
+    if !tree.iter_ref().any(|item| persisted(&item)) {
+        return Ok(None);
+    }
+    let table = write_table(path, loc, options, cache, tree.iter_ref())?;
+    Ok(Some(Arc::new(table)))
+}
+
+/// Merges the input tables of a compaction into runs of at most the task's
+/// output size. Nothing is written if everything merged was deleted, and
+/// nothing is kept if any input turns out to be corrupt.
+fn compact(
+    path: &Path,
+    task: &CompactionTask,
+    inputs: &[Arc<SSTableReader>],
+    options: WriterOptions,
+    cache: &Arc<BlockCache>,
+    next_run: &AtomicU32,
+) -> IoResult<Vec<(SSLoc, SSTableReader)>> {
+    let mut iters: Vec<_> = inputs.iter().map(|table| table.iter()).collect();
+    let merged = KvMergeIterator::new(iters.iter_mut().collect());
+    let mut records = CompactionIterator::new(merged, task.drop_tombstones).peekable();
+    let mut outputs = Vec::<(SSLoc, SSTableReader)>::new();
+    let written = loop {
+        if records.peek().is_none() {
+            break Ok(());
+        }
+        let loc = SSLoc {
+            tier: task.output_tier,
+            run: next_run.fetch_add(1, Ordering::AcqRel),
+        };
+        let chunk = SizeLimitIterator::new(records.by_ref(), task.max_output_size);
+        match write_table(path, &loc, options, cache.clone(), chunk) {
+            Ok(table) => outputs.push((loc, table)),
+            Err(err) => break Err(err),
+        }
+    };
+    drop(records);
+
+    // tables written from partially read inputs would lose records
+    if let Err(err) = written.and_then(|()| iters.iter().try_for_each(|iter| iter.status())) {
+        for (loc, table) in outputs {
+            drop(table);
+            let _ = fs::remove_file(path.join(loc.file_name()));
+        }
+        return Err(err);
+    }
+    Ok(outputs)
+}
+
+/// Writes a sorted stream of records to the table file at `loc`. The file
+/// only appears under its final name after it has been fully synced, and the
+/// name itself is synced before returning, so that the table survives a crash
+/// once it is logged by the manifest.
+fn write_table<Pointer, Iter>(
+    path: &Path,
+    loc: &SSLoc,
+    options: WriterOptions,
+    cache: Arc<BlockCache>,
+    iter: Iter,
+) -> IoResult<SSTableReader>
+where
+    Pointer: KvPointer,
+    Iter: Iterator<Item = Pointer>,
+{
+    let file_path = path.join(loc.file_name());
+    let tmp_path = path.join(format!("{}.tmp", loc.file_name()));
+
+    let mut writer = SSTableWriter::with_options(File::create(&tmp_path)?, options);
+    writer.write(iter)?;
+    drop(writer);
+    File::open(&tmp_path)?.sync_all()?;
+    fs::rename(&tmp_path, &file_path)?;
+    File::open(path)?.sync_all()?;
+
+    SSTableReader::with_cache(File::open(&file_path)?, cache)
+}
@@ -0,0 +1,177 @@
+use std::alloc::{alloc, dealloc, Layout};
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Usable size of a regular block.
+const BLOCK_SIZE: usize = 4096;
+
+/// Alignment of every block, which is also the granularity of allocations.
+const BLOCK_ALIGN: usize = 8;
+
+/// A bump allocator handing out memory from large blocks. Memory is never
+/// reused, and all blocks are released at once when the arena is dropped.
+///
+/// Allocations may be made from multiple threads at once. Most of them only
+/// bump the offset of the current block atomically, while starting a new
+/// block takes a lock. Allocations larger than a quarter of a block are given
+/// blocks of their own, so that little space is wasted at block ends.
+pub struct Arena {
+    /// Block being allocated from, which is null before the first allocation.
+    current: AtomicPtr<Block>,
+
+    /// Every block along with its layout.
+    blocks: Mutex<Vec<(*mut u8, Layout)>>,
+
+    /// Total bytes of all blocks.
+    usage: AtomicUsize,
+}
+
+unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
+
+/// Header at the start of every block, followed by the usable bytes.
+struct Block {
+    /// Offset of the next free byte, which may overshoot once the block is
+    /// used up.
+    used: AtomicUsize,
+}
+
+impl Block {
+    /// Gets the first usable byte of a block.
+    unsafe fn data(ptr: *mut Self) -> *mut u8 {
+        (ptr as *mut u8).add(mem::size_of::<Self>())
+    }
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self {
+            current: AtomicPtr::new(ptr::null_mut()),
+            blocks: Mutex::new(Vec::new()),
+            usage: AtomicUsize::new(0),
+        }
+    }
+
+    /// Total bytes allocated from the system, including block headers and
+    /// space left unused at the end of blocks.
+    pub fn memory_usage(&self) -> usize {
+        self.usage.load(Ordering::Relaxed)
+    }
+
+    /// Allocates uninitialized memory that stays valid until the arena is
+    /// dropped.
+    pub fn alloc(&self, layout: Layout) -> *mut u8 {
+        let size = (layout.size() + BLOCK_ALIGN - 1) & !(BLOCK_ALIGN - 1);
+        let reserve = match layout.align() > BLOCK_ALIGN {
+            true => size + layout.align(),
+            false => size,
+        };
+        let align_up = |p: *mut u8| unsafe { p.add(p.align_offset(layout.align())) };
+
+        if reserve > BLOCK_SIZE / 4 {
+            let mut blocks = self.blocks.lock().unwrap();
+            return align_up(unsafe { Block::data(self.new_block(&mut blocks, reserve)) });
+        }
+        loop {
+            let block = self.current.load(Ordering::Acquire);
+            if !block.is_null() {
+                let offset = unsafe { (*block).used.fetch_add(reserve, Ordering::Relaxed) };
+                if offset + reserve <= BLOCK_SIZE {
+                    return align_up(unsafe { Block::data(block).add(offset) });
+                }
+            }
+            // the block is used up, unless another thread replaced it already
+            let mut blocks = self.blocks.lock().unwrap();
+            if self.current.load(Ordering::Acquire) == block {
+                let block = self.new_block(&mut blocks, BLOCK_SIZE);
+                self.current.store(block, Ordering::Release);
+            }
+        }
+    }
+
+    /// Copies bytes into the arena.
+    pub fn copy_bytes(&self, bytes: &[u8]) -> &[u8] {
+        if bytes.is_empty() {
+            return &[];
+        }
+        let layout = Layout::array::<u8>(bytes.len()).unwrap();
+        unsafe {
+            let ptr = self.alloc(layout);
+            ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+            slice::from_raw_parts(ptr, bytes.len())
+        }
+    }
+
+    /// Allocates a block with `size` usable bytes.
+    fn new_block(&self, blocks: &mut Vec<(*mut u8, Layout)>, size: usize) -> *mut Block {
+        let layout = Layout::from_size_align(mem::size_of::<Block>() + size, BLOCK_ALIGN).unwrap();
+        unsafe {
+            let ptr = alloc(layout);
+            (ptr as *mut Block).write(Block {
+                used: AtomicUsize::new(0),
+            });
+            blocks.push((ptr, layout));
+            self.usage.fetch_add(layout.size(), Ordering::Relaxed);
+            ptr as *mut Block
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        for (ptr, layout) in self.blocks.get_mut().unwrap().drain(..) {
+            unsafe { dealloc(ptr, layout) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arena, BLOCK_SIZE};
+    use std::alloc::Layout;
+
+    #[test]
+    fn concurrent_allocs() {
+        let arena = Arena::new();
+        let threads = 8_usize;
+        let scale = 10000_usize;
+
+        let chunks: Vec<Vec<&[u8]>> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let arena = &arena;
+                    s.spawn(move || {
+                        (0..scale)
+                            .map(|i| arena.copy_bytes(&vec![t as u8; i % 37 + 1]))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        // no chunk got overwritten by another thread
+        for (t, chunks) in chunks.iter().enumerate() {
+            for (i, chunk) in chunks.iter().enumerate() {
+                assert_eq!(*chunk, &vec![t as u8; i % 37 + 1][..]);
+            }
+        }
+
+        let used: usize = (0..scale).map(|i| (i % 37 + 1).next_multiple_of(8)).sum();
+        assert!(arena.memory_usage() >= used * threads);
+        assert!(arena.memory_usage() < used * threads * 2);
+    }
+
+    #[test]
+    fn aligned_allocs() {
+        let arena = Arena::new();
+        for align in [1, 2, 8, 16, 64] {
+            for size in [1, 13, 64, BLOCK_SIZE] {
+                let ptr = arena.alloc(Layout::from_size_align(size, align).unwrap());
+                assert_eq!(ptr as usize % align, 0);
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::memtable::MemTable;
+use crate::memtable::{MemTable, MemTableIterator, MemTablePointer};
 use std::mem;
 use std::mem::MaybeUninit;
 use std::ptr;
@@ -37,8 +37,47 @@ where
         unsafe { self.insert_wrap(key, value) }
     }
 
-    fn remove(&mut self, _key: &K) -> Result<(), ()> {
-        Err(())
+    fn remove(&mut self, key: &K) -> Result<(), ()> {
+        unsafe { self.remove_wrap(key) }
+    }
+
+    fn approximate_memory_usage(&self) -> usize {
+        // nodes are counted by walking the tree
+        let mut nodes = 0_usize;
+        let mut stack: Vec<&Node<K, V, N>> = self.root.iter().map(|p| p.as_ref()).collect();
+        while let Some(p) = stack.pop() {
+            nodes += 1;
+            for child in p.children[..=p.count as usize].iter().flatten() {
+                stack.push(child);
+            }
+        }
+        nodes * mem::size_of::<Node<K, V, N>>()
+    }
+
+    fn iter(&mut self) -> MemTableIterator<'_, K, V> {
+        let mut iter = BTreeIterator { stack: Vec::new() };
+        unsafe { iter.descend(Node::as_ptr(&mut self.root)) };
+        Box::new(iter)
+    }
+
+    fn lower_bound(&mut self, key: &K) -> MemTableIterator<'_, K, V> {
+        let mut iter = BTreeIterator { stack: Vec::new() };
+        let mut p = Node::as_ptr(&mut self.root);
+        unsafe {
+            while !p.is_null() {
+                let count = (*p).count as usize;
+                let mut idx = 0;
+                while idx < count && (*p).keys[idx].assume_init_ref() < key {
+                    idx += 1;
+                }
+                iter.stack.push((p, idx));
+                if idx < count && (*p).keys[idx].assume_init_ref() == key {
+                    break;
+                }
+                p = Node::as_ptr(&mut (*p).children[idx]);
+            }
+        }
+        Box::new(iter)
     }
 }
 
@@ -77,6 +116,14 @@ where
         Self::init(p.as_mut_ptr());
         p.assume_init()
     }
+
+    /// Gets a raw pointer to an optional node, which is null if absent.
+    fn as_ptr(node: &mut Option<Box<Self>>) -> *mut Self {
+        match node {
+            Some(node) => node.as_mut(),
+            None => ptr::null_mut(),
+        }
+    }
 }
 
 impl<K: Ord + Eq, V, const N: usize> Drop for Node<K, V, N>
@@ -366,6 +413,162 @@ where
         }
     }
 
+    /// Remove key-value pair.
+    unsafe fn remove_wrap(&mut self, key: &K) -> Result<(), ()> {
+        let root = self.root.as_mut().unwrap();
+        let removed = Self::remove_r(root, key);
+
+        // the root ran out of keys and its only child takes over
+        if root.count == 0 {
+            if let Some(child) = mem::take(&mut root.children[0]) {
+                self.root = Some(child);
+            }
+        }
+        match removed {
+            Some(_) => Ok(()),
+            None => Err(()),
+        }
+    }
+
+    /// Remove recursively a key from a given node, returning the removed
+    /// key-value pair. Children left with too few keys are refilled from
+    /// their siblings or merged on the way while backtracing.
+    unsafe fn remove_r(p: &mut Node<K, V, N>, key: &K) -> Option<(K, V)> {
+        // find the first separator that is not less than key
+        let count = p.count as usize;
+        let mut idx: usize = 0;
+        while idx < count && p.keys[idx].assume_init_ref() < key {
+            idx += 1;
+        }
+        let found = idx < count && p.keys[idx].assume_init_ref() == key;
+
+        let removed = match (found, &mut p.children[idx]) {
+            // take the pair away from leaf
+            (true, None) => return Some(Self::take_pair(p, idx)),
+            (false, None) => return None,
+            // replace the pair with its predecessor
+            (true, Some(child)) => {
+                let (k, v) = Self::remove_max(child);
+                let k = mem::replace(p.keys[idx].assume_init_mut(), k);
+                let v = mem::replace(p.values[idx].assume_init_mut(), v);
+                (k, v)
+            }
+            (false, Some(child)) => Self::remove_r(child, key)?,
+        };
+        Self::refill(p, idx);
+        Some(removed)
+    }
+
+    /// Remove the largest key-value pair under a given node.
+    unsafe fn remove_max(p: &mut Node<K, V, N>) -> (K, V) {
+        let count = p.count as usize;
+        match &mut p.children[count] {
+            None => Self::take_pair(p, count - 1),
+            Some(child) => {
+                let removed = Self::remove_max(child);
+                Self::refill(p, count);
+                removed
+            }
+        }
+    }
+
+    /// Take the `idx`-th key-value pair away from a leaf.
+    unsafe fn take_pair(p: &mut Node<K, V, N>, idx: usize) -> (K, V) {
+        let count = p.count as usize;
+        let key = p.keys[idx].assume_init_read();
+        let value = p.values[idx].assume_init_read();
+        Self::slice_shl(&mut p.keys[idx..count], 1);
+        Self::slice_shl(&mut p.values[idx..count], 1);
+        p.count -= 1;
+        (key, value)
+    }
+
+    /// Ensures that the `idx`-th child of `p` holds at least $n$ keys, by
+    /// borrowing a key from either sibling or merging it with one.
+    unsafe fn refill(p: &mut Node<K, V, N>, idx: usize) {
+        let count = p.count as usize;
+        let child_count = |p: &Node<K, V, N>, i: usize| p.children[i].as_ref().unwrap().count;
+        if child_count(p, idx) as usize >= N {
+            return;
+        }
+
+        if idx > 0 && child_count(p, idx - 1) as usize > N {
+            // rotate right through the separator
+            //
+            //        [S]                 [c]
+            //   a b c   x     ->    a b     S x
+            let left = &mut *(p.children[idx - 1].as_mut().unwrap().as_mut() as *mut Node<K, V, N>);
+            let child = p.children[idx].as_mut().unwrap();
+            let (lc, cc) = (left.count as usize, child.count as usize);
+            Self::slice_shr(&mut child.keys[0..=cc], 1);
+            Self::slice_shr(&mut child.values[0..=cc], 1);
+            let grandchild = mem::take(&mut left.children[lc]);
+            Self::slice_shr_with(&mut child.children[0..=cc + 1], 1, grandchild);
+
+            let key = mem::replace(&mut left.keys[lc - 1], MaybeUninit::uninit());
+            let value = mem::replace(&mut left.values[lc - 1], MaybeUninit::uninit());
+            child.keys[0] = mem::replace(&mut p.keys[idx - 1], key);
+            child.values[0] = mem::replace(&mut p.values[idx - 1], value);
+            left.count -= 1;
+            child.count += 1;
+        } else if idx < count && child_count(p, idx + 1) as usize > N {
+            // rotate left through the separator
+            //
+            //      [S]                   [a]
+            //   x     a b c   ->    x S     b c
+            let right =
+                &mut *(p.children[idx + 1].as_mut().unwrap().as_mut() as *mut Node<K, V, N>);
+            let child = p.children[idx].as_mut().unwrap();
+            let (rc, cc) = (right.count as usize, child.count as usize);
+            let key = mem::replace(&mut right.keys[0], MaybeUninit::uninit());
+            let value = mem::replace(&mut right.values[0], MaybeUninit::uninit());
+            child.keys[cc] = mem::replace(&mut p.keys[idx], key);
+            child.values[cc] = mem::replace(&mut p.values[idx], value);
+            child.children[cc + 1] = mem::take(&mut right.children[0]);
+
+            Self::slice_shl(&mut right.keys[0..rc], 1);
+            Self::slice_shl(&mut right.values[0..rc], 1);
+            Self::slice_shl_with(&mut right.children[0..=rc], 1, None);
+            right.count -= 1;
+            child.count += 1;
+        } else if idx > 0 {
+            Self::merge(p, idx - 1);
+        } else {
+            Self::merge(p, idx);
+        }
+    }
+
+    /// Merges the `idx + 1`-th child of `p` into the `idx`-th child, along
+    /// with the separator between them.
+    ///
+    ///        [S]
+    ///   a b       c d    ->    a b S c d
+    unsafe fn merge(p: &mut Node<K, V, N>, idx: usize) {
+        let count = p.count as usize;
+        let mut right = mem::take(&mut p.children[idx + 1]).unwrap();
+        let left = p.children[idx].as_mut().unwrap();
+        let (lc, rc) = (left.count as usize, right.count as usize);
+
+        left.keys[lc].write(p.keys[idx].assume_init_read());
+        left.values[lc].write(p.values[idx].assume_init_read());
+        Self::slice_copy(&mut right.keys[0..rc], &mut left.keys[lc + 1..lc + 1 + rc]);
+        Self::slice_copy(
+            &mut right.values[0..rc],
+            &mut left.values[lc + 1..lc + 1 + rc],
+        );
+        for i in 0..=rc {
+            left.children[lc + 1 + i] = mem::take(&mut right.children[i]);
+        }
+        left.count = (lc + 1 + rc) as u16;
+        // everything had been moved out
+        right.count = 0;
+
+        Self::slice_shl(&mut p.keys[idx..count], 1);
+        Self::slice_shl(&mut p.values[idx..count], 1);
+        Self::slice_shl_with(&mut p.children[idx + 1..=count], 1, None);
+        p.count -= 1;
+    }
+
     /// Shift every item on `slice` right `offset` indices in-place. Items
     /// exceeding this area will not be overwritten.
     ///
@@ -400,6 +603,34 @@ where
         ptr::write(slice_ptr, overwrite);
     }
 
+    /// Shift every item on `slice` left `offset` indices in-place. Items
+    /// exceeding this area will not be overwritten.
+    #[inline]
+    unsafe fn slice_shl<T>(slice: &mut [MaybeUninit<T>], offset: usize) {
+        if slice.len() < offset {
+            return;
+        }
+
+        let slice_ptr = slice.as_mut_ptr();
+        ptr::copy(slice_ptr.add(offset), slice_ptr, slice.len() - offset);
+    }
+
+    /// Shift every item on `slice` left `offset` indices in-place. Items
+    /// exceeding this area will not be overwritten.
+    ///
+    /// The rightmost item is overwritten with an additional value `overwrite`.
+    /// The first item is not dropped.
+    #[inline]
+    unsafe fn slice_shl_with<T>(slice: &mut [T], offset: usize, overwrite: T) {
+        if slice.len() < offset {
+            return;
+        }
+
+        let slice_ptr = slice.as_mut_ptr();
+        ptr::copy(slice_ptr.add(offset), slice_ptr, slice.len() - offset);
+        ptr::write(slice_ptr.add(slice.len() - 1), overwrite);
+    }
+
     /// Copies data on `src` to `dest`. The two slices must be manually
     /// guaranteed to be non-overlapping.
     ///
@@ -412,6 +643,56 @@ where
     }
 }
 
+/// In-order iterator keeping the path from the root to the next pair.
+struct BTreeIterator<K: Ord + Eq, V, const N: usize>
+where
+    [(); N * 2]: Sized,
+    [(); N * 2 + 1]: Sized,
+{
+    /// Nodes on the path, each along with the index of its next key.
+    stack: Vec<(*mut Node<K, V, N>, usize)>,
+}
+
+impl<K: Ord + Eq, V, const N: usize> BTreeIterator<K, V, N>
+where
+    [(); N * 2]: Sized,
+    [(); N * 2 + 1]: Sized,
+{
+    /// Pushes the path from `p` to its leftmost leaf.
+    unsafe fn descend(&mut self, mut p: *mut Node<K, V, N>) {
+        while !p.is_null() {
+            self.stack.push((p, 0));
+            p = Node::as_ptr(&mut (*p).children[0]);
+        }
+    }
+}
+
+impl<K: Ord + Eq, V, const N: usize> Iterator for BTreeIterator<K, V, N>
+where
+    [(); N * 2]: Sized,
+    [(); N * 2 + 1]: Sized,
+{
+    type Item = MemTablePointer<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((p, idx)) = self.stack.pop() {
+            unsafe {
+                if idx >= (*p).count as usize {
+                    continue;
+                }
+                // the right subtree of this key comes before the next key
+                self.stack.push((p, idx + 1));
+                self.descend(Node::as_ptr(&mut (*p).children[idx + 1]));
+                return Some(MemTablePointer::new(
+                    (*p).keys[idx].as_ptr(),
+                    (*p).values[idx].as_mut_ptr(),
+                ));
+            }
+        }
+        None
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::BTreeImpl;
@@ -461,8 +742,8 @@ pub mod tests {
 
     /// Memory leak testing utilities
     #[derive(PartialOrd, Ord, PartialEq, Eq)]
-    struct DroppableI64 {
-        value: i64,
+    pub struct DroppableI64 {
+        pub value: i64,
         counter: *mut i64,
     }
 
@@ -475,7 +756,7 @@ pub mod tests {
     }
 
     impl DroppableI64 {
-        fn from(value: i64, counter_ref: *mut i64) -> Self {
+        pub fn from(value: i64, counter_ref: *mut i64) -> Self {
             Self {
                 value: value,
                 counter: counter_ref,
@@ -512,6 +793,28 @@ pub mod tests {
         assert_eq!(drop_counter, 57057);
     }
 
+    #[test]
+    fn stress_remove() {
+        // removals in scrambled order exercise borrows and merges
+        let loops: u64 = 23333;
+        let mut mp = BTreeImpl::<u64, u64, 2>::new();
+        for key in 1..=loops {
+            mp.insert(key, key * 2 + 1);
+        }
+        for i in 1..=loops {
+            let key = i * 7919 % loops + 1;
+            if !key.is_multiple_of(3) {
+                assert!(mp.remove(&key).is_ok());
+            }
+        }
+        for key in 1..=loops {
+            match key % 3 {
+                0 => assert_eq!(mp.get(&key), Some(&mut (key * 2 + 1))),
+                _ => assert_eq!(mp.get(&key), None),
+            }
+        }
+    }
+
     #[test]
     fn stress_test() {
         // ensure it works on big data
@@ -1,5 +1,7 @@
-use crate::memtable::MemTable;
+use crate::memtable::{MemTable, MemTableIterator, MemTablePointer};
 use std::collections::BTreeMap;
+use std::mem;
+use std::ops::Bound;
 
 /// Wrapper of built-in B-Tree map implementation.
 pub struct BTreeBuiltin<K: Ord + Eq, V> {
@@ -28,8 +30,32 @@ impl<K: Ord + Eq, V> MemTable<K, V> for BTreeBuiltin<K, V> {
         }
     }
 
-    fn remove(&mut self, _key: &K) -> Result<(), ()> {
-        Err(())
+    fn remove(&mut self, key: &K) -> Result<(), ()> {
+        match self.proxy.remove(key) {
+            Some(_) => Ok(()),
+            None => Err(()),
+        }
+    }
+
+    fn approximate_memory_usage(&self) -> usize {
+        // node layout is private, so only pairs are counted
+        self.proxy.len() * (mem::size_of::<K>() + mem::size_of::<V>())
+    }
+
+    fn iter(&mut self) -> MemTableIterator<'_, K, V> {
+        Box::new(
+            self.proxy
+                .iter_mut()
+                .map(|(key, value)| MemTablePointer::new(key, value)),
+        )
+    }
+
+    fn lower_bound(&mut self, key: &K) -> MemTableIterator<'_, K, V> {
+        Box::new(
+            self.proxy
+                .range_mut((Bound::Included(key), Bound::Unbounded))
+                .map(|(key, value)| MemTablePointer::new(key, value)),
+        )
     }
 }
 
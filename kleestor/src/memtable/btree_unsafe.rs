@@ -1,5 +1,6 @@
-use crate::memtable::MemTable;
+use crate::memtable::{MemTable, MemTableIterator, MemTablePointer};
 use std::alloc::{alloc, dealloc, Layout};
+use std::mem;
 use std::ptr;
 
 /// A B-tree of key-value pairs, where each node may contain up to $ORDER - 1$
@@ -23,8 +24,58 @@ impl<K: Ord + Eq, V, const ORDER: usize> MemTable<K, V> for BTreeUnsafe<K, V, OR
         unsafe { self.insert_wrap(key, value) }
     }
 
-    fn remove(&mut self, _key: &K) -> Result<(), ()> {
-        Err(())
+    fn remove(&mut self, key: &K) -> Result<(), ()> {
+        unsafe { self.remove_wrap(key) }
+    }
+
+    fn approximate_memory_usage(&self) -> usize {
+        // nodes are counted by walking the tree, while every pair is boxed
+        let (mut nodes, mut pairs) = (0_usize, 0_usize);
+        let mut stack = vec![self.root];
+        while let Some(p) = stack.pop() {
+            if p.is_null() {
+                continue;
+            }
+            let p = unsafe { &*p };
+            nodes += 1;
+            pairs += p.keys_cnt as usize;
+            stack.extend_from_slice(&p.children[..=p.keys_cnt as usize]);
+        }
+        nodes * mem::size_of::<Node<K, V, ORDER>>()
+            + pairs * (mem::size_of::<K>() + mem::size_of::<V>())
+    }
+
+    fn iter(&mut self) -> MemTableIterator<'_, K, V> {
+        let mut iter = BTreeUnsafeIterator { stack: Vec::new() };
+        unsafe { iter.descend(self.root) };
+        Box::new(iter)
+    }
+
+    fn lower_bound(&mut self, key: &K) -> MemTableIterator<'_, K, V> {
+        let mut iter = BTreeUnsafeIterator { stack: Vec::new() };
+        let mut p = self.root;
+        unsafe {
+            while !p.is_null() {
+                let keys_cnt = (*p).keys_cnt as usize;
+                let mut idx = 0;
+                while idx < keys_cnt && &*(*p).keys[idx] < key {
+                    idx += 1;
+                }
+                iter.stack.push((p, idx));
+                if idx < keys_cnt && &*(*p).keys[idx] == key {
+                    break;
+                }
+                p = (*p).children[idx];
+            }
+        }
+        Box::new(iter)
+    }
+}
+
+impl<K: Ord + Eq, V, const ORDER: usize> Drop for BTreeUnsafe<K, V, ORDER> {
+    fn drop(&mut self) {
+        unsafe { Self::drop_r(self.root) }
+        self.root = ptr::null_mut();
     }
 }
 
@@ -245,7 +296,7 @@ impl<K: Ord + Eq, V, const ORDER: usize> BTreeUnsafe<K, V, ORDER> {
             for i in (idx..keys_cnt as usize).rev() {
                 (*p).keys[i + 1] = (*p).keys[i];
                 (*p).values[i + 1] = (*p).values[i];
-                (*p).children[i + 1] = (*p).children[i];
+                (*p).children[i + 2] = (*p).children[i + 1];
             }
             // insert leaf
             (*p).keys[idx] = key;
@@ -278,8 +329,8 @@ impl<K: Ord + Eq, V, const ORDER: usize> BTreeUnsafe<K, V, ORDER> {
             (*lc).children[idx + 1] = rchild;
 
             for i in (idx + 1)..(ORDER / 2) {
-                (*lc).keys[i] = (*p).keys[i];
-                (*lc).values[i] = (*p).values[i];
+                (*lc).keys[i] = (*p).keys[i - 1];
+                (*lc).values[i] = (*p).values[i - 1];
                 (*lc).children[i + 1] = (*p).children[i];
             }
 
@@ -346,7 +397,8 @@ impl<K: Ord + Eq, V, const ORDER: usize> BTreeUnsafe<K, V, ORDER> {
             }
         }
 
-        // TODO: `p` is nof freed
+        // everything in `p` had been moved out
+        dealloc(p as *mut u8, Node::<K, V, ORDER>::layout());
 
         (*lc).keys_cnt = (ORDER / 2) as u16;
         (*rc).keys_cnt = (ORDER / 2) as u16;
@@ -360,6 +412,235 @@ impl<K: Ord + Eq, V, const ORDER: usize> BTreeUnsafe<K, V, ORDER> {
     }
 }
 
+impl<K: Ord + Eq, V, const ORDER: usize> BTreeUnsafe<K, V, ORDER> {
+    /// Remove key-value pair.
+    unsafe fn remove_wrap(&mut self, key: &K) -> Result<(), ()> {
+        let removed = Self::remove_r(self.root, key);
+
+        // the root ran out of keys and its only child takes over
+        let root = self.root;
+        if (*root).keys_cnt == 0 && !(*root).children[0].is_null() {
+            self.root = (*root).children[0];
+            dealloc(root as *mut u8, Node::<K, V, ORDER>::layout());
+        }
+        match removed {
+            Some((key, value)) => {
+                free_from_heap(key);
+                free_from_heap(value);
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+
+    /// Remove recursively a key from a given node, returning the removed
+    /// key-value pair. Children left with too few keys are refilled from
+    /// their siblings or merged on the way while backtracing.
+    unsafe fn remove_r(p: *mut Node<K, V, ORDER>, key: &K) -> Option<(*mut K, *mut V)> {
+        // p.key[idx - 1] < key <= p.key[idx]
+        let keys_cnt = (*p).keys_cnt as usize;
+        let mut idx: usize = 0;
+        while idx < keys_cnt && &*(*p).keys[idx] < key {
+            idx += 1;
+        }
+        let found = idx < keys_cnt && &*(*p).keys[idx] == key;
+
+        let child = (*p).children[idx];
+        let removed = if child.is_null() {
+            // reached a leaf
+            if !found {
+                return None;
+            }
+            return Some(Self::take_pair(p, idx));
+        } else if found {
+            // replace the pair with its predecessor
+            let (k, v) = Self::remove_max(child);
+            let removed = ((*p).keys[idx], (*p).values[idx]);
+            (*p).keys[idx] = k;
+            (*p).values[idx] = v;
+            removed
+        } else {
+            Self::remove_r(child, key)?
+        };
+        Self::refill(p, idx);
+        Some(removed)
+    }
+
+    /// Remove the largest key-value pair under a given node.
+    unsafe fn remove_max(p: *mut Node<K, V, ORDER>) -> (*mut K, *mut V) {
+        let keys_cnt = (*p).keys_cnt as usize;
+        let child = (*p).children[keys_cnt];
+        if child.is_null() {
+            return Self::take_pair(p, keys_cnt - 1);
+        }
+        let removed = Self::remove_max(child);
+        Self::refill(p, keys_cnt);
+        removed
+    }
+
+    /// Take the `idx`-th key-value pair away from a leaf.
+    unsafe fn take_pair(p: *mut Node<K, V, ORDER>, idx: usize) -> (*mut K, *mut V) {
+        let keys_cnt = (*p).keys_cnt as usize;
+        let removed = ((*p).keys[idx], (*p).values[idx]);
+        for i in idx..keys_cnt - 1 {
+            (*p).keys[i] = (*p).keys[i + 1];
+            (*p).values[i] = (*p).values[i + 1];
+        }
+        (*p).keys[keys_cnt - 1] = ptr::null_mut();
+        (*p).values[keys_cnt - 1] = ptr::null_mut();
+        (*p).keys_cnt -= 1;
+        removed
+    }
+
+    /// Ensures that the `idx`-th child of `p` holds at least $ORDER / 2$ keys,
+    /// by borrowing a key from either sibling or merging it with one.
+    unsafe fn refill(p: *mut Node<K, V, ORDER>, idx: usize) {
+        let min_cnt = (ORDER / 2) as u16;
+        let child = (*p).children[idx];
+        if (*child).keys_cnt >= min_cnt {
+            return;
+        }
+        let cc = (*child).keys_cnt as usize;
+
+        if idx > 0 && (*(*p).children[idx - 1]).keys_cnt > min_cnt {
+            // rotate right through the separator
+            //    [S]              [c]
+            //   a b c  x   ->   a b  S x
+            let left = (*p).children[idx - 1];
+            let lc = (*left).keys_cnt as usize;
+            for i in (0..cc).rev() {
+                (*child).keys[i + 1] = (*child).keys[i];
+                (*child).values[i + 1] = (*child).values[i];
+            }
+            for i in (0..=cc).rev() {
+                (*child).children[i + 1] = (*child).children[i];
+            }
+            (*child).keys[0] = (*p).keys[idx - 1];
+            (*child).values[0] = (*p).values[idx - 1];
+            (*child).children[0] = (*left).children[lc];
+            (*p).keys[idx - 1] = (*left).keys[lc - 1];
+            (*p).values[idx - 1] = (*left).values[lc - 1];
+
+            (*left).keys[lc - 1] = ptr::null_mut();
+            (*left).values[lc - 1] = ptr::null_mut();
+            (*left).children[lc] = ptr::null_mut();
+            (*left).keys_cnt -= 1;
+            (*child).keys_cnt += 1;
+        } else if idx < (*p).keys_cnt as usize && (*(*p).children[idx + 1]).keys_cnt > min_cnt {
+            // rotate left through the separator
+            //    [S]              [a]
+            //   x  a b c   ->   x S  b c
+            let right = (*p).children[idx + 1];
+            let rc = (*right).keys_cnt as usize;
+            (*child).keys[cc] = (*p).keys[idx];
+            (*child).values[cc] = (*p).values[idx];
+            (*child).children[cc + 1] = (*right).children[0];
+            (*p).keys[idx] = (*right).keys[0];
+            (*p).values[idx] = (*right).values[0];
+
+            for i in 0..rc - 1 {
+                (*right).keys[i] = (*right).keys[i + 1];
+                (*right).values[i] = (*right).values[i + 1];
+            }
+            for i in 0..rc {
+                (*right).children[i] = (*right).children[i + 1];
+            }
+            (*right).keys[rc - 1] = ptr::null_mut();
+            (*right).values[rc - 1] = ptr::null_mut();
+            (*right).children[rc] = ptr::null_mut();
+            (*right).keys_cnt -= 1;
+            (*child).keys_cnt += 1;
+        } else if idx > 0 {
+            Self::merge(p, idx - 1);
+        } else {
+            Self::merge(p, idx);
+        }
+    }
+
+    /// Merges the `idx + 1`-th child of `p` into the `idx`-th child, along
+    /// with the separator between them.
+    ///
+    ///       [S]
+    ///   a b     c d   ->   a b S c d
+    unsafe fn merge(p: *mut Node<K, V, ORDER>, idx: usize) {
+        let keys_cnt = (*p).keys_cnt as usize;
+        let left = (*p).children[idx];
+        let right = (*p).children[idx + 1];
+        let (lc, rc) = ((*left).keys_cnt as usize, (*right).keys_cnt as usize);
+
+        (*left).keys[lc] = (*p).keys[idx];
+        (*left).values[lc] = (*p).values[idx];
+        for i in 0..rc {
+            (*left).keys[lc + 1 + i] = (*right).keys[i];
+            (*left).values[lc + 1 + i] = (*right).values[i];
+        }
+        for i in 0..=rc {
+            (*left).children[lc + 1 + i] = (*right).children[i];
+        }
+        (*left).keys_cnt = (lc + 1 + rc) as u16;
+        // everything in `right` had been moved out
+        dealloc(right as *mut u8, Node::<K, V, ORDER>::layout());
+
+        for i in idx..keys_cnt - 1 {
+            (*p).keys[i] = (*p).keys[i + 1];
+            (*p).values[i] = (*p).values[i + 1];
+        }
+        for i in idx + 1..keys_cnt {
+            (*p).children[i] = (*p).children[i + 1];
+        }
+        (*p).keys[keys_cnt - 1] = ptr::null_mut();
+        (*p).values[keys_cnt - 1] = ptr::null_mut();
+        (*p).children[keys_cnt] = ptr::null_mut();
+        (*p).keys_cnt -= 1;
+    }
+
+    /// Recursively drop tree.
+    unsafe fn drop_r(p: *mut Node<K, V, ORDER>) {
+        if p.is_null() {
+            return;
+        }
+        for i in 0..ORDER {
+            Self::drop_r((*p).children[i]);
+        }
+        Node::drop(p);
+    }
+}
+
+/// In-order iterator keeping the path from the root to the next pair.
+struct BTreeUnsafeIterator<K: Ord + Eq, V, const ORDER: usize> {
+    /// Nodes on the path, each along with the index of its next key.
+    stack: Vec<(*mut Node<K, V, ORDER>, usize)>,
+}
+
+impl<K: Ord + Eq, V, const ORDER: usize> BTreeUnsafeIterator<K, V, ORDER> {
+    /// Pushes the path from `p` to its leftmost leaf.
+    unsafe fn descend(&mut self, mut p: *mut Node<K, V, ORDER>) {
+        while !p.is_null() {
+            self.stack.push((p, 0));
+            p = (*p).children[0];
+        }
+    }
+}
+
+impl<K: Ord + Eq, V, const ORDER: usize> Iterator for BTreeUnsafeIterator<K, V, ORDER> {
+    type Item = MemTablePointer<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((p, idx)) = self.stack.pop() {
+            unsafe {
+                if idx >= (*p).keys_cnt as usize {
+                    continue;
+                }
+                // the right subtree of this key comes before the next key
+                self.stack.push((p, idx + 1));
+                self.descend((*p).children[idx + 1]);
+                return Some(MemTablePointer::new((*p).keys[idx], (*p).values[idx]));
+            }
+        }
+        None
+    }
+}
+
 unsafe fn free_from_heap<T>(p: *mut T) -> () {
     if p != ptr::null_mut() {
         let _item = Box::from_raw(p);
@@ -1,9 +1,14 @@
+pub mod arena;
 pub mod btree;
 pub mod btree_builtin;
 pub mod btree_unsafe;
 pub mod rbtree;
+pub mod skiplist;
 pub mod splay;
 
+use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer, KvPointerMut};
+use std::ops::Bound;
+
 /// Basic MemTable implementation interface.
 pub trait MemTable<K: Ord + Eq, V> {
     /// Accesses `table[key] -> value`.
@@ -16,4 +21,273 @@ pub trait MemTable<K: Ord + Eq, V> {
     /// Removes key from table. Returns `Ok(())` for deletion success, or
     /// `Err(())` when no such key exists.
     fn remove(&mut self, key: &K) -> Result<(), ()>;
+
+    /// Approximate number of bytes allocated by the table for its nodes.
+    /// Memory owned by keys and values themselves is only counted by tables
+    /// that copy them into an arena.
+    fn approximate_memory_usage(&self) -> usize;
+
+    /// Iterates all key-value pairs in ascending order of keys.
+    fn iter(&mut self) -> MemTableIterator<'_, K, V>;
+
+    /// Iterates in ascending order from the first key that is not less than
+    /// `key`.
+    fn lower_bound(&mut self, key: &K) -> MemTableIterator<'_, K, V>;
+
+    /// Iterates in ascending order over the keys within `start` and `end`.
+    fn range<'a>(&'a mut self, start: Bound<&'a K>, end: Bound<&'a K>) -> MemTableIterator<'a, K, V>
+    where
+        V: 'a,
+    {
+        let iter = match start {
+            Bound::Included(key) => self.lower_bound(key),
+            Bound::Excluded(key) => {
+                Box::new(self.lower_bound(key).skip_while(move |p| p.key() == key))
+            }
+            Bound::Unbounded => self.iter(),
+        };
+        Box::new(iter.take_while(move |p| match end {
+            Bound::Included(key) => p.key() <= key,
+            Bound::Excluded(key) => p.key() < key,
+            Bound::Unbounded => true,
+        }))
+    }
+}
+
+/// Ordered iterator over the key-value pairs of a [`MemTable`].
+///
+/// The table may not be modified while it is being iterated.
+pub type MemTableIterator<'a, K, V> = Box<dyn Iterator<Item = MemTablePointer<K, V>> + 'a>;
+
+/// Points to a key-value pair inside a [`MemTable`], which is a [`KvPointer`]
+/// for tables holding records.
+pub struct MemTablePointer<K, V> {
+    key: *const K,
+    value: *mut V,
+}
+
+impl<K, V> MemTablePointer<K, V> {
+    pub fn new(key: *const K, value: *mut V) -> Self {
+        Self { key, value }
+    }
+
+    /// Accesses the pointed key.
+    pub fn key(&self) -> &K {
+        unsafe { &*self.key }
+    }
+
+    /// Accesses the pointed key-value pair, the value being writable through
+    /// a pointer held exclusively.
+    pub fn pair(&mut self) -> (&K, &mut V) {
+        unsafe { (&*self.key, &mut *self.value) }
+    }
+}
+
+impl KvPointer for MemTablePointer<ByteStream, KvEntry> {
+    fn key(&self) -> &[u8] {
+        MemTablePointer::key(self).as_ref()
+    }
+
+    fn value(&self) -> KvDataRef {
+        let value = unsafe { &(*self.value) };
+        match &value.record {
+            KvData::Tombstone { cached } => KvDataRef::Tombstone { cached: *cached },
+            KvData::Value { cached, value } => KvDataRef::Value {
+                cached: *cached,
+                value: value.as_ref(),
+            },
+        }
+    }
+}
+
+impl KvPointerMut for MemTablePointer<ByteStream, KvEntry> {
+    fn value_mut(&mut self) -> &mut KvEntry {
+        self.pair().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::btree::tests::DroppableI64;
+    use super::btree::BTreeImpl;
+    use super::btree_builtin::BTreeBuiltin;
+    use super::btree_unsafe::BTreeUnsafe;
+    use super::rbtree::RBTree;
+    use super::skiplist::SkipList;
+    use super::splay::SplayTree;
+    use super::MemTable;
+    use crate::record::{ByteStream, KvData, KvEntry, KvMergeIterator, KvPointer};
+    use crate::sstable::reader::SSTableReader;
+    use crate::sstable::writer::SSTableWriter;
+    use std::ops::Bound;
+
+    /// Inserts and removes keys in scrambled orders, ensuring that removed
+    /// values are dropped right away and the others when the table is.
+    fn expect_remove_ok(mut map: Box<dyn MemTable<u64, DroppableI64>>) {
+        let mut drop_counter = 0i64;
+        let ctr_ref = &mut drop_counter as *mut i64;
+
+        let scale = 2000_u64;
+        for i in 0..scale {
+            let key = i * 7919 % scale;
+            assert!(map
+                .insert(key, DroppableI64::from(key as i64, ctr_ref))
+                .is_none());
+        }
+        let mut removed = 0i64;
+        for i in 0..scale {
+            let key = i * 104729 % scale;
+            if key.is_multiple_of(3) {
+                continue;
+            }
+            assert!(map.remove(&key).is_ok());
+            assert!(map.remove(&key).is_err());
+            removed += key as i64;
+            assert_eq!(unsafe { *ctr_ref }, removed);
+        }
+        for key in 0..scale {
+            match (key % 3, map.get(&key)) {
+                (0, Some(value)) => assert_eq!(value.value, key as i64),
+                (0, None) => panic!("key {key} missing"),
+                (_, found) => assert!(found.is_none()),
+            }
+        }
+
+        drop(map);
+        assert_eq!(drop_counter, (0..scale as i64).sum::<i64>());
+    }
+
+    #[test]
+    fn remove_rbtree() {
+        expect_remove_ok(Box::new(RBTree::new()));
+    }
+
+    #[test]
+    fn remove_skiplist() {
+        expect_remove_ok(Box::new(SkipList::new()));
+    }
+
+    #[test]
+    fn remove_splay() {
+        expect_remove_ok(Box::new(SplayTree::new()));
+    }
+
+    #[test]
+    fn remove_btree() {
+        expect_remove_ok(Box::new(BTreeImpl::<u64, DroppableI64, 2>::new()));
+        expect_remove_ok(Box::new(BTreeImpl::<u64, DroppableI64, 7>::new()));
+    }
+
+    #[test]
+    fn remove_btree_unsafe() {
+        expect_remove_ok(Box::new(BTreeUnsafe::<u64, DroppableI64, 5>::new()));
+        expect_remove_ok(Box::new(BTreeUnsafe::<u64, DroppableI64, 15>::new()));
+    }
+
+    #[test]
+    fn remove_btree_builtin() {
+        expect_remove_ok(Box::new(BTreeBuiltin::new()));
+    }
+
+    fn key_of(i: u64) -> ByteStream {
+        ByteStream::from_vec(format!("key-{i:04}").into_bytes())
+    }
+
+    fn entry_of(i: u64) -> KvEntry {
+        KvEntry::new(KvData::Value {
+            cached: false,
+            value: ByteStream::from_vec(format!("value-{i}").into_bytes()),
+        })
+    }
+
+    fn keys_of<P: KvPointer>(iter: impl Iterator<Item = P>) -> Vec<String> {
+        iter.map(|p| String::from_utf8(p.key().to_vec()).unwrap())
+            .collect()
+    }
+
+    /// Fills even keys in a scrambled order and scans them back in order,
+    /// merged with odd keys and flushed to disk.
+    fn expect_scan_ok(mut map: Box<dyn MemTable<ByteStream, KvEntry>>, name: &str) {
+        let scale = 1000_u64;
+        for i in 0..scale {
+            let i = i * 7919 % scale;
+            if i.is_multiple_of(2) {
+                map.insert(key_of(i), entry_of(i));
+            }
+        }
+        let evens = |from: u64, to: u64| -> Vec<String> {
+            (from..to)
+                .filter(|i| i % 2 == 0)
+                .map(|i| format!("key-{i:04}"))
+                .collect()
+        };
+
+        assert_eq!(keys_of(map.iter()), evens(0, scale));
+        assert_eq!(keys_of(map.lower_bound(&key_of(100))), evens(100, scale));
+        assert_eq!(keys_of(map.lower_bound(&key_of(101))), evens(102, scale));
+        assert!(map.lower_bound(&key_of(scale)).next().is_none());
+
+        let (lo, hi) = (key_of(100), key_of(110));
+        let range = map.range(Bound::Excluded(&lo), Bound::Included(&hi));
+        assert_eq!(keys_of(range), evens(101, 111));
+        let range = map.range(Bound::Included(&lo), Bound::Excluded(&hi));
+        assert_eq!(keys_of(range), evens(100, 110));
+        let range = map.range(Bound::Unbounded, Bound::Excluded(&lo));
+        assert_eq!(keys_of(range), evens(0, 100));
+
+        // any table may be merged with the others
+        let mut odds = BTreeBuiltin::new();
+        for i in (1..scale).step_by(2) {
+            odds.insert(key_of(i), entry_of(i));
+        }
+        let merged = KvMergeIterator::new(vec![map.iter(), odds.iter()]);
+        let expected: Vec<String> = (0..scale).map(|i| format!("key-{i:04}")).collect();
+        assert_eq!(keys_of(merged), expected);
+
+        // any table may be flushed to disk
+        let mut path = std::env::temp_dir();
+        path.push(format!("_kleestor_memtable_scan_{name}.db"));
+        let file = std::fs::File::create(&path).unwrap();
+        SSTableWriter::new(file).write(map.iter()).unwrap();
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(keys_of(table.iter()), evens(0, scale));
+        match table.get(key_of(42).as_ref()).unwrap() {
+            Some(KvData::Value { value, .. }) => assert_eq!(value.as_ref(), b"value-42"),
+            _ => panic!("key-0042 missing"),
+        }
+        drop(table);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn scan_rbtree() {
+        expect_scan_ok(Box::new(RBTree::new()), "rbtree");
+    }
+
+    #[test]
+    fn scan_skiplist() {
+        expect_scan_ok(Box::new(SkipList::new()), "skiplist");
+    }
+
+    #[test]
+    fn scan_splay() {
+        expect_scan_ok(Box::new(SplayTree::new()), "splay");
+    }
+
+    #[test]
+    fn scan_btree() {
+        expect_scan_ok(Box::new(BTreeImpl::<_, _, 2>::new()), "btree_2");
+        expect_scan_ok(Box::new(BTreeImpl::<_, _, 7>::new()), "btree_7");
+    }
+
+    #[test]
+    fn scan_btree_unsafe() {
+        expect_scan_ok(Box::new(BTreeUnsafe::<_, _, 5>::new()), "btree_unsafe_5");
+        expect_scan_ok(Box::new(BTreeUnsafe::<_, _, 15>::new()), "btree_unsafe_15");
+    }
+
+    #[test]
+    fn scan_btree_builtin() {
+        expect_scan_ok(Box::new(BTreeBuiltin::new()), "btree_builtin");
+    }
 }
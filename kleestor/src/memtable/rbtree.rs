@@ -1,7 +1,6 @@
-use crate::memtable::MemTable;
-use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer};
-use crate::utils;
-use std::alloc::{alloc, Layout};
+use crate::memtable::{MemTable, MemTableIterator, MemTablePointer};
+use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer, KvPointerMut};
+use std::alloc::{alloc, dealloc, Layout};
 use std::mem;
 use std::ptr::{self, null_mut};
 
@@ -14,7 +13,6 @@ pub struct RBTree<K: Ord + Eq, V> {
     /// Pointer to tree root.
     root: *mut Node<K, V>,
     /// A total of `length` nodes are in this tree.
-    #[allow(dead_code)]
     length: usize,
 }
 
@@ -31,14 +29,20 @@ impl RBTree<ByteStream, KvEntry> {
         }
     }
 
-    /// Access full-scan iterator.
-    pub fn iter_mut(&mut self) -> RBTreeIterator {
+    /// Accesses iterator at the first key that is not less than `key`.
+    pub fn lower_bound_iter(&mut self, key: &[u8]) -> RBTreeIterator {
         unsafe {
             let mut ptr = self.root;
-            while ptr != ptr::null_mut() && (*ptr).child[0] != ptr::null_mut() {
-                ptr = (*ptr).child[0];
+            let mut found = ptr::null_mut();
+            while !ptr.is_null() {
+                if (*ptr).key.as_ref() < key {
+                    ptr = (*ptr).child[1];
+                } else {
+                    found = ptr;
+                    ptr = (*ptr).child[0];
+                }
             }
-            RBTreeIterator { node: ptr }
+            RBTreeIterator { node: found }
         }
     }
 
@@ -58,13 +62,13 @@ impl RBTree<ByteStream, KvEntry> {
 }
 
 /// Tree node iterator manager.
-pub struct RBTreeIterator {
+pub struct RBTreeIterator<K: Ord + Eq = ByteStream, V = KvEntry> {
     /// Pointer to next item.
-    node: *mut Node<ByteStream, KvEntry>,
+    node: *mut Node<K, V>,
 }
 
-impl Iterator for RBTreeIterator {
-    type Item = RBTreePointer;
+impl<K: Ord + Eq, V> Iterator for RBTreeIterator<K, V> {
+    type Item = RBTreePointer<K, V>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.node == null_mut() {
@@ -100,9 +104,15 @@ impl Iterator for RBTreeIterator {
 }
 
 /// Tree iterator (pointer) interface.
-pub struct RBTreePointer {
+pub struct RBTreePointer<K: Ord + Eq = ByteStream, V = KvEntry> {
     /// Private pointer to current node.
-    _node: *mut Node<ByteStream, KvEntry>,
+    _node: *mut Node<K, V>,
+}
+
+impl<K: Ord + Eq, V> From<RBTreePointer<K, V>> for MemTablePointer<K, V> {
+    fn from(item: RBTreePointer<K, V>) -> Self {
+        unsafe { MemTablePointer::new(&(*item._node).key, &mut (*item._node).value) }
+    }
 }
 
 impl KvPointer for RBTreePointer {
@@ -121,9 +131,11 @@ impl KvPointer for RBTreePointer {
             },
         }
     }
+}
 
-    fn value_mut(&self) -> &mut KvEntry {
-        unsafe { utils::const_as_mut(&(*self._node).value) }
+impl KvPointerMut for RBTreePointer {
+    fn value_mut(&mut self) -> &mut KvEntry {
+        unsafe { &mut (*self._node).value }
     }
 }
 
@@ -149,8 +161,30 @@ impl<K: Ord + Eq, V> MemTable<K, V> for RBTree<K, V> {
         }
     }
 
-    fn remove(&mut self, _key: &K) -> Result<(), ()> {
-        Err(())
+    fn remove(&mut self, key: &K) -> Result<(), ()> {
+        unsafe {
+            let ptr = self.access(key);
+            if ptr.is_null() {
+                return Err(());
+            }
+            self.remove_node(ptr);
+            Ok(())
+        }
+    }
+
+    fn approximate_memory_usage(&self) -> usize {
+        self.length * mem::size_of::<Node<K, V>>()
+    }
+
+    fn iter(&mut self) -> MemTableIterator<'_, K, V> {
+        Box::new(self.iter_mut().map(MemTablePointer::from))
+    }
+
+    fn lower_bound(&mut self, key: &K) -> MemTableIterator<'_, K, V> {
+        let iter = RBTreeIterator {
+            node: unsafe { self.lower_bound_node(key) },
+        };
+        Box::new(iter.map(MemTablePointer::from))
     }
 }
 
@@ -190,9 +224,11 @@ impl<K: Ord + Eq, V> Node<K, V> {
     #[allow(dead_code)]
     pub unsafe fn drop(ptr: *mut Self) -> () {
         let _item = ptr.read();
+        dealloc(ptr as *mut u8, Self::layout());
     }
 }
 
+#[derive(Clone, Copy)]
 enum Color {
     Red,
     Black,
@@ -208,6 +244,32 @@ impl<K: Ord + Eq, V> RBTree<K, V> {
         }
     }
 
+    /// Access full-scan iterator.
+    pub fn iter_mut(&mut self) -> RBTreeIterator<K, V> {
+        unsafe {
+            let mut ptr = self.root;
+            while !ptr.is_null() && !(*ptr).child[0].is_null() {
+                ptr = (*ptr).child[0];
+            }
+            RBTreeIterator { node: ptr }
+        }
+    }
+
+    /// Finds the node with the first key that is not less than `key`.
+    unsafe fn lower_bound_node(&self, key: &K) -> *mut Node<K, V> {
+        let mut ptr = self.root;
+        let mut found = ptr::null_mut();
+        while !ptr.is_null() {
+            if &(*ptr).key < key {
+                ptr = (*ptr).child[1];
+            } else {
+                found = ptr;
+                ptr = (*ptr).child[0];
+            }
+        }
+        found
+    }
+
     /// Access node with key in red-black tree.
     unsafe fn access(&self, key: &K) -> *mut Node<K, V> {
         if self.root == ptr::null_mut() {
@@ -317,6 +379,7 @@ impl<K: Ord + Eq, V> RBTree<K, V> {
         (*n).child[0] = ptr::null_mut();
         (*n).child[1] = ptr::null_mut();
         (*n).parent = p;
+        self.length += 1;
 
         let g: *mut Node<K, V> = ptr::null_mut(); // grandparent of `n`
         let u: *mut Node<K, V> = ptr::null_mut(); // uncle of `n`
@@ -403,6 +466,125 @@ impl<K: Ord + Eq, V> RBTree<K, V> {
         return;
     }
 
+    /// Checks if the node is black, where null leaves count as black.
+    unsafe fn is_black(&self, p: *mut Node<K, V>) -> bool {
+        p.is_null() || matches!((*p).color, Color::Black)
+    }
+
+    /// Puts subtree `v` in place of subtree `u` under the parent of `u`.
+    unsafe fn transplant(&mut self, u: *mut Node<K, V>, v: *mut Node<K, V>) {
+        let g = (*u).parent;
+        if g.is_null() {
+            self.root = v;
+        } else {
+            (*g).child[self.get_side(u, g)] = v;
+        }
+        if !v.is_null() {
+            (*v).parent = g;
+        }
+    }
+
+    /// Unlinks node `n` from the tree and releases it.
+    ///
+    /// Nodes are relinked rather than having their contents swapped, so that
+    /// pointers to the values of all other nodes remain valid.
+    unsafe fn remove_node(&mut self, n: *mut Node<K, V>) {
+        // `x` takes the place of the node actually taken out of the tree,
+        // having `p` as its parent (as `x` might be null)
+        let x: *mut Node<K, V>;
+        let p: *mut Node<K, V>;
+        let removed_color: Color;
+
+        if (*n).child[0].is_null() || (*n).child[1].is_null() {
+            // at most one child, which replaces `n`
+            x = match (*n).child[0] {
+                c if c.is_null() => (*n).child[1],
+                c => c,
+            };
+            p = (*n).parent;
+            removed_color = (*n).color;
+            self.transplant(n, x);
+        } else {
+            // the successor `s` (having no left child) replaces `n`
+            let mut s = (*n).child[1];
+            while !(*s).child[0].is_null() {
+                s = (*s).child[0];
+            }
+            x = (*s).child[1];
+            removed_color = (*s).color;
+            if (*s).parent == n {
+                p = s;
+            } else {
+                p = (*s).parent;
+                self.transplant(s, x);
+                (*s).child[1] = (*n).child[1];
+                (*(*s).child[1]).parent = s;
+            }
+            self.transplant(n, s);
+            (*s).child[0] = (*n).child[0];
+            (*(*s).child[0]).parent = s;
+            (*s).color = (*n).color;
+        }
+        Node::drop(n);
+        self.length -= 1;
+
+        if let Color::Black = removed_color {
+            self.remove_fixup(x, p);
+        }
+    }
+
+    /// Restores red-black properties after a black node was taken out from
+    /// the path to `x`, which now lacks one black node.
+    unsafe fn remove_fixup(&mut self, mut x: *mut Node<K, V>, mut p: *mut Node<K, V>) {
+        while x != self.root && self.is_black(x) {
+            // the sibling `w` cannot be null, as its side has a black height
+            // of at least 1
+            let side = if x == (*p).child[0] { 0 } else { 1 };
+            let mut w = (*p).child[1 - side];
+
+            // Remove case 1:
+            // The sibling is red. After a rotation the sibling is black.
+            if !self.is_black(w) {
+                (*w).color = Color::Black;
+                (*p).color = Color::Red;
+                self.rotate(p, side);
+                w = (*p).child[1 - side];
+            }
+
+            // Remove case 2:
+            // Both children of the sibling are black. Repainting the sibling
+            // red moves the missing black node up to the parent.
+            if self.is_black((*w).child[0]) && self.is_black((*w).child[1]) {
+                (*w).color = Color::Red;
+                x = p;
+                p = (*x).parent;
+                continue;
+            }
+
+            // Remove case 3:
+            // The far child of the sibling is black, so the near one is red
+            // and gets rotated to the far side.
+            if self.is_black((*w).child[1 - side]) {
+                (*(*w).child[side]).color = Color::Black;
+                (*w).color = Color::Red;
+                self.rotate(w, 1 - side);
+                w = (*p).child[1 - side];
+            }
+
+            // Remove case 4:
+            // The far child of the sibling is red. Rotating the parent gives
+            // the path to `x` its missing black node.
+            (*w).color = (*p).color;
+            (*p).color = Color::Black;
+            (*(*w).child[1 - side]).color = Color::Black;
+            self.rotate(p, side);
+            x = self.root;
+        }
+        if !x.is_null() {
+            (*x).color = Color::Black;
+        }
+    }
+
     /// Recursively drop tree.
     unsafe fn drop_recursive(&self, ptr: *mut Node<K, V>) -> () {
         if ptr == ptr::null_mut() {
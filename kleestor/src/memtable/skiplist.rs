@@ -0,0 +1,842 @@
+use crate::memtable::arena::Arena;
+use crate::memtable::{MemTable, MemTableIterator, MemTablePointer};
+use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer};
+use std::alloc::Layout;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Maximum number of levels a node may be linked into.
+const MAX_HEIGHT: usize = 12;
+
+/// Each node is linked into the next level with a probability of 1 / this.
+const BRANCHING: u64 = 4;
+
+/// A skip list allowing concurrent inserts and lookups.
+///
+/// Inserts through [`SkipList::get_or_insert`] only take `&self` and link new
+/// nodes with compare-and-swap, so any number of threads may insert at once
+/// without locks. Lookups never wait or retry, and iterators over a shared
+/// list only read. Nodes are never unlinked while the list is shared, so
+/// every reference handed out lives as long as the list does.
+///
+/// Replacing values in place and removing keys require exclusive access,
+/// which is given through the [`MemTable`] interface. Records of a shared list
+/// are replaced by swapping in new nodes, see [`SkipList::replace`].
+///
+/// Nodes are allocated from an [`Arena`], and so are the bytes of records, so
+/// that dropping the list releases a few large blocks only.
+pub struct SkipList<K: Ord + Eq, V> {
+    /// Successors of the head on each level.
+    head: [AtomicPtr<Node<K, V>>; MAX_HEIGHT],
+    /// Number of levels that may be in use, which never decreases.
+    height: AtomicUsize,
+    /// State of the generator picking heights of new nodes.
+    seed: AtomicU64,
+    /// Nodes swapped out by replacements, which are dropped along with the
+    /// list.
+    retired: Mutex<Vec<*mut Node<K, V>>>,
+    /// Memory of nodes and record bytes. This must be dropped last.
+    arena: Arena,
+}
+
+unsafe impl<K: Ord + Eq + Send + Sync, V: Send + Sync> Send for SkipList<K, V> {}
+unsafe impl<K: Ord + Eq + Send + Sync, V: Send + Sync> Sync for SkipList<K, V> {}
+
+/// Access points for universal trait.
+impl<K: Ord + Eq, V> MemTable<K, V> for SkipList<K, V> {
+    fn get(&mut self, key: &K) -> Option<&mut V> {
+        unsafe {
+            let node = self.find_node(key);
+            if node.is_null() {
+                return None;
+            }
+            Some(&mut (*node).value)
+        }
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<()> {
+        unsafe {
+            let node = self.find_node(&key);
+            if !node.is_null() {
+                (*node).value = value;
+                return Some(());
+            }
+            let _ = self.insert_node(key, value);
+            None
+        }
+    }
+
+    fn approximate_memory_usage(&self) -> usize {
+        self.arena.memory_usage()
+    }
+
+    fn remove(&mut self, key: &K) -> Result<(), ()> {
+        unsafe {
+            let mut preds = [ptr::null_mut(); MAX_HEIGHT];
+            let mut succs = [ptr::null_mut(); MAX_HEIGHT];
+            let node = self.seek(key, &mut preds, &mut succs);
+            if node.is_null() || (*node).key != *key {
+                return Err(());
+            }
+            // exclusive access, so the node is the successor on all its levels
+            for (level, &pred) in preds.iter().enumerate().take((*node).height) {
+                let next = Node::next(node, level).load(Ordering::Relaxed);
+                self.link(pred, level).store(next, Ordering::Relaxed);
+            }
+            ptr::drop_in_place(node);
+            Ok(())
+        }
+    }
+
+    fn iter(&mut self) -> MemTableIterator<'_, K, V> {
+        let iter = self.iter_from(self.head[0].load(Ordering::Acquire));
+        Box::new(iter.map(SkipListPointer::writable))
+    }
+
+    fn lower_bound(&mut self, key: &K) -> MemTableIterator<'_, K, V> {
+        let iter = self.iter_from(unsafe { self.lower_bound_node(key) });
+        Box::new(iter.map(SkipListPointer::writable))
+    }
+}
+
+/// A skip list node, allocated along with as many links as its height.
+#[repr(C)]
+struct Node<K: Ord + Eq, V> {
+    pub key: K,
+    pub value: V,
+    /// Number of levels this node is linked into.
+    pub height: usize,
+    /// Successors on each level, of which `height` are allocated.
+    tower: [AtomicPtr<Node<K, V>>; 0],
+}
+
+impl<K: Ord + Eq, V> Node<K, V> {
+    /// Returns the layout of a node with the given height.
+    pub fn layout(height: usize) -> Layout {
+        let size = mem::offset_of!(Self, tower) + height * mem::size_of::<AtomicPtr<Self>>();
+        Layout::from_size_align(size, mem::align_of::<Self>()).unwrap()
+    }
+
+    /// Creates a new unlinked node returning its mutable pointer (unsafe).
+    pub unsafe fn new(arena: &Arena, key: K, value: V, height: usize) -> *mut Self {
+        let ptr = arena.alloc(Self::layout(height)) as *mut Self;
+
+        ptr::addr_of_mut!((*ptr).key).write(key);
+        ptr::addr_of_mut!((*ptr).value).write(value);
+        ptr::addr_of_mut!((*ptr).height).write(height);
+        let tower = ptr::addr_of_mut!((*ptr).tower) as *mut AtomicPtr<Self>;
+        for level in 0..height {
+            tower.add(level).write(AtomicPtr::new(ptr::null_mut()));
+        }
+        ptr
+    }
+
+    /// Gets the link to the successor on `level`.
+    pub unsafe fn next<'a>(ptr: *mut Self, level: usize) -> &'a AtomicPtr<Self> {
+        let tower = ptr::addr_of_mut!((*ptr).tower) as *mut AtomicPtr<Self>;
+        &*tower.add(level)
+    }
+}
+
+/// Implementations for fundamental skip list algorithms.
+impl<K: Ord + Eq, V> SkipList<K, V> {
+    /// Creates new instance.
+    pub fn new() -> Self {
+        Self {
+            head: std::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+            height: AtomicUsize::new(1),
+            seed: AtomicU64::new(0),
+            retired: Mutex::new(Vec::new()),
+            arena: Arena::new(),
+        }
+    }
+
+    /// Looks up the value of a key. This never blocks and is safe to run
+    /// alongside inserts.
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        unsafe {
+            let node = self.find_node(key);
+            if node.is_null() {
+                return None;
+            }
+            Some(&(*node).value)
+        }
+    }
+
+    /// Looks up the value of a key, returning a pointer into its node. The
+    /// value may only be written through the pointer while its writers are
+    /// kept apart from each other and from readers, e.g. by a lock held in
+    /// the value itself.
+    pub fn get_ptr(&self, key: &K) -> Option<*mut V> {
+        unsafe {
+            let node = self.find_node(key);
+            if node.is_null() {
+                return None;
+            }
+            Some(ptr::addr_of_mut!((*node).value))
+        }
+    }
+
+    /// Inserts a key-value pair unless the key exists, in which case the pair
+    /// is dropped. Returns the value held by the key afterwards.
+    ///
+    /// This may race with other inserts and lookups.
+    pub fn get_or_insert(&self, key: K, value: V) -> &V {
+        match unsafe { self.insert_node(key, value) } {
+            Ok(node) | Err(node) => unsafe { &(*node).value },
+        }
+    }
+
+    /// Ordered iterator over all pairs.
+    pub fn iter_ref(&self) -> SkipListIterator<K, V> {
+        self.iter_from(self.head[0].load(Ordering::Acquire))
+    }
+
+    fn iter_from(&self, node: *mut Node<K, V>) -> SkipListIterator<K, V> {
+        SkipListIterator { node }
+    }
+
+    /// Iterator over all pairs in descending order.
+    pub fn iter_rev(&self) -> SkipListReverseIterator<K, V> {
+        let mut cursor = self.cursor_from(None);
+        cursor.prev();
+        SkipListReverseIterator { cursor }
+    }
+
+    /// Cursor at the first node that is not less than `key`, or off the end
+    /// after the last node without a key.
+    fn cursor_from(&self, key: Option<&K>) -> SkipListCursor<K, V> {
+        unsafe {
+            let preds = self.preds_before(key);
+            SkipListCursor {
+                list: self,
+                node: self.link(preds[0], 0).load(Ordering::Acquire),
+                preds,
+            }
+        }
+    }
+
+    /// Gets the link from `pred` on `level`, where a null `pred` is the head.
+    unsafe fn link(&self, pred: *mut Node<K, V>, level: usize) -> &AtomicPtr<Node<K, V>> {
+        if pred.is_null() {
+            &self.head[level]
+        } else {
+            Node::next(pred, level)
+        }
+    }
+
+    /// Picks the height of a new node from a geometric distribution.
+    fn random_height(&self) -> usize {
+        // splitmix64 over a shared counter
+        let mut z = self
+            .seed
+            .fetch_add(0x9e3779b97f4a7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+
+        let mut height = 1;
+        while height < MAX_HEIGHT && z.is_multiple_of(BRANCHING) {
+            height += 1;
+            z /= BRANCHING;
+        }
+        height
+    }
+
+    /// Moves right from `pred` on `level` while the successor is less than
+    /// `key`, returning the last such node and its successor.
+    unsafe fn seek_level(
+        &self,
+        mut pred: *mut Node<K, V>,
+        key: &K,
+        level: usize,
+    ) -> (*mut Node<K, V>, *mut Node<K, V>) {
+        loop {
+            let next = self.link(pred, level).load(Ordering::Acquire);
+            if !next.is_null() && (*next).key < *key {
+                pred = next;
+            } else {
+                return (pred, next);
+            }
+        }
+    }
+
+    /// Finds where `key` belongs on every level, returning the first node
+    /// that is not less than `key`. Levels above the height in use are left
+    /// pointing to the head.
+    unsafe fn seek(
+        &self,
+        key: &K,
+        preds: &mut [*mut Node<K, V>; MAX_HEIGHT],
+        succs: &mut [*mut Node<K, V>; MAX_HEIGHT],
+    ) -> *mut Node<K, V> {
+        let mut pred = ptr::null_mut();
+        for level in (0..self.height.load(Ordering::Acquire)).rev() {
+            let (p, s) = self.seek_level(pred, key, level);
+            preds[level] = p;
+            succs[level] = s;
+            pred = p;
+        }
+        succs[0]
+    }
+
+    /// Finds the first node that is not less than `key`.
+    unsafe fn lower_bound_node(&self, key: &K) -> *mut Node<K, V> {
+        let mut pred = ptr::null_mut();
+        let mut succ = ptr::null_mut();
+        for level in (0..self.height.load(Ordering::Acquire)).rev() {
+            (pred, succ) = self.seek_level(pred, key, level);
+        }
+        // reloading the link may see nodes less than `key` linked meanwhile
+        succ
+    }
+
+    /// Finds the last node that is less than `key` on every level, or the
+    /// last node of all without a key. Null stands for no such node.
+    unsafe fn preds_before(&self, key: Option<&K>) -> [*mut Node<K, V>; MAX_HEIGHT] {
+        let mut preds = [ptr::null_mut(); MAX_HEIGHT];
+        let mut pred = ptr::null_mut();
+        for level in (0..self.height.load(Ordering::Acquire)).rev() {
+            loop {
+                let next = self.link(pred, level).load(Ordering::Acquire);
+                if !next.is_null() && key.is_none_or(|key| (*next).key < *key) {
+                    pred = next;
+                } else {
+                    break;
+                }
+            }
+            preds[level] = pred;
+        }
+        preds
+    }
+
+    /// Finds the node holding `key`, or null if there is none.
+    unsafe fn find_node(&self, key: &K) -> *mut Node<K, V> {
+        let node = self.lower_bound_node(key);
+        if !node.is_null() && (*node).key == *key {
+            node
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    /// Links a new node bottom-up, unless `key` exists. Returns the node now
+    /// holding `key`, which is `Ok` if it was linked by this call.
+    ///
+    /// A node is visible once it is linked on level 0, which is also the only
+    /// level where concurrent inserts of the same key may collide.
+    unsafe fn insert_node(&self, key: K, value: V) -> Result<*mut Node<K, V>, *mut Node<K, V>> {
+        let mut preds = [ptr::null_mut(); MAX_HEIGHT];
+        let mut succs = [ptr::null_mut(); MAX_HEIGHT];
+        let found = self.seek(&key, &mut preds, &mut succs);
+        if !found.is_null() && (*found).key == key {
+            return Err(found);
+        }
+
+        let height = self.random_height();
+        self.height.fetch_max(height, Ordering::AcqRel);
+        let node = Node::new(&self.arena, key, value, height);
+        for level in 0..height {
+            loop {
+                Node::next(node, level).store(succs[level], Ordering::Relaxed);
+                let link = self.link(preds[level], level);
+                if link
+                    .compare_exchange(succs[level], node, Ordering::Release, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    break;
+                }
+                // something got linked in between, so look again from there
+                let (pred, succ) = self.seek_level(preds[level], &(*node).key, level);
+                if level == 0 && !succ.is_null() && (*succ).key == (*node).key {
+                    // lost the race against another insert of the same key
+                    ptr::drop_in_place(node);
+                    return Err(succ);
+                }
+                preds[level] = pred;
+                succs[level] = succ;
+            }
+        }
+        Ok(node)
+    }
+}
+
+/// Additional (special) implementations for skip lists holding records.
+impl SkipList<ByteStream, KvEntry> {
+    /// Accesses iterator at the first key that is not less than `key`.
+    pub fn lower_bound_iter(&self, key: &[u8]) -> SkipListIterator<ByteStream, KvEntry> {
+        let key = ByteStream::from_slice(key);
+        self.iter_from(unsafe { self.lower_bound_node(&key) })
+    }
+
+    /// Accesses descending iterator at the last key that is not greater than
+    /// `key`.
+    pub fn seek_for_prev_iter(&self, key: &[u8]) -> SkipListReverseIterator<ByteStream, KvEntry> {
+        let key = ByteStream::from_slice(key);
+        let mut cursor = self.cursor_from(Some(&key));
+        if cursor.key() != Some(&key) {
+            cursor.prev();
+        }
+        SkipListReverseIterator { cursor }
+    }
+
+    /// Accesses cursor at the first key that is not less than `key`, or off
+    /// the end after the last key without one.
+    pub fn cursor(&self, key: Option<&[u8]>) -> SkipListCursor<ByteStream, KvEntry> {
+        let key = key.map(ByteStream::from_slice);
+        self.cursor_from(key.as_ref())
+    }
+
+    /// Access full-scan iterator.
+    pub fn iter_mut(&mut self) -> SkipListIterator<ByteStream, KvEntry> {
+        self.iter_ref()
+    }
+
+    /// Copies a record into the arena. The copy must not outlive the list.
+    pub fn arena_record(&self, record: &KvData) -> KvData {
+        match record {
+            KvData::Tombstone { cached } => KvData::Tombstone { cached: *cached },
+            KvData::Value { cached, value } => KvData::Value {
+                cached: *cached,
+                value: self.arena_bytes(value.as_ref()),
+            },
+        }
+    }
+
+    fn arena_bytes(&self, bytes: &[u8]) -> ByteStream {
+        unsafe { ByteStream::from_arena(self.arena.copy_bytes(bytes)) }
+    }
+
+    /// Insert with internal replacing, which keeps the metadata of an existing
+    /// entry. The record is copied into the arena, and so is the key unless
+    /// it exists already. Returns the entry holding `key`.
+    pub fn insert_internal(&mut self, key: &[u8], record: &KvData) -> &mut KvEntry {
+        let record = self.arena_record(record);
+        unsafe {
+            let node = self.find_node(&ByteStream::from_slice(key));
+            if !node.is_null() {
+                (*node).value.record = record;
+                return &mut (*node).value;
+            }
+            let key = self.arena_bytes(key);
+            match self.insert_node(key, KvEntry::new(record)) {
+                Ok(node) | Err(node) => &mut (*node).value,
+            }
+        }
+    }
+
+    /// Inserts a record under a key unless it exists, copying both into the
+    /// arena. The new entry is given the sequence number `seq`. Returns a
+    /// pointer to the entry holding `key`, which is `Ok` if it was newly
+    /// inserted. The same rules as for [`SkipList::get_ptr`] apply to writing
+    /// through it, and existing records are replaced with
+    /// [`SkipList::replace`].
+    ///
+    /// This may race with other inserts and lookups. Copies made by an insert
+    /// losing a race stay in the arena.
+    pub fn try_insert(
+        &self,
+        key: &[u8],
+        record: &KvData,
+        seq: u64,
+    ) -> Result<*mut KvEntry, *mut KvEntry> {
+        unsafe {
+            let node = self.find_node(&ByteStream::from_slice(key));
+            if !node.is_null() {
+                return Err(ptr::addr_of_mut!((*node).value));
+            }
+            let mut entry = KvEntry::new(self.arena_record(record));
+            entry.seq = seq;
+            match self.insert_node(self.arena_bytes(key), entry) {
+                Ok(node) => Ok(ptr::addr_of_mut!((*node).value)),
+                Err(node) => Err(ptr::addr_of_mut!((*node).value)),
+            }
+        }
+    }
+
+    /// Replaces the record of an existing key, copying it into the arena, and
+    /// gives the entry the sequence number `seq`. Returns the new entry, or
+    /// `None` if the key does not exist.
+    ///
+    /// The entry is not written in place. A new node taking over the key
+    /// bytes and the metadata of the old one is linked in its stead, while
+    /// the old node keeps its links, so that readers holding it go on reading
+    /// the old record undisturbed.
+    ///
+    /// This may race with lookups and iterators, but not with inserts or other
+    /// replacements.
+    pub unsafe fn replace(&self, key: &[u8], record: &KvData, seq: u64) -> Option<*mut KvEntry> {
+        let mut preds = [ptr::null_mut(); MAX_HEIGHT];
+        let mut succs = [ptr::null_mut(); MAX_HEIGHT];
+        let old = self.seek(&ByteStream::from_slice(key), &mut preds, &mut succs);
+        if old.is_null() || (*old).key.as_ref() != key {
+            return None;
+        }
+        let mut entry = KvEntry::new(self.arena_record(record));
+        entry.ts_read = (*old).value.ts_read;
+        entry.ts_write = (*old).value.ts_write;
+        entry.seq = seq;
+        // key bytes of the old node stay in place as long as the list does
+        let key = ByteStream::from_arena((*old).key.as_ref());
+        let height = (*old).height;
+        let node = Node::new(&self.arena, key, entry, height);
+        for level in 0..height {
+            let next = Node::next(old, level).load(Ordering::Acquire);
+            Node::next(node, level).store(next, Ordering::Relaxed);
+        }
+        // with no inserts around, the old node follows `preds` on its levels
+        for (level, &pred) in preds.iter().enumerate().take(height) {
+            self.link(pred, level).store(node, Ordering::Release);
+        }
+        self.retired.lock().unwrap().push(old);
+        Some(ptr::addr_of_mut!((*node).value))
+    }
+}
+
+impl<K: Ord + Eq, V> Drop for SkipList<K, V> {
+    fn drop(&mut self) {
+        // memory of nodes goes away along with the arena
+        if !mem::needs_drop::<K>() && !mem::needs_drop::<V>() {
+            return;
+        }
+        let mut p = self.head[0].load(Ordering::Relaxed);
+        while !p.is_null() {
+            unsafe {
+                let next = Node::next(p, 0).load(Ordering::Relaxed);
+                ptr::drop_in_place(p);
+                p = next;
+            }
+        }
+        for &node in self.retired.get_mut().unwrap().iter() {
+            unsafe { ptr::drop_in_place(node) };
+        }
+    }
+}
+
+/// Iterator following level 0 of a skip list.
+pub struct SkipListIterator<K: Ord + Eq, V> {
+    /// Pointer to next item.
+    node: *mut Node<K, V>,
+}
+
+/// Only reads nodes of a list, which is shared across threads.
+unsafe impl<K: Ord + Eq + Send + Sync, V: Send + Sync> Send for SkipListIterator<K, V> {}
+
+impl<K: Ord + Eq, V> Iterator for SkipListIterator<K, V> {
+    type Item = SkipListPointer<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.node.is_null() {
+            return None;
+        }
+        let current = self.node;
+        self.node = unsafe { Node::next(current, 0).load(Ordering::Acquire) };
+        Some(SkipListPointer { node: current })
+    }
+}
+
+/// Iterator walking a skip list backwards with a [`SkipListCursor`].
+pub struct SkipListReverseIterator<K: Ord + Eq, V> {
+    /// Cursor at the next item.
+    cursor: SkipListCursor<K, V>,
+}
+
+impl<K: Ord + Eq, V> Iterator for SkipListReverseIterator<K, V> {
+    type Item = SkipListPointer<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.cursor.current()?;
+        self.cursor.prev();
+        Some(current)
+    }
+}
+
+/// Position in a skip list that moves both ways, which is either at a node or
+/// off one end of the list.
+///
+/// Nodes only link to their successors. The cursor keeps the last node before
+/// its position on every level, from which the predecessor is found by moving
+/// right on the lower levels, rather than by searching from the top level.
+pub struct SkipListCursor<K: Ord + Eq, V> {
+    list: *const SkipList<K, V>,
+
+    /// Node the cursor is at, or null off either end.
+    node: *mut Node<K, V>,
+
+    /// Last node before the position on every level, null for the head.
+    preds: [*mut Node<K, V>; MAX_HEIGHT],
+}
+
+/// Only reads nodes of a list, which is shared across threads.
+unsafe impl<K: Ord + Eq + Send + Sync, V: Send + Sync> Send for SkipListCursor<K, V> {}
+
+impl<K: Ord + Eq, V> SkipListCursor<K, V> {
+    /// Points to the pair the cursor is at.
+    pub fn current(&self) -> Option<SkipListPointer<K, V>> {
+        match self.node.is_null() {
+            true => None,
+            false => Some(SkipListPointer { node: self.node }),
+        }
+    }
+
+    /// Accesses the key the cursor is at.
+    pub fn key(&self) -> Option<&K> {
+        match self.node.is_null() {
+            true => None,
+            false => Some(unsafe { &(*self.node).key }),
+        }
+    }
+
+    /// Moves to the next node. Off the start of the list, this is the first
+    /// node.
+    pub fn next(&mut self) {
+        unsafe {
+            if self.node.is_null() {
+                // off either end, the position is right after the last node
+                // before it
+                self.node = (*self.list).link(self.preds[0], 0).load(Ordering::Acquire);
+                return;
+            }
+            let node = self.node;
+            self.preds[..(*node).height].fill(node);
+            self.node = Node::next(node, 0).load(Ordering::Acquire);
+        }
+    }
+
+    /// Moves to the previous node. Off the end of the list, this is the last
+    /// node.
+    pub fn prev(&mut self) {
+        let target = self.preds[0];
+        self.node = target;
+        if target.is_null() {
+            self.preds = [ptr::null_mut(); MAX_HEIGHT];
+            return;
+        }
+        // nodes before the target are only to be found on the levels where
+        // the target is the last node before the position
+        let list = unsafe { &*self.list };
+        let key = unsafe { &(*target).key };
+        let stale = self
+            .preds
+            .iter()
+            .take_while(|pred| **pred == target)
+            .count();
+        for level in (0..stale).rev() {
+            let start = self
+                .preds
+                .get(level + 1)
+                .copied()
+                .unwrap_or(ptr::null_mut());
+            self.preds[level] = unsafe { list.seek_level(start, key, level).0 };
+        }
+    }
+}
+
+/// Points to a key-value pair inside a skip list that may be shared, so that
+/// it only allows reading the pair.
+pub struct SkipListPointer<K: Ord + Eq, V> {
+    node: *const Node<K, V>,
+}
+
+/// Only reads nodes of a list, which is shared across threads.
+unsafe impl<K: Ord + Eq + Send + Sync, V: Send + Sync> Send for SkipListPointer<K, V> {}
+
+impl<K: Ord + Eq, V> SkipListPointer<K, V> {
+    /// Accesses the pointed key.
+    pub fn key(&self) -> &K {
+        unsafe { &(*self.node).key }
+    }
+
+    /// Accesses the pointed key-value pair.
+    pub fn pair(&self) -> (&K, &V) {
+        unsafe { (&(*self.node).key, &(*self.node).value) }
+    }
+    /// Turns a pointer obtained through exclusive access to the list into a
+    /// writable one.
+    fn writable(self) -> MemTablePointer<K, V> {
+        let node = self.node as *mut Node<K, V>;
+        unsafe { MemTablePointer::new(&(*node).key, &mut (*node).value) }
+    }
+}
+
+impl KvPointer for SkipListPointer<ByteStream, KvEntry> {
+    fn key(&self) -> &[u8] {
+        SkipListPointer::key(self).as_ref()
+    }
+
+    fn value(&self) -> KvDataRef {
+        let value = unsafe { &(*self.node).value };
+        match &value.record {
+            KvData::Tombstone { cached } => KvDataRef::Tombstone { cached: *cached },
+            KvData::Value { cached, value } => KvDataRef::Value {
+                cached: *cached,
+                value: value.as_ref(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SkipList, SkipListCursor};
+    use crate::memtable::MemTable;
+    use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn concurrent_inserts() {
+        let list = SkipList::<u64, u64>::new();
+        let threads = 8_u64;
+        let scale = 20000_u64;
+
+        std::thread::scope(|s| {
+            for t in 0..threads {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..scale {
+                        // every key is raced for by two threads
+                        let key = (i * 7919 + t / 2 * scale) % (scale * threads / 2);
+                        let value = list.get_or_insert(key, key * 2 + 1);
+                        assert_eq!(*value, key * 2 + 1);
+                        assert_eq!(list.get_ref(&key), Some(&(key * 2 + 1)));
+                    }
+                });
+            }
+        });
+
+        let keys: Vec<u64> = list.iter_ref().map(|p| *p.key()).collect();
+        let expected: BTreeSet<u64> = (0..threads / 2)
+            .flat_map(|t| (0..scale).map(move |i| (i * 7919 + t * scale) % (scale * threads / 2)))
+            .collect();
+        assert_eq!(keys, expected.into_iter().collect::<Vec<_>>());
+
+        let mut list = list;
+        assert!(list.insert(0, 0).is_some());
+        assert_eq!(list.get(&0), Some(&mut 0));
+    }
+
+    #[test]
+    fn overwrites_keep_keys() {
+        let mut list = SkipList::<ByteStream, KvEntry>::new();
+        let key = [b'k'; 512];
+        list.insert_internal(&key, &KvData::Tombstone { cached: false });
+        let usage = list.approximate_memory_usage();
+        for _ in 0..1000 {
+            list.insert_internal(&key, &KvData::Tombstone { cached: false });
+        }
+        assert_eq!(list.approximate_memory_usage(), usage);
+        assert_eq!(list.iter_ref().count(), 1);
+    }
+
+    #[test]
+    fn concurrent_try_inserts() {
+        let list = SkipList::<ByteStream, KvEntry>::new();
+        let inserted: usize = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    let list = &list;
+                    s.spawn(move || {
+                        (0..1000)
+                            .filter(|i| {
+                                let key = format!("key-{i:04}");
+                                let record = KvData::Tombstone { cached: false };
+                                list.try_insert(key.as_bytes(), &record, 0).is_ok()
+                            })
+                            .count()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+        assert_eq!(inserted, 1000);
+        assert_eq!(list.iter_ref().count(), 1000);
+    }
+
+    #[test]
+    fn replacements_keep_readers() {
+        let list = SkipList::<ByteStream, KvEntry>::new();
+        let value = |v: &[u8]| KvData::Value {
+            cached: false,
+            value: ByteStream::from_slice(v),
+        };
+        for i in 0..100 {
+            let key = format!("key-{i:04}");
+            assert!(list.try_insert(key.as_bytes(), &value(b"old"), 1).is_ok());
+        }
+
+        // readers at the replaced entry keep the old record
+        let mut cursor = list.cursor(Some(b"key-0050"));
+        let held = cursor.current().unwrap();
+        assert!(unsafe { list.replace(b"key-0050", &value(b"new"), 2) }.is_some());
+        assert!(unsafe { list.replace(b"key-0100", &value(b"new"), 2) }.is_none());
+        assert!(matches!(
+            held.value(),
+            KvDataRef::Value { value: b"old", .. }
+        ));
+        let entry = list.get_ref(&ByteStream::from_slice(b"key-0050")).unwrap();
+        assert!(
+            entry.seq == 2
+                && matches!(&entry.record, KvData::Value { value, .. } if value.as_ref() == b"new")
+        );
+
+        // moving on from the old node leads back into the list
+        cursor.next();
+        assert_eq!(cursor.key().unwrap().as_ref(), b"key-0051");
+        cursor.prev();
+        cursor.prev();
+        assert_eq!(cursor.key().unwrap().as_ref(), b"key-0049");
+        assert_eq!(list.iter_ref().count(), 100);
+    }
+
+    #[test]
+    fn iterates_backwards() {
+        let mut list = SkipList::<ByteStream, KvEntry>::new();
+        for i in (0..1000).step_by(2) {
+            let key = format!("key-{i:04}");
+            list.insert_internal(key.as_bytes(), &KvData::Tombstone { cached: false });
+        }
+
+        let forward: Vec<Vec<u8>> = list.iter_ref().map(|p| p.key().as_ref().to_vec()).collect();
+        let mut backward: Vec<Vec<u8>> =
+            list.iter_rev().map(|p| p.key().as_ref().to_vec()).collect();
+        backward.reverse();
+        assert_eq!(backward, forward);
+
+        let prev = |key: &[u8]| -> Vec<Vec<u8>> {
+            let iter = list.seek_for_prev_iter(key);
+            iter.take(2).map(|p| p.key().as_ref().to_vec()).collect()
+        };
+        assert_eq!(prev(b"key-0500"), [b"key-0500", b"key-0498"]);
+        assert_eq!(prev(b"key-0501"), [b"key-0500", b"key-0498"]);
+        assert_eq!(prev(b"key-9999"), [b"key-0998", b"key-0996"]);
+        assert!(prev(b"key").is_empty());
+
+        // cursors turn around anywhere, and come back from either end
+        let key = |cursor: &SkipListCursor<ByteStream, KvEntry>| {
+            cursor.key().map(|key| key.as_ref().to_vec())
+        };
+        let mut cursor = list.cursor(Some(b"key-0501"));
+        assert_eq!(key(&cursor).unwrap(), b"key-0502");
+        cursor.prev();
+        assert_eq!(key(&cursor).unwrap(), b"key-0500");
+        cursor.next();
+        cursor.next();
+        assert_eq!(key(&cursor).unwrap(), b"key-0504");
+        let mut walked = Vec::new();
+        while let Some(found) = key(&cursor) {
+            walked.push(found);
+            cursor.prev();
+        }
+        walked.reverse();
+        assert_eq!(walked[..], forward[..=252]);
+        cursor.next();
+        assert_eq!(key(&cursor).unwrap(), b"key-0000");
+        let mut cursor = list.cursor(None);
+        assert!(key(&cursor).is_none());
+        cursor.prev();
+        assert_eq!(key(&cursor).unwrap(), b"key-0998");
+    }
+}
@@ -1,11 +1,14 @@
-use crate::memtable::MemTable;
+use crate::memtable::{MemTable, MemTableIterator, MemTablePointer};
 use std::alloc::{alloc, dealloc, Layout};
+use std::mem;
 use std::ptr;
 
 /// Splay tree.
 pub struct SplayTree<K: Ord + Eq, V> {
     /// Pointer to tree root.
     root: *mut Node<K, V>,
+    /// A total of `length` nodes are in this tree.
+    length: usize,
 }
 
 /// Access points for universal trait.
@@ -25,8 +28,76 @@ impl<K: Ord + Eq, V> MemTable<K, V> for SplayTree<K, V> {
         unsafe { self.insert_wrap(key, value) }
     }
 
-    fn remove(&mut self, _key: &K) -> Result<(), ()> {
-        Err(())
+    fn remove(&mut self, key: &K) -> Result<(), ()> {
+        unsafe { self.remove_wrap(key) }
+    }
+
+    fn approximate_memory_usage(&self) -> usize {
+        self.length * mem::size_of::<Node<K, V>>()
+    }
+
+    fn iter(&mut self) -> MemTableIterator<'_, K, V> {
+        let mut p = self.root;
+        unsafe {
+            while !p.is_null() && !(*p).child[0].is_null() {
+                p = (*p).child[0];
+            }
+        }
+        Box::new(SplayTreeIterator { node: p })
+    }
+
+    fn lower_bound(&mut self, key: &K) -> MemTableIterator<'_, K, V> {
+        // iterating does not splay, so that the tree stays intact
+        let mut p = self.root;
+        let mut found = ptr::null_mut();
+        unsafe {
+            while !p.is_null() {
+                if (*p).key < *key {
+                    p = (*p).child[1];
+                } else {
+                    found = p;
+                    p = (*p).child[0];
+                }
+            }
+        }
+        Box::new(SplayTreeIterator { node: found })
+    }
+}
+
+/// In-order iterator that follows parent links.
+struct SplayTreeIterator<K: Ord + Eq, V> {
+    /// Pointer to next item.
+    node: *mut Node<K, V>,
+}
+
+impl<K: Ord + Eq, V> Iterator for SplayTreeIterator<K, V> {
+    type Item = MemTablePointer<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.node.is_null() {
+            return None;
+        }
+        let current = self.node;
+        unsafe {
+            let mut p = self.node;
+            if !(*p).child[1].is_null() {
+                // leftmost descendant of the right child
+                p = (*p).child[1];
+                while !(*p).child[0].is_null() {
+                    p = (*p).child[0];
+                }
+                self.node = p;
+            } else {
+                // first ancestor reached from its left side
+                let mut q = (*p).parent;
+                while !q.is_null() && p == (*q).child[1] {
+                    p = q;
+                    q = (*p).parent;
+                }
+                self.node = q;
+            }
+            Some(MemTablePointer::new(&(*current).key, &mut (*current).value))
+        }
     }
 }
 
@@ -48,8 +119,8 @@ impl<K: Ord + Eq, V> Node<K, V> {
         let layout = Self::layout();
         let ptr = alloc(layout) as *mut Self;
 
-        (*ptr).key = key;
-        (*ptr).value = value;
+        ptr::addr_of_mut!((*ptr).key).write(key);
+        ptr::addr_of_mut!((*ptr).value).write(value);
         (*ptr).parent = ptr::null_mut();
         (*ptr).child[0] = ptr::null_mut();
         (*ptr).child[1] = ptr::null_mut();
@@ -60,9 +131,7 @@ impl<K: Ord + Eq, V> Node<K, V> {
     /// Releases pointer.
     #[allow(dead_code)]
     pub unsafe fn drop(ptr: *mut Self) -> () {
-        drop(Box::from_raw(&mut (*ptr).key));
-        drop(Box::from_raw(&mut (*ptr).value));
-
+        ptr::drop_in_place(ptr);
         dealloc(ptr as *mut u8, Self::layout());
     }
 }
@@ -73,6 +142,7 @@ impl<K: Ord + Eq, V> SplayTree<K, V> {
     pub fn new() -> Self {
         Self {
             root: ptr::null_mut(),
+            length: 0,
         }
     }
 
@@ -165,6 +235,7 @@ impl<K: Ord + Eq, V> SplayTree<K, V> {
             } else if key < (*p).key {
                 if (*p).child[0] == ptr::null_mut() {
                     let ch = Node::new(key, value);
+                    self.length += 1;
                     (*p).child[0] = ch;
                     (*ch).parent = p;
                     self.splay(ch, ptr::null_mut());
@@ -174,6 +245,7 @@ impl<K: Ord + Eq, V> SplayTree<K, V> {
             } else {
                 if (*p).child[1] == ptr::null_mut() {
                     let ch = Node::new(key, value);
+                    self.length += 1;
                     (*p).child[1] = ch;
                     (*ch).parent = p;
                     self.splay(ch, ptr::null_mut());
@@ -183,7 +255,58 @@ impl<K: Ord + Eq, V> SplayTree<K, V> {
             }
         }
         // should insert to root
+        self.length += 1;
         self.root = Node::new(key, value);
         None
     }
+
+    /// Remove key-value pair from tree. The node is splayed to the root, and
+    /// the largest node of its left subtree joins both subtrees.
+    unsafe fn remove_wrap(&mut self, key: &K) -> Result<(), ()> {
+        let p = self.access(key);
+        if p.is_null() {
+            return Err(());
+        }
+        let (l, r) = ((*p).child[0], (*p).child[1]);
+        if l.is_null() {
+            self.root = r;
+            if !r.is_null() {
+                (*r).parent = ptr::null_mut();
+            }
+        } else {
+            // the largest node of `l` has no right child after splaying
+            (*l).parent = ptr::null_mut();
+            self.root = l;
+            let mut q = l;
+            while !(*q).child[1].is_null() {
+                q = (*q).child[1];
+            }
+            self.splay(q, ptr::null_mut());
+            (*q).child[1] = r;
+            if !r.is_null() {
+                (*r).parent = q;
+            }
+        }
+        Node::drop(p);
+        self.length -= 1;
+        Ok(())
+    }
+}
+
+impl<K: Ord + Eq, V> Drop for SplayTree<K, V> {
+    fn drop(&mut self) {
+        // splay trees may grow arbitrarily deep, so nodes are released
+        // without recursion
+        unsafe {
+            let mut stack = vec![self.root];
+            while let Some(p) = stack.pop() {
+                if !p.is_null() {
+                    stack.push((*p).child[0]);
+                    stack.push((*p).child[1]);
+                    Node::drop(p);
+                }
+            }
+        }
+        self.root = ptr::null_mut();
+    }
 }
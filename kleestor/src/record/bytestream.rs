@@ -2,32 +2,55 @@ use std::cmp::{min, Ordering};
 use std::hash::{Hash, Hasher};
 use std::simd::Simd;
 
-#[derive(Eq, Ord)]
 pub struct ByteStream {
-    data: Vec<u8>,
+    data: Storage,
 }
 
+/// Bytes are either owned or borrowed from an arena.
+enum Storage {
+    Owned(Vec<u8>),
+    Arena(*const [u8]),
+}
+
+/// Bytes in arenas are never modified.
+unsafe impl Send for ByteStream {}
+unsafe impl Sync for ByteStream {}
+
 impl ByteStream {
     pub fn new() -> Self {
-        Self { data: vec![] }
+        Self::from_vec(vec![])
     }
 
     pub fn from_vec(bytes: Vec<u8>) -> Self {
-        Self { data: bytes }
+        Self {
+            data: Storage::Owned(bytes),
+        }
     }
 
     pub fn from_slice(bytes: &[u8]) -> Self {
+        Self::from_vec(Vec::from(bytes))
+    }
+
+    /// Refers to bytes held by an [`Arena`] instead of owning them.
+    ///
+    /// The stream must be dropped before the arena is.
+    ///
+    /// [`Arena`]: crate::memtable::arena::Arena
+    pub unsafe fn from_arena(bytes: &[u8]) -> Self {
         Self {
-            data: Vec::from(bytes),
+            data: Storage::Arena(bytes),
         }
     }
 
     pub fn as_ref(&self) -> &[u8] {
-        self.data.as_ref()
+        match &self.data {
+            Storage::Owned(bytes) => bytes.as_ref(),
+            Storage::Arena(bytes) => unsafe { &**bytes },
+        }
     }
 
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.as_ref().len()
     }
 
     /// Compare equality of a reference against another reference using SIMD.
@@ -68,7 +91,7 @@ impl ByteStream {
 
     /// Compare equality against another reference using SIMD.
     pub fn ref_eq(&self, other: &[u8]) -> bool {
-        Self::ref_2_eq(&self.data, other)
+        Self::ref_2_eq(self.as_ref(), other)
     }
 
     /// Compare against another reference using SIMD.
@@ -138,7 +161,7 @@ impl ByteStream {
 
 impl From<&ByteStream> for ByteStream {
     fn from(other: &ByteStream) -> Self {
-        Self::from_slice(&other.data)
+        Self::from_slice(other.as_ref())
     }
 }
 
@@ -157,6 +180,14 @@ impl PartialEq for ByteStream {
     }
 }
 
+impl Eq for ByteStream {}
+
+impl Ord for ByteStream {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_ref().cmp(other.as_ref())
+    }
+}
+
 impl PartialOrd for ByteStream {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         self.ref_partial_cmp(other.as_ref())
@@ -168,7 +199,7 @@ impl Hash for ByteStream {
     where
         H: Hasher,
     {
-        self.data.hash(state)
+        self.as_ref().hash(state)
     }
 }
 
@@ -17,15 +17,6 @@ pub trait KvPointer {
     /// freed while it is in the hands of another iterator.
     fn value(&self) -> KvDataRef;
 
-    /// Gets a mutable reference to the pointing value.
-    ///
-    /// This exposes the underlying implementation. Expect reference to
-    /// invalidate after pointer leaves scope.
-    ///
-    /// This function may be unimplemented since certain data structures are
-    /// opened readonly.
-    fn value_mut(&self) -> &mut KvEntry;
-
     /// You may wrap a custom 'Display' trait over this function.
     fn _fmt(&self, fmt: &mut Formatter) -> Result<(), std::fmt::Error> {
         let key = self.key();
@@ -64,3 +55,13 @@ pub trait KvPointer {
         buf
     }
 }
+
+/// Pointer into a data structure opened as read-write, which gives access to
+/// the entry behind the record as well.
+pub trait KvPointerMut: KvPointer {
+    /// Gets a mutable reference to the pointing value.
+    ///
+    /// This exposes the underlying implementation. Expect reference to
+    /// invalidate after pointer leaves scope.
+    fn value_mut(&mut self) -> &mut KvEntry;
+}
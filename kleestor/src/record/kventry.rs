@@ -14,6 +14,10 @@ pub struct KvEntry {
     /// Write timestamp, as defined in the TS-based MVCC.
     pub ts_write: u64,
 
+    /// Sequence number of the last write made to the entry outside
+    /// transactions, which orders writes racing for the same key.
+    pub seq: u64,
+
     /// Content of the entry.
     pub record: KvData,
 }
@@ -24,6 +28,7 @@ impl KvEntry {
             lock: Mutex::<()>::new(()),
             ts_read: 0_u64,
             ts_write: 0_u64,
+            seq: 0_u64,
             record,
         }
     }
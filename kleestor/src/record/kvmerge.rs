@@ -1,11 +1,17 @@
-use crate::record::{ByteStream, KvDataRef, KvEntry, KvPointer};
+use crate::record::{ByteStream, KvDataRef, KvPointer};
 use crate::utils;
 use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 
 /// Joins a list of [`Iterator<KvPointer>`] with priority. Earlier items have
 /// higher priority and will override all latter items with the same key.
 ///
-/// It must be guaranteed that keys are unique in every iterator.
+/// It must be guaranteed that keys are unique in every iterator, and that every
+/// iterator yields its keys in the order being merged in, which is ascending
+/// unless the iterator is made with [`new_descending`](Self::new_descending).
+///
+/// The next item of every iterator waits in a binary heap, so that each step
+/// takes O(log n) comparisons for n iterators.
 ///
 /// Writing is banned in this iterator.
 pub struct KvMergeIterator<'a, Pointer, Iter>
@@ -15,8 +21,10 @@ where
 {
     /// A list of all iterators to merge.
     iterators: Vec<Iter>,
-    /// Buffer that is used to compare and store new (key, value, index) pairs.
-    buffer: Vec<(&'a [u8], Pointer, usize)>,
+    /// Heap holding the next item of every iterator that is not used up.
+    heap: BinaryHeap<HeapItem<'a, Pointer>>,
+    /// Whether keys are merged from the greatest to the least.
+    descending: bool,
 }
 
 impl<'a, Pointer, Iter> KvMergeIterator<'a, Pointer, Iter>
@@ -26,70 +34,47 @@ where
 {
     /// Create merged iterator.
     pub fn new(iters: Vec<Iter>) -> Self {
+        Self::with_order(iters, false)
+    }
+
+    /// Create merged iterator over iterators yielding keys in descending
+    /// order, such as [`SSTableReader::iter_rev`](crate::sstable::reader::SSTableReader::iter_rev).
+    pub fn new_descending(iters: Vec<Iter>) -> Self {
+        Self::with_order(iters, true)
+    }
+
+    /// Iterators being merged, in the order they were given.
+    pub fn iterators(&self) -> &[Iter] {
+        &self.iterators
+    }
+
+    fn with_order(iters: Vec<Iter>, descending: bool) -> Self {
         let mut iter = Self {
+            heap: BinaryHeap::with_capacity(iters.len()),
             iterators: iters,
-            buffer: vec![],
+            descending,
         };
         for index in 0..iter.iterators.len() {
-            iter.binary_insert(index);
+            iter.push_next(index);
         }
         iter
     }
 
-    /// Insert a next item from the [`index`]-th iterator, until the new / next
-    /// item is no longer equal to any of the existing items in [`buffer`], or
-    /// no next items exist anymore.
+    /// Pushes the next item of the [`index`]-th iterator onto the heap, if it
+    /// has any left.
     ///
     /// It is required that no items belonging to the [`index`]-th iterator
-    /// still persist in the buffer.
-    fn binary_insert(&mut self, index: usize) -> () {
-        let iter = unsafe { utils::const_as_mut(&self.iterators[index]) };
-        loop {
-            let item = match iter.next() {
-                None => break,
-                Some(it) => it,
-            };
+    /// still persist in the heap.
+    fn push_next(&mut self, index: usize) {
+        if let Some(item) = self.iterators[index].next() {
             let key = unsafe { utils::reborrow_slice(item.key()) };
-
-            match self.binary_search(key) {
-                FoundIndex::Equal(insert_at) => {
-                    // higher priority
-                    if index < self.buffer[insert_at].2 {
-                        self.buffer[insert_at] = (key, item, index);
-                    } else {
-                        continue;
-                    }
-                }
-                FoundIndex::Less(insert_at) => {
-                    self.buffer.insert(insert_at, (key, item, index));
-                }
-            };
-            break;
-        }
-    }
-
-    /// Binary search for a location where we could insert a new item.
-    fn binary_search(&self, key: &[u8]) -> FoundIndex {
-        let len = self.buffer.len();
-        if len == 0 {
-            return FoundIndex::Less(0); // no keys
-        }
-        let mut left = 0_usize;
-        let mut right = len;
-
-        while left < right {
-            let mid = (left + right) >> 1;
-            if mid >= len {
-                return FoundIndex::Less(len); // key > max(all_keys)
-            }
-            match ByteStream::ref_2_partial_cmp(self.buffer[mid].0, key) {
-                Some(Ordering::Less) => left = mid + 1,
-                Some(Ordering::Greater) => right = mid,
-                Some(Ordering::Equal) => return FoundIndex::Equal(mid),
-                _ => panic!("expect ordering to return comparison"),
-            }
+            self.heap.push(HeapItem {
+                key,
+                item,
+                index,
+                descending: self.descending,
+            });
         }
-        FoundIndex::Less(left) // it is magically never equal
     }
 }
 
@@ -102,14 +87,22 @@ where
 
     fn next(&mut self) -> Option<Self::Item> {
         // really nothing to take or else
-        let (_key, item, index) = match self.buffer.len() {
-            0 => return None,
-            _ => self.buffer.remove(0),
-        };
+        let top = self.heap.pop()?;
+
+        // the same key from iterators of lower priority is shadowed, and
+        // comes right after in the heap
+        while let Some(shadowed) = self.heap.peek() {
+            if shadowed.key != top.key {
+                break;
+            }
+            let index = shadowed.index;
+            self.heap.pop();
+            self.push_next(index);
+        }
 
         // fill the next in and leave
-        self.binary_insert(index);
-        Some(KvMergePointer { _item: item })
+        self.push_next(top.index);
+        Some(KvMergePointer { _item: top.item })
     }
 }
 
@@ -130,32 +123,54 @@ impl<Pointer: KvPointer> KvPointer for KvMergePointer<Pointer> {
     fn value(&self) -> KvDataRef {
         self._item.value().clone()
     }
+}
 
-    /// Gets a mutable reference to the pointing value.
-    ///
-    /// This exposes the underlying implementation. Lifetime should be manually
-    /// ensured.
-    fn value_mut(&self) -> &mut KvEntry {
-        unimplemented!("kvmerge may only be used read-only");
+/// Item waiting in the heap of a [`KvMergeIterator`], along with the index of
+/// its iterator. Items that come first in the merge order are greater, and so
+/// are items of earlier iterators among equal keys.
+struct HeapItem<'a, Pointer: KvPointer> {
+    key: &'a [u8],
+    item: Pointer,
+    index: usize,
+    descending: bool,
+}
+
+impl<'a, Pointer: KvPointer> Ord for HeapItem<'a, Pointer> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let ordering = match ByteStream::ref_2_partial_cmp(self.key, other.key) {
+            Some(ordering) => ordering,
+            None => panic!("expect ordering to return comparison"),
+        };
+        let ordering = match self.descending {
+            true => ordering,
+            false => ordering.reverse(),
+        };
+        ordering.then_with(|| other.index.cmp(&self.index))
     }
 }
 
-/// Just an internal representation used for binary search within
-/// [`KvMergeIterator`].
-enum FoundIndex {
-    /// The discovered [`vec[index]`] is less than the queried key.
-    Less(usize),
-    /// The discovered [`vec[index]`] is equal to the queried key.
-    Equal(usize),
+impl<'a, Pointer: KvPointer> PartialOrd for HeapItem<'a, Pointer> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
+impl<'a, Pointer: KvPointer> PartialEq for HeapItem<'a, Pointer> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a, Pointer: KvPointer> Eq for HeapItem<'a, Pointer> {}
+
 #[cfg(test)]
 mod tests {
     use crate::memtable::rbtree::RBTree;
     use crate::memtable::MemTable;
-    use crate::record::{ByteStream, KvData, KvEntry, KvPointer};
+    use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer};
     use crate::sstable::reader::SSTableReader;
     use crate::sstable::writer::SSTableWriter;
+    use std::collections::BTreeMap;
     use std::io::Result;
     use std::path::PathBuf;
 
@@ -183,6 +198,107 @@ mod tests {
         }
     }
 
+    #[test]
+    fn merge_overlapping_ok() {
+        // run #5 overrides every other key of run #4
+        create_run(4, 1000, 1999, 1).unwrap();
+        create_run(5, 1000, 1999, 2).unwrap();
+        let runs = [read_run(5), read_run(4)];
+        let iters = runs.iter().map(|run| run.iter()).collect();
+        // every key is found exactly once, with the newest value
+        let merger = KvMergeIterator::new(iters);
+        let mut count = 0;
+        for (i, item) in (1000..=1999).zip(merger) {
+            let run = if i % 2 == 0 { 5 } else { 4 };
+            let value = format!("value-{i}-{i}-{i}-{i}-run-{run}");
+            assert_eq!(item.key(), format!("sample-key-{i}").as_bytes());
+            match item.value() {
+                KvDataRef::Value { value: found, .. } => assert_eq!(found, value.as_bytes()),
+                KvDataRef::Tombstone { .. } => panic!("unexpected tombstone"),
+            }
+            count += 1;
+        }
+        assert_eq!(count, 1000);
+        // clean objects
+        for id in 4..=5 {
+            let _ = std::fs::remove_file(get_file_path(id));
+        }
+    }
+
+    #[test]
+    fn merge_descending_ok() {
+        // run #7 overrides every other key of run #6
+        create_run(6, 1000, 1999, 1).unwrap();
+        create_run(7, 1000, 1999, 2).unwrap();
+        let runs = [read_run(7), read_run(6)];
+        // the latest 100 keys up to 1500, with the newest values
+        let iters = runs
+            .iter()
+            .map(|run| run.seek_for_prev(b"sample-key-1500"))
+            .collect();
+        let merger = KvMergeIterator::new_descending(iters);
+        let mut count = 0;
+        for (i, item) in (1401..=1500).rev().zip(merger.take(100)) {
+            let run = if i % 2 == 0 { 7 } else { 6 };
+            let value = format!("value-{i}-{i}-{i}-{i}-run-{run}");
+            assert_eq!(item.key(), format!("sample-key-{i}").as_bytes());
+            match item.value() {
+                KvDataRef::Value { value: found, .. } => assert_eq!(found, value.as_bytes()),
+                KvDataRef::Tombstone { .. } => panic!("unexpected tombstone"),
+            }
+            count += 1;
+        }
+        assert_eq!(count, 100);
+        // clean objects
+        for id in 6..=7 {
+            let _ = std::fs::remove_file(get_file_path(id));
+        }
+    }
+
+    #[test]
+    fn merge_many_ok() {
+        // 64 overlapping runs, each holding every few keys from some offset
+        let mut runs: Vec<RBTree<ByteStream, KvEntry>> = (0..64).map(|_| RBTree::new()).collect();
+        let mut expected = BTreeMap::<String, String>::new();
+        for (id, run) in runs.iter_mut().enumerate() {
+            for i in (id * 13 % 100..2000).step_by(id % 7 + 1) {
+                let key = format!("sample-key-{i:04}");
+                let value = format!("value-{i}-run-{id}");
+                // earlier runs take priority
+                expected.entry(key.clone()).or_insert(value.clone());
+                run.insert(
+                    ByteStream::from_slice(key.as_bytes()),
+                    KvEntry::new(KvData::Value {
+                        cached: false,
+                        value: ByteStream::from_slice(value.as_bytes()),
+                    }),
+                );
+            }
+        }
+        let read = |item: &dyn KvPointer| -> (String, String) {
+            let key = String::from_utf8(item.key().to_vec()).unwrap();
+            match item.value() {
+                KvDataRef::Value { value, .. } => (key, String::from_utf8(value.to_vec()).unwrap()),
+                KvDataRef::Tombstone { .. } => panic!("unexpected tombstone"),
+            }
+        };
+
+        let iters = runs.iter_mut().map(|run| run.iter()).collect();
+        let merged: Vec<(String, String)> = KvMergeIterator::new(iters)
+            .map(|item| read(&item))
+            .collect();
+        assert_eq!(merged, expected.clone().into_iter().collect::<Vec<_>>());
+
+        let iters = runs
+            .iter_mut()
+            .map(|run| run.iter().collect::<Vec<_>>().into_iter().rev())
+            .collect();
+        let merged: Vec<(String, String)> = KvMergeIterator::new_descending(iters)
+            .map(|item| read(&item))
+            .collect();
+        assert_eq!(merged, expected.into_iter().rev().collect::<Vec<_>>());
+    }
+
     fn get_file_path(id: u32) -> PathBuf {
         let mut tmp_dir = std::env::temp_dir();
         tmp_dir.push(format!("_kleestor_record_kvmerge_run_{id}.db"));
@@ -4,6 +4,6 @@ mod kventry;
 mod kvmerge;
 
 pub use bytestream::ByteStream;
-pub use iterator::KvPointer;
+pub use iterator::{KvPointer, KvPointerMut};
 pub use kventry::{KvData, KvDataRef, KvEntry};
 pub use kvmerge::KvMergeIterator;
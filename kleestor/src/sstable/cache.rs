@@ -0,0 +1,309 @@
+use papaya::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Capacity of the cache shared by the whole process.
+const GLOBAL_CAPACITY: usize = 8 << 20;
+
+/// Cache shared by the whole process.
+static GLOBAL: OnceLock<Arc<BlockCache>> = OnceLock::new();
+
+/// Smallest number of bytes a shard of a cache is made to hold.
+const MIN_SHARD_CAPACITY: usize = 512 << 10;
+
+/// Largest number of shards a cache is split into.
+const MAX_SHARDS: usize = 64;
+
+/// Identifies a block by the table it belongs to and its offset in there.
+type BlockKey = (u64, usize);
+
+/// Cache of decompressed data blocks, shared by any number of
+/// [`SSTableReader`](crate::sstable::reader::SSTableReader)s and bounded by
+/// the bytes of blocks it holds.
+///
+/// Blocks are spread over shards by their key, each with an equal part of the
+/// capacity, so that threads loading blocks mostly count and evict them in
+/// different places. Every shard keeps its blocks in a lock-free hash map, so
+/// that lookups and inserts never wait for each other and the cache may be
+/// used from async tasks. Lookups mark the block they find as referenced.
+/// Once a shard outgrows its capacity, the blocks not referenced since they
+/// were last passed are swept out, as in the CLOCK algorithm. Only one thread
+/// sweeps a shard at a time, and the others leave it to that thread rather
+/// than wait.
+pub struct BlockCache {
+    shards: Box<[Shard]>,
+
+    /// Bytes the cache may hold.
+    capacity: usize,
+}
+
+struct Shard {
+    blocks: HashMap<BlockKey, Slot>,
+
+    /// Bytes of all blocks cached in the shard.
+    usage: AtomicUsize,
+
+    /// Bytes the shard may hold.
+    capacity: usize,
+
+    /// Whether a thread is sweeping out blocks.
+    sweeping: AtomicBool,
+
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct Slot {
+    block: Arc<Vec<u8>>,
+
+    /// Whether the block was looked up since the last sweep passed it.
+    referenced: AtomicBool,
+}
+
+impl BlockCache {
+    /// Creates a cache holding up to about `capacity` bytes of blocks. Small
+    /// caches are not split, so that a shard holds more than a few blocks.
+    pub fn new(capacity: usize) -> Self {
+        let count = (capacity / MIN_SHARD_CAPACITY).clamp(1, MAX_SHARDS);
+        let count = match count.is_power_of_two() {
+            true => count,
+            false => count.next_power_of_two() / 2,
+        };
+        let shards = (0..count)
+            .map(|_| Shard {
+                blocks: HashMap::new(),
+                usage: AtomicUsize::new(0),
+                capacity: capacity / count,
+                sweeping: AtomicBool::new(false),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            })
+            .collect();
+        Self { shards, capacity }
+    }
+
+    /// Gets the cache shared by the whole process.
+    pub fn global() -> Arc<Self> {
+        GLOBAL
+            .get_or_init(|| Arc::new(Self::new(GLOBAL_CAPACITY)))
+            .clone()
+    }
+
+    /// Gets an identifier no other table shares with this process.
+    pub fn new_file_id() -> u64 {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Looks up a block, or loads it with `load` if it is not cached.
+    ///
+    /// Blocks are loaded outside of the map, so that lookups racing for the
+    /// same block may each load it. Only the first of them is cached.
+    pub fn get_or_load<E>(
+        &self,
+        file_id: u64,
+        offset: usize,
+        load: impl FnOnce() -> Result<Vec<u8>, E>,
+    ) -> Result<Arc<Vec<u8>>, E> {
+        let key = (file_id, offset);
+        let shard = self.shard(&key);
+        if let Some(slot) = shard.blocks.pin().get(&key) {
+            slot.referenced.store(true, Ordering::Relaxed);
+            shard.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(slot.block.clone());
+        }
+        shard.misses.fetch_add(1, Ordering::Relaxed);
+
+        let block = Arc::new(load()?);
+        let slot = Slot {
+            block: block.clone(),
+            referenced: AtomicBool::new(false),
+        };
+        // counted ahead, as a sweep may evict the block as soon as it is in
+        shard.usage.fetch_add(block.len(), Ordering::SeqCst);
+        if let Err(occupied) = shard.blocks.pin().try_insert(key, slot) {
+            shard.usage.fetch_sub(block.len(), Ordering::SeqCst);
+            return Ok(occupied.current.block.clone());
+        }
+        shard.sweep();
+        Ok(block)
+    }
+
+    /// Number of lookups that found their block cached.
+    pub fn hits(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.hits.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Number of lookups that had to load their block.
+    pub fn misses(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.misses.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Bytes of all cached blocks.
+    pub fn usage(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.usage.load(Ordering::SeqCst))
+            .sum()
+    }
+
+    /// Bytes the cache may hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Picks the shard of a block, mixing both parts of its key so that the
+    /// blocks of a table are spread as well as the tables themselves. The top
+    /// bits of the product are taken, as every bit of the key reaches them.
+    fn shard(&self, key: &BlockKey) -> &Shard {
+        let hash = (key.0 ^ (key.1 as u64).rotate_left(32)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let bits = self.shards.len().trailing_zeros();
+        &self.shards[hash.rotate_left(bits) as usize & (self.shards.len() - 1)]
+    }
+}
+
+impl Shard {
+    /// Evicts blocks until the shard fits its capacity again, unless another
+    /// thread is already doing so. The first pass over the blocks clears their
+    /// referenced marks, so two passes are enough to get below the capacity.
+    ///
+    /// Blocks cached while sweeping are left to the sweeping thread, which
+    /// checks the usage again after it is done.
+    fn sweep(&self) {
+        while self.usage.load(Ordering::SeqCst) > self.capacity {
+            if self.sweeping.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            let blocks = self.blocks.pin();
+            'sweep: for _ in 0..2 {
+                for (key, slot) in blocks.iter() {
+                    if self.usage.load(Ordering::SeqCst) <= self.capacity {
+                        break 'sweep;
+                    }
+                    if slot.referenced.swap(false, Ordering::Relaxed) {
+                        continue;
+                    }
+                    // the block may have been looked up in the meantime
+                    let unused =
+                        |_: &BlockKey, slot: &Slot| !slot.referenced.load(Ordering::Relaxed);
+                    if let Ok(Some((_, evicted))) = blocks.remove_if(key, unused) {
+                        self.usage.fetch_sub(evicted.block.len(), Ordering::SeqCst);
+                    }
+                }
+            }
+            self.sweeping.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlockCache;
+    use std::convert::Infallible;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn evicts_by_size() {
+        let cache = BlockCache::new(64 * 1024);
+        let load = |len: usize| move || Ok::<_, Infallible>(vec![0_u8; len]);
+
+        // the same block is loaded once
+        cache.get_or_load(1, 0, load(1024)).unwrap();
+        cache.get_or_load(1, 0, load(1024)).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(cache.usage(), 1024);
+
+        // blocks beyond the capacity are evicted, except those in use
+        for offset in 0..1000 {
+            cache.get_or_load(2, offset * 1024, load(1024)).unwrap();
+            cache.get_or_load(1, 0, load(1024)).unwrap();
+        }
+        assert!(cache.usage() <= cache.capacity());
+        assert!(cache.usage() > cache.capacity() / 2);
+        assert_eq!(cache.misses(), 1001);
+
+        // errors are passed on, and nothing is cached
+        let usage = cache.usage();
+        assert!(cache.get_or_load(3, 0, || Err(())).is_err());
+        assert_eq!(cache.usage(), usage);
+    }
+
+    #[test]
+    fn concurrent_lookups() {
+        let cache = BlockCache::new(1 << 20);
+        let load = || Ok::<_, Infallible>(vec![0_u8; 1024]);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for offset in 0..64 {
+                        cache.get_or_load(1, offset, load).unwrap();
+                    }
+                });
+            }
+        });
+
+        // every block is cached once, whoever loaded it
+        assert_eq!(cache.hits() + cache.misses(), 8 * 64);
+        assert_eq!(cache.usage(), 64 * 1024);
+        let misses = cache.misses();
+        for offset in 0..64 {
+            cache.get_or_load(1, offset, load).unwrap();
+        }
+        assert_eq!(cache.misses(), misses);
+    }
+
+    #[test]
+    fn concurrent_evictions() {
+        let cache = BlockCache::new(64 * 1024);
+        let load = || Ok::<_, Infallible>(vec![0_u8; 1024]);
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let cache = &cache;
+                s.spawn(move || {
+                    for offset in 0..256 {
+                        cache.get_or_load(t, offset, load).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(cache.misses(), 8 * 256);
+        assert!(cache.usage() <= cache.capacity());
+        assert!(cache.usage() > cache.capacity() / 2);
+    }
+
+    #[test]
+    fn sharded_evictions() {
+        let cache = BlockCache::new(4 << 20);
+        assert_eq!(cache.shards.len(), 8);
+        let load = || Ok::<_, Infallible>(vec![0_u8; 4096]);
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let cache = &cache;
+                s.spawn(move || {
+                    for offset in 0..1024 {
+                        cache.get_or_load(t, offset * 4096, load).unwrap();
+                    }
+                });
+            }
+        });
+
+        // every shard is filled and kept within its part of the capacity
+        assert_eq!(cache.misses(), 8 * 1024);
+        for shard in cache.shards.iter() {
+            assert!(shard.usage.load(Ordering::SeqCst) <= shard.capacity);
+            assert!(shard.usage.load(Ordering::SeqCst) > shard.capacity / 2);
+        }
+        assert!(cache.usage() <= cache.capacity());
+
+        // small caches are left whole
+        assert_eq!(BlockCache::new(64 * 1024).shards.len(), 1);
+        assert_eq!(BlockCache::new(3 << 20).shards.len(), 4);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::utils::varint::VarUint64;
+use std::io::{Error, ErrorKind, Result};
+
+/// Codecs data blocks may be compressed with. The discriminant is the block
+/// kind recorded in the trailer of each block.
+///
+/// A compressed block starts with the length of its uncompressed contents as
+/// a varuint64, followed by the output of the codec.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2,
+    Snappy = 3,
+}
+
+impl Compression {
+    /// Gets the codec of a block kind.
+    pub fn from_kind(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(Self::None),
+            1 => Some(Self::Lz4),
+            2 => Some(Self::Zstd),
+            3 => Some(Self::Snappy),
+            _ => None,
+        }
+    }
+
+    /// Compresses `block` into `output`. Returns `false` without touching
+    /// `output` if the codec does not make the block any smaller, in which
+    /// case it should be stored as is.
+    pub fn compress(&self, block: &[u8], output: &mut Vec<u8>) -> bool {
+        let payload = match self {
+            Self::None => return false,
+            Self::Lz4 => lz4_flex::block::compress(block),
+            Self::Zstd => match zstd::bulk::compress(block, 0) {
+                Ok(payload) => payload,
+                Err(_) => return false,
+            },
+            Self::Snappy => match snap::raw::Encoder::new().compress_vec(block) {
+                Ok(payload) => payload,
+                Err(_) => return false,
+            },
+        };
+
+        let mut len = [0_u8; 9];
+        let len_size = VarUint64::as_slice(block.len() as u64, &mut len);
+        if len_size + payload.len() >= block.len() {
+            return false;
+        }
+        output.clear();
+        output.extend_from_slice(&len[..len_size]);
+        output.extend_from_slice(&payload);
+        true
+    }
+
+    /// Restores the contents of a block compressed by [`compress`].
+    pub fn decompress(&self, block: &[u8]) -> Result<Vec<u8>> {
+        if let Self::None = self {
+            return Ok(block.to_vec());
+        }
+        let mut offset = 0_usize;
+        let len = VarUint64::read_and_seek(block, &mut offset, block.len())? as usize;
+        let payload = &block[offset..];
+
+        let contents = match self {
+            Self::None => unreachable!(),
+            Self::Lz4 => lz4_flex::block::decompress(payload, len)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+            Self::Zstd => zstd::bulk::decompress(payload, len),
+            Self::Snappy => snap::raw::Decoder::new()
+                .decompress_vec(payload)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+        }?;
+        if contents.len() != len {
+            return Err(Error::new(ErrorKind::InvalidData, "block length mismatch"));
+        }
+        Ok(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Compression;
+
+    #[test]
+    fn round_trips() {
+        let block = br#"{"tenant": "klee", "entity": "bomb", "count": 4}"#.repeat(64);
+        let mut output = Vec::new();
+
+        assert!(!Compression::None.compress(&block, &mut output));
+        for codec in [Compression::Lz4, Compression::Zstd, Compression::Snappy] {
+            assert!(codec.compress(&block, &mut output));
+            assert!(output.len() < block.len() / 4);
+            assert_eq!(codec.decompress(&output).unwrap(), block);
+            assert_eq!(Compression::from_kind(codec as u8), Some(codec));
+
+            // incompressible blocks are left alone
+            output.clear();
+            assert!(!codec.compress(b"klee", &mut output));
+            assert!(output.is_empty());
+        }
+    }
+}
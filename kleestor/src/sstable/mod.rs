@@ -1,19 +1,170 @@
+pub mod cache;
+pub mod compression;
+pub mod prefix;
+pub mod range;
 pub mod reader;
 pub mod writer;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+use std::io::{Error, ErrorKind, Result as IoResult};
+
+/// Magic ending format v1 tables, whose data section is one stream of records
+/// indexed every 50 keys. Their footer is the header block offset alone.
+const MAGIC_V1: u64 = 0x1145_1419_1981_fee1_u64;
+
+/// Magic ending tables with a versioned [`Footer`].
+const MAGIC: u64 = 0x1145_1419_1981_fee2_u64;
+
+/// Newest format version, written by [`SSTableWriter`](writer::SSTableWriter).
+/// Version 2 groups records into checksummed blocks.
+const FORMAT_VERSION: u32 = 2;
+
+/// Blocks are not checksummed, as in format v1.
+const CHECKSUM_NONE: u32 = 0;
+
+/// Blocks are checksummed with CRC-32C.
+const CHECKSUM_CRC32C: u32 = 1;
+
+/// Feature flag of tables which may contain compressed blocks.
+const FEATURE_COMPRESSION: u64 = 1 << 0;
+
+/// Every feature flag understood by the reader.
+const KNOWN_FEATURES: u64 = FEATURE_COMPRESSION;
+
+/// Data blocks are closed once they reach this many bytes.
+const BLOCK_SIZE: usize = 4096;
+
+/// Every block in a v2 table is followed by a 1-byte block kind and a CRC-32C
+/// checksum of the stored block and the kind byte. The kind is the
+/// [`Compression`](compression::Compression) the block is stored with.
+const BLOCK_TRAILER_SIZE: usize = 5;
+
+/// Filters are told apart by their metablock type. Type 2 is the fixed-size
+/// bloom filter of format v1 and never appears in v2 tables.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum MetaBlockType {
     Index = 1,
     BloomFilter = 2,
+    BlockedBloomFilter = 3,
+    XorFilter = 4,
+    PrefixFilter = 5,
+    RangeFilter = 6,
+    KeyRange = 7,
+    SizedBloomFilter = 8,
+}
+
+/// Fixed-size footer at the very end of a table, holding everything needed to
+/// tell whether and how the table can be read:
+///
+///   * Bytes 0 ~ 7: header block offset.
+///   * Bytes 8 ~ 15: feature flags.
+///   * Bytes 16 ~ 19: checksum type.
+///   * Bytes 20 ~ 23: format version.
+///   * Bytes 24 ~ 31: the magic.
+///
+/// All fields are little-endian. Format v1 tables predate this footer and are
+/// told apart by their magic.
+struct Footer {
+    header: usize,
+    features: u64,
+    checksum: u32,
+    version: u32,
+}
+
+impl Footer {
+    const SIZE: usize = 32;
+
+    fn encode(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0_u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&(self.header as u64).to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.features.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.version.to_le_bytes());
+        bytes[24..32].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes
+    }
+
+    /// Reads the footer of a table, rejecting tables written by a newer format
+    /// or relying on features this reader does not know of.
+    fn decode(region: &[u8]) -> IoResult<Self> {
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(region[offset..offset + 8].try_into().unwrap());
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(region[offset..offset + 4].try_into().unwrap());
+
+        if region.len() < 16 {
+            return Err(Error::new(ErrorKind::InvalidData, "table too short"));
+        }
+        let footer = match u64_at(region.len() - 8) {
+            MAGIC_V1 => Self {
+                header: u64_at(region.len() - 16) as usize,
+                features: 0,
+                checksum: CHECKSUM_NONE,
+                version: 1,
+            },
+            MAGIC if region.len() >= Self::SIZE => {
+                let offset = region.len() - Self::SIZE;
+                Self {
+                    header: u64_at(offset) as usize,
+                    features: u64_at(offset + 8),
+                    checksum: u32_at(offset + 16),
+                    version: u32_at(offset + 20),
+                }
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidData, "invalid magic")),
+        };
+
+        if footer.version > FORMAT_VERSION || footer.version < 1 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "table format version {} is not supported, expecting 1 to {FORMAT_VERSION}",
+                    footer.version
+                ),
+            ));
+        }
+        let checksum = match footer.version {
+            1 => CHECKSUM_NONE,
+            _ => CHECKSUM_CRC32C,
+        };
+        if footer.checksum != checksum {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported checksum type {}", footer.checksum),
+            ));
+        }
+        if footer.features & !KNOWN_FEATURES != 0 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "unsupported table features {:#x}",
+                    footer.features & !KNOWN_FEATURES
+                ),
+            ));
+        }
+        Ok(footer)
+    }
+}
+
+/// Location of a block within a table, not counting its trailer.
+#[derive(Clone, Copy)]
+struct BlockHandle {
+    offset: usize,
+    size: usize,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::reader::SSTableReader;
-    use super::writer::SSTableWriter;
+    use super::compression::Compression;
+    use super::prefix::PrefixExtractor;
+    use super::reader::{SSTableReader, SSTableReaderCursor};
+    use super::writer::{FilterPolicy, SSTableWriter, WriterOptions};
+    use crate::bloom::LegacyBloomFilter;
     use crate::memtable::rbtree::RBTree;
     use crate::memtable::MemTable;
-    use crate::record::{ByteStream, KvData, KvEntry};
+    use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer};
+    use crate::utils::varint::VarUint64;
+    use std::io::ErrorKind;
+    use std::ops::Bound::{Excluded, Included, Unbounded};
 
     /// Checks if the reader can successfully read index.
     #[test]
@@ -65,4 +216,598 @@ mod tests {
         // cleanup
         std::fs::remove_file(&tmp_dir).unwrap();
     }
+
+    /// Checks that records span many blocks and flipped bits are caught once
+    /// the block holding them is read.
+    #[test]
+    fn detects_corruption() {
+        let mut path = std::env::temp_dir();
+        path.push("_kleestor_sstable_detects_corruption.db");
+
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        for i in 0..1000 {
+            map.insert(
+                ByteStream::from_slice(format!("sample-key-{i:04}").as_bytes()),
+                KvEntry::new(KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(format!("value-{i}").repeat(8).as_bytes()),
+                }),
+            );
+        }
+        let file = std::fs::File::create(&path).unwrap();
+        SSTableWriter::new(file).write(map.iter_mut()).unwrap();
+
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(table.data_size() > super::BLOCK_SIZE * 4);
+        assert_eq!(table.iter().count(), 1000);
+        for i in [0, 499, 999] {
+            let key = format!("sample-key-{i:04}");
+            let value = format!("value-{i}").repeat(8);
+            match table.get(key.as_bytes()).unwrap() {
+                Some(KvData::Value { value: found, .. }) => assert!(found.ref_eq(value.as_bytes())),
+                _ => panic!("missing {key}"),
+            }
+        }
+        assert!(table.get(b"sample-key-1000").unwrap().is_none());
+        let last = table.seek(b"sample-key-0998").nth(1).unwrap();
+        assert_eq!(last.key(), b"sample-key-0999");
+        drop(table);
+
+        // flip a bit in the middle of the records
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[super::BLOCK_SIZE * 2] ^= 0x10;
+        std::fs::write(&path, &bytes).unwrap();
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+
+        // reading stops at the corrupt block, which lookups report
+        let mut iter = table.iter();
+        let intact: Vec<Vec<u8>> = iter.by_ref().map(|item| item.key().to_vec()).collect();
+        assert!(!intact.is_empty() && intact.len() < 1000);
+        assert_eq!(iter.status().unwrap_err().kind(), ErrorKind::InvalidData);
+        let mut iter = table.iter_rev();
+        assert!(iter.by_ref().count() < 1000 - intact.len());
+        assert_eq!(iter.status().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(table.get(b"sample-key-0000").unwrap().is_some());
+        let next = format!("sample-key-{:04}", intact.len());
+        match table.get(next.as_bytes()) {
+            Err(err) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+            Ok(_) => panic!("corrupt {next} read"),
+        }
+        drop(table);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Checks that compressed tables read back the same records.
+    #[test]
+    fn compressed_blocks() {
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        for i in 0..1000 {
+            let value = format!(r#"{{"tenant": "klee", "entity": "bomb", "id": {i}}}"#);
+            map.insert(
+                ByteStream::from_slice(format!("sample-key-{i:04}").as_bytes()),
+                KvEntry::new(KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(value.as_bytes()),
+                }),
+            );
+        }
+
+        let mut sizes = Vec::new();
+        for codec in [
+            Compression::None,
+            Compression::Lz4,
+            Compression::Zstd,
+            Compression::Snappy,
+        ] {
+            let mut path = std::env::temp_dir();
+            path.push(format!("_kleestor_sstable_compressed_blocks_{codec:?}.db"));
+            let file = std::fs::File::create(&path).unwrap();
+            SSTableWriter::with_options(
+                file,
+                WriterOptions {
+                    compression: codec,
+                    ..Default::default()
+                },
+            )
+            .write(map.iter_mut())
+            .unwrap();
+
+            let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+            let records = map.iter_mut().zip(table.iter());
+            for (expected, found) in records {
+                assert_eq!(expected.key(), found.key());
+                match (expected.value(), found.value()) {
+                    (KvDataRef::Value { value: l, .. }, KvDataRef::Value { value: r, .. }) => {
+                        assert_eq!(l, r)
+                    }
+                    _ => panic!("value mismatch"),
+                }
+            }
+            assert_eq!(table.iter().count(), 1000);
+            assert!(table.get(b"sample-key-0500").unwrap().is_some());
+            sizes.push(table.data_size());
+
+            drop(table);
+            std::fs::remove_file(&path).unwrap();
+        }
+        for size in &sizes[1..] {
+            assert!(*size < sizes[0] / 2);
+        }
+    }
+
+    /// Checks that tables are read back with every filter layout.
+    #[test]
+    fn filter_layouts() {
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        for i in 0..5000 {
+            map.insert(
+                ByteStream::from_slice(format!("sample-key-{i:04}").as_bytes()),
+                KvEntry::new(KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(b"klee"),
+                }),
+            );
+        }
+
+        for filter in [
+            FilterPolicy::Bloom,
+            FilterPolicy::BlockedBloom,
+            FilterPolicy::Xor,
+        ] {
+            let mut path = std::env::temp_dir();
+            path.push(format!("_kleestor_sstable_filter_layouts_{filter:?}.db"));
+            let file = std::fs::File::create(&path).unwrap();
+            SSTableWriter::with_options(
+                file,
+                WriterOptions {
+                    filter,
+                    ..Default::default()
+                },
+            )
+            .write(map.iter_mut())
+            .unwrap();
+
+            let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+            for i in 0..5000 {
+                assert!(table
+                    .get(format!("sample-key-{i:04}").as_bytes())
+                    .unwrap()
+                    .is_some());
+            }
+            let found = (0..5000)
+                .filter(|i| {
+                    table
+                        .get(format!("missing-key-{i:04}").as_bytes())
+                        .unwrap()
+                        .is_some()
+                })
+                .count();
+            assert_eq!(found, 0);
+
+            drop(table);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    /// Prefix scans yield exactly the keys with the prefix, and the prefix
+    /// filter rules out most prefixes not in the table.
+    #[test]
+    fn prefix_filter() {
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        for tenant in 0..50 {
+            for i in 0..40 {
+                map.insert(
+                    ByteStream::from_slice(format!("tenant-{tenant}/bomb/{i}").as_bytes()),
+                    KvEntry::new(KvData::Value {
+                        cached: false,
+                        value: ByteStream::from_slice(b"klee"),
+                    }),
+                );
+            }
+        }
+
+        let mut path = std::env::temp_dir();
+        path.push("_kleestor_sstable_prefix_filter.db");
+        let file = std::fs::File::create(&path).unwrap();
+        let extractor = PrefixExtractor::Delimited {
+            delimiter: b'/',
+            count: 1,
+        };
+        SSTableWriter::with_options(
+            file,
+            WriterOptions {
+                prefix_extractor: Some(extractor),
+                ..Default::default()
+            },
+        )
+        .write(map.iter_mut())
+        .unwrap();
+
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(table.prefix_scan(b"tenant-7/").count(), 40);
+        assert_eq!(table.prefix_scan(b"tenant-7/bomb/1").count(), 11);
+        assert_eq!(table.prefix_scan(b"tenant-1").count(), 40 * 11);
+        assert_eq!(table.prefix_scan(b"tenant-70/").count(), 0);
+
+        // prefixes too short for the extractor cannot be ruled out
+        assert!(table.may_contain_prefix(b"tenant-70"));
+        let found = (50..1050)
+            .filter(|t| table.may_contain_prefix(format!("tenant-{t}/").as_bytes()))
+            .count();
+        assert!(found < 50, "{found} false positives");
+
+        drop(table);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// The key range and the range filter rule out ranges without keys.
+    #[test]
+    fn range_filter() {
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        for i in (0..1000).chain(1500..2000).step_by(10) {
+            map.insert(
+                ByteStream::from_slice(format!("sample-key-{i:04}").as_bytes()),
+                KvEntry::new(KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(b"klee"),
+                }),
+            );
+        }
+
+        let mut path = std::env::temp_dir();
+        path.push("_kleestor_sstable_range_filter.db");
+        let file = std::fs::File::create(&path).unwrap();
+        SSTableWriter::new(file).write(map.iter_mut()).unwrap();
+
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let (min, max) = table.key_range().unwrap();
+        assert!(min.as_ref() == b"sample-key-0000" && max.as_ref() == b"sample-key-1990");
+
+        let range = |start: &str, end: &str| {
+            table.may_contain_range(Included(start.as_bytes()), Excluded(end.as_bytes()))
+        };
+        assert!(range("sample-key-0100", "sample-key-0101"));
+        assert!(range("sample-key-0101", "sample-key-0111"));
+        assert!(range("sample-key-1000", "sample-key-1600"));
+        assert!(!range("sample-key-1000", "sample-key-1400"));
+        assert!(!range("sample-key-2000", "sample-key-3000"));
+        assert!(!range("a", "sample-key-0000"));
+        assert!(table.may_contain_range(Unbounded, Unbounded));
+
+        drop(table);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// A table may be read from many threads at once.
+    #[test]
+    fn concurrent_reads() {
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        for i in 0..2000 {
+            map.insert(
+                ByteStream::from_slice(format!("sample-key-{i:04}").as_bytes()),
+                KvEntry::new(KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(format!("value-{i}").as_bytes()),
+                }),
+            );
+        }
+
+        let mut path = std::env::temp_dir();
+        path.push("_kleestor_sstable_concurrent_reads.db");
+        let file = std::fs::File::create(&path).unwrap();
+        let options = WriterOptions {
+            compression: Compression::Lz4,
+            ..Default::default()
+        };
+        SSTableWriter::with_options(file, options)
+            .write(map.iter_mut())
+            .unwrap();
+
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let table = &table;
+                s.spawn(move || {
+                    for i in (t..2000).step_by(4) {
+                        let key = format!("sample-key-{i:04}");
+                        match table.get(key.as_bytes()).unwrap() {
+                            Some(KvData::Value { value, .. }) => {
+                                assert!(value.as_ref() == format!("value-{i}").as_bytes())
+                            }
+                            _ => panic!("missing key {key}"),
+                        }
+                    }
+                });
+            }
+        });
+
+        drop(table);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Tables are read backwards across blocks, from the end or from any key.
+    #[test]
+    fn reverse_iteration() {
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        for i in (0..3000).step_by(2) {
+            map.insert(
+                ByteStream::from_slice(format!("sample-key-{i:04}").as_bytes()),
+                KvEntry::new(KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(format!("value-{i}").as_bytes()),
+                }),
+            );
+        }
+
+        let mut path = std::env::temp_dir();
+        path.push("_kleestor_sstable_reverse_iteration.db");
+        let file = std::fs::File::create(&path).unwrap();
+        let options = WriterOptions {
+            compression: Compression::Lz4,
+            ..Default::default()
+        };
+        SSTableWriter::with_options(file, options)
+            .write(map.iter_mut())
+            .unwrap();
+
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut forward: Vec<Vec<u8>> = table.iter().map(|item| item.key().to_vec()).collect();
+        let backward: Vec<Vec<u8>> = table.iter_rev().map(|item| item.key().to_vec()).collect();
+        forward.reverse();
+        assert_eq!(forward.len(), 1500);
+        assert_eq!(backward, forward);
+
+        // values are still those of their keys
+        let item = table.iter_rev().nth(10).unwrap();
+        match item.value() {
+            KvDataRef::Value { value, .. } => assert_eq!(value, b"value-2978"),
+            _ => panic!("unexpected tombstone"),
+        }
+
+        let prev = |key: &str| {
+            table
+                .seek_for_prev(key.as_bytes())
+                .next()
+                .map(|item| item.key().to_vec())
+        };
+        assert_eq!(prev("sample-key-1234").unwrap(), b"sample-key-1234");
+        assert_eq!(prev("sample-key-1235").unwrap(), b"sample-key-1234");
+        assert_eq!(prev("sample-key-9999").unwrap(), b"sample-key-2998");
+        assert_eq!(prev("sample-key-0000").unwrap(), b"sample-key-0000");
+        assert_eq!(prev("sample-key"), None);
+
+        // the keys before a key, from the key onwards
+        let found: Vec<Vec<u8>> = table
+            .seek_for_prev(b"sample-key-2001")
+            .map(|item| item.key().to_vec())
+            .collect();
+        assert_eq!(found.len(), 1001);
+        assert_eq!(found[..], forward[499..]);
+
+        // cursors turn around anywhere, and come back from either end
+        let key = |cursor: &SSTableReaderCursor| cursor.current().map(|item| item.key().to_vec());
+        let mut cursor = table.cursor(Some(b"sample-key-1235"));
+        assert_eq!(key(&cursor).unwrap(), b"sample-key-1236");
+        cursor.prev();
+        assert_eq!(key(&cursor).unwrap(), b"sample-key-1234");
+        cursor.next();
+        assert_eq!(key(&cursor).unwrap(), b"sample-key-1236");
+        let mut walked = Vec::new();
+        while let Some(found) = key(&cursor) {
+            walked.push(found);
+            cursor.prev();
+        }
+        assert_eq!(walked[..], forward[881..]);
+        cursor.next();
+        assert_eq!(key(&cursor).unwrap(), b"sample-key-0000");
+        let mut cursor = table.cursor(None);
+        assert!(key(&cursor).is_none());
+        cursor.prev();
+        assert_eq!(key(&cursor).unwrap(), b"sample-key-2998");
+        assert!(cursor.status().is_ok());
+
+        drop(table);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Keys handed out by iterators stay intact while the iterator reads on,
+    /// whether they are held on to or not.
+    #[test]
+    fn keeps_yielded_keys() {
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        for i in 0..2000 {
+            map.insert(
+                ByteStream::from_slice(format!("sample-key-{i:04}").as_bytes()),
+                KvEntry::new(KvData::Value {
+                    cached: false,
+                    value: ByteStream::from_slice(format!("value-{i}").as_bytes()),
+                }),
+            );
+        }
+
+        let mut path = std::env::temp_dir();
+        path.push("_kleestor_sstable_keeps_yielded_keys.db");
+        let file = std::fs::File::create(&path).unwrap();
+        SSTableWriter::new(file).write(map.iter_mut()).unwrap();
+
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let expected = |i: usize| format!("sample-key-{i:04}").into_bytes();
+
+        // every other entry is kept, the rest are dropped right away
+        let mut iter = table.seek(b"sample-key-0500");
+        let mut kept = Vec::new();
+        while let Some(item) = iter.next() {
+            assert!(item.key() == expected(500 + kept.len() * 2));
+            kept.push(item);
+            let Some(item) = iter.next() else { break };
+            assert!(item.key() == expected(499 + kept.len() * 2));
+        }
+        assert!(kept.len() == 750);
+        for (i, item) in kept.iter().enumerate() {
+            assert!(item.key() == expected(500 + i * 2));
+        }
+
+        // lookups probe past the keys before without losing their place
+        for i in (0..2000).step_by(7) {
+            assert!(table.get(&expected(i)).unwrap().is_some());
+        }
+        assert!(table.get(b"sample-key-0500a").unwrap().is_none());
+
+        drop(kept);
+        drop(table);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Builds a format v1 table by hand, prefix compressing all keys but the
+    /// first one, which is the only one indexed.
+    fn write_v1(path: &std::path::Path, records: &[(&[u8], &[u8])]) {
+        let varu64 = |bytes: &mut Vec<u8>, value: usize| {
+            let mut buf = [0_u8; 9];
+            let len = VarUint64::as_slice(value as u64, &mut buf);
+            bytes.extend_from_slice(&buf[..len]);
+        };
+        let mut bytes = Vec::<u8>::new();
+        let mut bloom = LegacyBloomFilter::new();
+        let mut last_key: &[u8] = b"";
+        for (key, value) in records {
+            let common = key.iter().zip(last_key).take_while(|(l, r)| l == r).count();
+            for len in [key.len(), common, value.len(), 0] {
+                varu64(&mut bytes, len);
+            }
+            bytes.extend_from_slice(&key[common..]);
+            bytes.extend_from_slice(value);
+            bloom.insert(key);
+            last_key = key;
+        }
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+
+        let index = bytes.len();
+        for value in [1, 0] {
+            varu64(&mut bytes, value);
+        }
+        let filter = bytes.len();
+        varu64(&mut bytes, bloom.size());
+        bytes.extend_from_slice(bloom.as_slice());
+        let header = bytes.len();
+        for value in [2, 1, index, 2, filter] {
+            varu64(&mut bytes, value);
+        }
+        bytes.extend_from_slice(&(header as u64).to_le_bytes());
+        bytes.extend_from_slice(&super::MAGIC_V1.to_le_bytes());
+        std::fs::write(path, &bytes).unwrap();
+    }
+
+    /// Checks that format v1 tables stay readable while tables from a newer
+    /// format are rejected.
+    #[test]
+    fn negotiates_format() {
+        let mut path = std::env::temp_dir();
+        path.push("_kleestor_sstable_negotiates_format.db");
+
+        let keys: Vec<String> = (0..200).map(|i| format!("sample-key-{i:03}")).collect();
+        let records: Vec<(&[u8], &[u8])> = keys
+            .iter()
+            .map(|key| (key.as_bytes(), &key.as_bytes()[7..]))
+            .collect();
+        write_v1(&path, &records);
+
+        let table = SSTableReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let found: Vec<Vec<u8>> = table.iter().map(|item| item.key().to_vec()).collect();
+        assert_eq!(
+            found,
+            records
+                .iter()
+                .map(|(key, _)| key.to_vec())
+                .collect::<Vec<_>>()
+        );
+        match table.get(b"sample-key-150").unwrap() {
+            Some(KvData::Value { value, .. }) => assert!(value.ref_eq(b"key-150")),
+            _ => panic!("missing sample-key-150"),
+        }
+        assert_eq!(
+            table.seek(b"sample-key-0995").next().unwrap().key(),
+            b"sample-key-100"
+        );
+        assert_eq!(
+            table
+                .seek_for_prev(b"sample-key-0995")
+                .next()
+                .unwrap()
+                .key(),
+            b"sample-key-099"
+        );
+        assert_eq!(table.iter_rev().count(), records.len());
+        drop(table);
+
+        // a table from the current format claiming to be from the future
+        let mut map = RBTree::<ByteStream, KvEntry>::new();
+        map.insert(
+            ByteStream::from_slice(b"sample-key"),
+            KvEntry::new(KvData::Tombstone { cached: false }),
+        );
+        let file = std::fs::File::create(&path).unwrap();
+        SSTableWriter::new(file).write(map.iter_mut()).unwrap();
+        assert!(SSTableReader::new(std::fs::File::open(&path).unwrap()).is_ok());
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let version = bytes.len() - 12;
+        bytes[version..version + 4].copy_from_slice(&(super::FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let err = SSTableReader::new(std::fs::File::open(&path).unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Checks that torn format v1 tables are rejected instead of being read
+    /// past their ends.
+    #[test]
+    fn rejects_truncated_v1() {
+        let mut path = std::env::temp_dir();
+        path.push("_kleestor_sstable_rejects_truncated_v1.db");
+
+        let keys: Vec<String> = (0..20).map(|i| format!("sample-key-{i:02}")).collect();
+        let records: Vec<(&[u8], &[u8])> = keys
+            .iter()
+            .map(|key| (key.as_bytes(), &key.as_bytes()[7..]))
+            .collect();
+        write_v1(&path, &records);
+        let bytes = std::fs::read(&path).unwrap();
+        let (body, magic) = bytes.split_at(bytes.len() - 8);
+        let header = u64::from_le_bytes(body[body.len() - 8..].try_into().unwrap()) as usize;
+        let body = &body[..body.len() - 8];
+
+        let check = |table: &[u8]| {
+            std::fs::write(&path, table).unwrap();
+            match SSTableReader::new(std::fs::File::open(&path).unwrap()) {
+                Err(err) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+                Ok(_) => panic!("torn table of {} bytes read", table.len()),
+            }
+        };
+        // the bloom filter takes most of the table, so it is only cut sparsely
+        assert!(header > 512);
+        let cuts = (0..512)
+            .chain((512..header).step_by(4096))
+            .chain(header..body.len());
+        for len in cuts {
+            // cut the header, or the blocks it points to
+            let mut table = body[..len].to_vec();
+            table.extend_from_slice(&(header as u64).to_le_bytes());
+            table.extend_from_slice(magic);
+            check(&table);
+
+            if len < header {
+                let mut table = body[..len].to_vec();
+                table.extend_from_slice(&body[header..]);
+                table.extend_from_slice(&(len as u64).to_le_bytes());
+                table.extend_from_slice(magic);
+                check(&table);
+            }
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
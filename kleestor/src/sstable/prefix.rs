@@ -0,0 +1,76 @@
+/// Takes the prefix of a key that prefix filters are built on. Keys without
+/// such a prefix are left out of the filter.
+///
+/// A scan over some prefix may only consult the filter if the extractor finds
+/// a prefix in the scanned prefix itself, which is then shared by all keys the
+/// scan could yield.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PrefixExtractor {
+    /// The first bytes of a key, up to the given length.
+    Fixed(usize),
+
+    /// A key up to and including the `count`-th `delimiter`, e.g. `tenant/` of
+    /// `tenant/entity/id` with `b'/'` and 1.
+    Delimited { delimiter: u8, count: usize },
+}
+
+impl PrefixExtractor {
+    /// Kind and parameters of the extractor, as recorded in the prefix filter
+    /// block.
+    pub(super) fn encode(&self) -> [u64; 3] {
+        match *self {
+            Self::Fixed(len) => [1, len as u64, 0],
+            Self::Delimited { delimiter, count } => [2, delimiter as u64, count as u64],
+        }
+    }
+
+    /// Restores an extractor from its [`encode`](Self::encode)d form.
+    pub(super) fn decode(params: [u64; 3]) -> Option<Self> {
+        match params {
+            [1, len, 0] => Some(Self::Fixed(len as usize)),
+            [2, delimiter, count] if delimiter <= u8::MAX as u64 => Some(Self::Delimited {
+                delimiter: delimiter as u8,
+                count: count as usize,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Gets the prefix of a key, if it has one.
+    pub fn extract<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        match *self {
+            Self::Fixed(len) => key.get(..len),
+            Self::Delimited { delimiter, count } => {
+                if count == 0 {
+                    return Some(&[]);
+                }
+                let (end, _) = key
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, byte)| **byte == delimiter)
+                    .nth(count - 1)?;
+                Some(&key[..=end])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrefixExtractor;
+
+    #[test]
+    fn extracts_prefixes() {
+        let fixed = PrefixExtractor::Fixed(4);
+        assert_eq!(fixed.extract(b"klee/bomb"), Some(&b"klee"[..]));
+        assert_eq!(fixed.extract(b"kle"), None);
+
+        let delimited = PrefixExtractor::Delimited {
+            delimiter: b'/',
+            count: 2,
+        };
+        assert_eq!(delimited.extract(b"klee/bomb/4"), Some(&b"klee/bomb/"[..]));
+        assert_eq!(delimited.extract(b"klee/bomb/"), Some(&b"klee/bomb/"[..]));
+        assert_eq!(delimited.extract(b"klee/bomb"), None);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::utils::varint::VarUint64;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Bound;
+
+/// Number of entries from one restart point to the next. An entry at a
+/// restart point shares nothing with the prefix before it, so that it can be
+/// read on its own.
+const RESTART_INTERVAL: usize = 16;
+
+/// Range filter telling whether a table might hold any key within a range, in
+/// the way of the base variant of SuRF (the Succinct Range Filter).
+///
+/// Every key is cut down to the shortest prefix telling it apart from both of
+/// its neighbours. A cut prefix stands for all keys starting with it, while a
+/// key that cannot be cut stands for itself alone. Since the keys are sorted,
+/// so are the ranges they stand for, and none of them overlap.
+///
+/// Ranges falling between two keys but within the prefix of one of them are
+/// false positives, which get rarer the more alike neighbouring keys are.
+///
+/// Prefixes are kept front-coded as they were written, and only every
+/// [`RESTART_INTERVAL`]-th of them is indexed. A query searches the indexed
+/// prefixes and then reads at most one interval of entries.
+pub struct RangeFilter {
+    /// Encoded entries, see [`RangeFilterBuilder`].
+    block: Vec<u8>,
+
+    /// Where every entry at a restart point starts in `block`.
+    restarts: Vec<usize>,
+
+    /// Number of prefixes.
+    count: usize,
+}
+
+impl RangeFilter {
+    /// Restores a range filter from a block written by [`RangeFilterBuilder`].
+    /// Every entry is checked, so that queries never read out of bounds.
+    pub fn decode(block: &[u8]) -> Result<Self> {
+        let mut offset = 0_usize;
+        let count = VarUint64::read_and_seek(block, &mut offset, block.len())? as usize;
+        let block = &block[offset..];
+
+        let mut restarts = Vec::<usize>::with_capacity(count.min(block.len()) / RESTART_INTERVAL);
+        let mut entries = Entries::new(block, 0);
+        for index in 0..count {
+            if index % RESTART_INTERVAL == 0 {
+                restarts.push(entries.offset);
+            }
+            match entries.read(index % RESTART_INTERVAL == 0) {
+                Some(_) => (),
+                None => return Err(Error::new(ErrorKind::InvalidData, "improper range filter")),
+            }
+        }
+        Ok(Self {
+            block: block.to_vec(),
+            restarts,
+            count,
+        })
+    }
+
+    /// Number of prefixes in the filter.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Queries if any key within the bounds might be in this filter.
+    ///
+    /// A `false` result indicates a definite 'not exist', while a `true` might
+    /// inflict a false positive.
+    pub fn may_contain(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+        // the first prefix whose keys are not all below the range
+        let below = |prefix: &[u8], cut: bool| match (start, cut) {
+            (Bound::Unbounded, _) => false,
+            (Bound::Included(key), false) => prefix < key,
+            (Bound::Excluded(key), false) => prefix <= key,
+            (Bound::Included(key) | Bound::Excluded(key), true) => {
+                prefix < key && !key.starts_with(prefix)
+            }
+        };
+        let restart = self.restarts.partition_point(|offset| {
+            let mut entries = Entries::new(&self.block, *offset);
+            match entries.read(true) {
+                Some(cut) => below(&entries.prefix, cut),
+                None => false,
+            }
+        });
+        let mut entries = Entries::new(&self.block, 0);
+        if let Some(offset) = restart.checked_sub(1).map(|r| self.restarts[r]) {
+            entries.offset = offset;
+        }
+        let remaining = self.count - restart.saturating_sub(1) * RESTART_INTERVAL;
+        let found = (0..remaining).any(|_| match entries.read(false) {
+            Some(cut) => !below(&entries.prefix, cut),
+            None => false,
+        });
+        if !found {
+            return false;
+        }
+
+        // whose keys are not all above the range either
+        let prefix = entries.prefix.as_slice();
+        match end {
+            Bound::Unbounded => true,
+            Bound::Included(key) => prefix <= key,
+            Bound::Excluded(key) => prefix < key,
+        }
+    }
+}
+
+/// Reads entries of a [`RangeFilter`] one after another.
+struct Entries<'a> {
+    block: &'a [u8],
+
+    /// Where the next entry starts.
+    offset: usize,
+
+    /// Prefix of the last entry read.
+    prefix: Vec<u8>,
+}
+
+impl<'a> Entries<'a> {
+    fn new(block: &'a [u8], offset: usize) -> Self {
+        Self {
+            block,
+            offset,
+            prefix: Vec::new(),
+        }
+    }
+
+    /// Reads the next entry into `prefix`, returning whether it was cut, or
+    /// `None` if the entry is improper. Entries at restart points must not
+    /// share anything with the prefix before them.
+    fn read(&mut self, restart: bool) -> Option<bool> {
+        // entries are the length shared with the last prefix, then the
+        // length of the rest shifted by the cut flag, then the rest
+        let shared = self.read_varu64()? as usize;
+        let len_cut = self.read_varu64()? as usize;
+        let len = len_cut >> 1;
+        if shared > self.prefix.len() || (restart && shared > 0) {
+            return None;
+        }
+        let rest = self.block.get(self.offset..self.offset.checked_add(len)?)?;
+        self.prefix.truncate(shared);
+        self.prefix.extend_from_slice(rest);
+        self.offset += len;
+        Some(len_cut & 1 != 0)
+    }
+
+    fn read_varu64(&mut self) -> Option<u64> {
+        let rest = self.block.get(self.offset..)?;
+        VarUint64::read_and_seek(rest, &mut self.offset, rest.len()).ok()
+    }
+}
+
+/// Builds the block of a [`RangeFilter`] from keys added in ascending order.
+pub struct RangeFilterBuilder {
+    /// Encoded entries so far.
+    block: Vec<u8>,
+
+    /// Number of encoded entries.
+    count: usize,
+
+    /// Key not yet encoded, which waits for the key after it.
+    pending: Option<Vec<u8>>,
+
+    /// Length shared by the pending key and the one before it.
+    pending_lcp: usize,
+
+    /// Last encoded prefix.
+    last_prefix: Vec<u8>,
+}
+
+impl RangeFilterBuilder {
+    pub fn new() -> Self {
+        Self {
+            block: Vec::new(),
+            count: 0,
+            pending: None,
+            pending_lcp: 0,
+            last_prefix: Vec::new(),
+        }
+    }
+
+    /// Adds a key greater than all keys added before.
+    pub fn add(&mut self, key: &[u8]) {
+        let lcp = match &self.pending {
+            Some(pending) => {
+                let lcp = Self::common_len(pending, key);
+                self.encode_pending(lcp);
+                lcp
+            }
+            None => 0,
+        };
+        self.pending = Some(key.to_vec());
+        self.pending_lcp = lcp;
+    }
+
+    /// Finishes the block, which starts with the number of entries.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.encode_pending(0);
+        let mut block = Vec::with_capacity(self.block.len() + 9);
+        Self::write_varu64(&mut block, self.count as u64);
+        block.extend_from_slice(&self.block);
+        block
+    }
+
+    /// Encodes the pending key, cut right after where it parts from either of
+    /// its neighbours. `next_lcp` is the length it shares with the next key.
+    ///
+    /// Keys are front-coded against the prefix before them, except for those
+    /// at restart points.
+    fn encode_pending(&mut self, next_lcp: usize) {
+        let key = match self.pending.take() {
+            Some(key) => key,
+            None => return,
+        };
+        let len = usize::max(self.pending_lcp, next_lcp) + 1;
+        let (prefix, cut) = match len < key.len() {
+            true => (&key[..len], true),
+            false => (&key[..], false),
+        };
+
+        let shared = match self.count % RESTART_INTERVAL {
+            0 => 0,
+            _ => Self::common_len(&self.last_prefix, prefix),
+        };
+        let rest = &prefix[shared..];
+        Self::write_varu64(&mut self.block, shared as u64);
+        Self::write_varu64(&mut self.block, (rest.len() << 1 | cut as usize) as u64);
+        self.block.extend_from_slice(rest);
+        self.last_prefix = prefix.to_vec();
+        self.count += 1;
+    }
+
+    fn common_len(left: &[u8], right: &[u8]) -> usize {
+        left.iter().zip(right).take_while(|(l, r)| l == r).count()
+    }
+
+    fn write_varu64(block: &mut Vec<u8>, value: u64) {
+        let mut bytes = [0_u8; 9];
+        let len = VarUint64::as_slice(value, &mut bytes);
+        block.extend_from_slice(&bytes[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RangeFilter, RangeFilterBuilder};
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+
+    #[test]
+    fn prunes_ranges() {
+        let keys: [&[u8]; 5] = [b"apple", b"apricot", b"banana", b"band", b"bandana"];
+        let mut builder = RangeFilterBuilder::new();
+        for key in keys {
+            builder.add(key);
+        }
+        let filter = RangeFilter::decode(&builder.finish()).unwrap();
+        assert_eq!(filter.len(), keys.len());
+
+        // every key is found
+        for key in keys {
+            assert!(filter.may_contain(Included(key), Included(key)));
+        }
+        assert!(filter.may_contain(Unbounded, Unbounded));
+        assert!(filter.may_contain(Included(b"b"), Excluded(b"c")));
+
+        // ranges between keys are not
+        assert!(!filter.may_contain(Included(b"a"), Excluded(b"ap")));
+        assert!(!filter.may_contain(Excluded(b"bandz"), Unbounded));
+        assert!(!filter.may_contain(Included(b"c"), Included(b"d")));
+        assert!(!filter.may_contain(Included(b"aq"), Included(b"az")));
+        assert!(!filter.may_contain(Included(b"ban"), Excluded(b"bana")));
+
+        // unless they share the cut prefix of a key
+        assert!(filter.may_contain(Included(b"appz"), Included(b"apq")));
+        assert!(filter.may_contain(Excluded(b"bandana"), Unbounded));
+    }
+
+    #[test]
+    fn spans_restarts() {
+        // keys this alike are never cut, which makes the filter exact
+        let keys: Vec<String> = (0..1000).map(|i| format!("key-{:05}", i * 3)).collect();
+        let mut builder = RangeFilterBuilder::new();
+        for key in &keys {
+            builder.add(key.as_bytes());
+        }
+        let filter = RangeFilter::decode(&builder.finish()).unwrap();
+        assert_eq!(filter.len(), keys.len());
+
+        for start in (0..3100).step_by(7) {
+            for len in [1, 2, 3, 50] {
+                let (start, end) = (format!("key-{start:05}"), format!("key-{:05}", start + len));
+                let expected = keys.iter().any(|key| *key >= start && *key < end);
+                let found =
+                    filter.may_contain(Included(start.as_bytes()), Excluded(end.as_bytes()));
+                assert_eq!(found, expected, "{start}..{end}");
+            }
+        }
+        assert!(!filter.may_contain(Excluded(b"key-02997"), Unbounded));
+        assert!(!filter.may_contain(Unbounded, Excluded(b"key-00000")));
+    }
+}
@@ -1,18 +1,45 @@
-use crate::bloom::BloomFilter;
-use crate::record::{ByteStream, KvData, KvDataRef, KvEntry, KvPointer};
+use crate::bloom::{blocked, sized};
+use crate::bloom::{BlockedBloomFilter, BloomFilter, Filter, LegacyBloomFilter, XorFilter};
+use crate::record::{ByteStream, KvData, KvDataRef, KvPointer};
 use crate::utils;
-use crate::utils::futures::MutexSync;
+use crate::utils::crc32c::Crc32c;
 use crate::utils::varint::VarUint64;
-use lru::LruCache;
 use memmap::{Mmap, MmapOptions};
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{Error, ErrorKind, Result as IoResult};
 use std::iter::Peekable;
-use std::rc::Rc;
+use std::mem;
+use std::ops::{Bound, Deref, Range};
+use std::sync::{Arc, OnceLock};
 
-use super::MetaBlockType;
+use super::cache::BlockCache;
+use super::compression::Compression;
+use super::prefix::PrefixExtractor;
+use super::range::RangeFilter;
+use super::{BlockHandle, Footer, MetaBlockType, BLOCK_TRAILER_SIZE};
+
+/// Everything loaded from the metablocks of a table, see the fields of
+/// [`SSTableReader`].
+struct TableMeta {
+    keys: Vec<(ByteStream, usize)>,
+    blocks: Vec<BlockHandle>,
+    codecs: Vec<OnceLock<Option<Compression>>>,
+    bloom: Box<dyn Filter>,
+    prefix_filter: Option<PrefixFilter>,
+    range_filter: Option<RangeFilter>,
+    key_range: Option<(ByteStream, ByteStream)>,
+    data_size: usize,
+}
+
+/// Bloom filter of key prefixes, along with how the prefixes were taken.
+type PrefixFilter = (PrefixExtractor, Box<dyn Filter>);
+
+/// Last key of every data block along with its index, and where the blocks
+/// lie in the file.
+type BlockIndex = (Vec<(ByteStream, usize)>, Vec<BlockHandle>);
 
 pub struct SSTableReader {
     /// Reference to file, just to keep it open.
@@ -22,48 +49,90 @@ pub struct SSTableReader {
     region: Mmap,
 
     /// Internal bloom filter to check for missing keys.
-    bloom: BloomFilter,
+    bloom: Box<dyn Filter>,
 
-    /// Indexed breaking points that are saved every ?? keys.
+    /// Bloom filter to check for missing key prefixes, if the table has one.
+    prefix_filter: Option<PrefixFilter>,
+
+    /// Filter to check for key ranges without any keys, if the table has one.
+    range_filter: Option<RangeFilter>,
+
+    /// The smallest and the largest key, if recorded in the table.
+    key_range: Option<(ByteStream, ByteStream)>,
+
+    /// Indexed breaking points, each being the first key of a data block.
     keys: Vec<(ByteStream, usize)>,
 
-    /// LRU cache locks.
-    ///
-    /// TODO: this might cause issues on an async workload.
-    cache_lock: MutexSync<()>,
+    /// Data blocks holding the records.
+    blocks: Vec<BlockHandle>,
+
+    /// Codec of every data block once it has been checked against its
+    /// trailer, or `None` if it turned out to be corrupt. Blocks are only
+    /// checked when first read, and only once.
+    codecs: Vec<OnceLock<Option<Compression>>>,
 
-    /// LRU cache for recently *accessed* items.
-    cache_read: LruCache<ByteStream, KvData>,
+    /// Bytes taken by records, which precede all metablocks.
+    data_size: usize,
 
-    /// LRU cache as a look-aside buffer. This can be really useful when
-    /// continuously accessing discrete items sequentially (and for this
-    /// purpose is way smaller than the read buffer).
-    cache_lookaside: LruCache<ByteStream, KvData>,
+    /// Cache of decompressed data blocks, which may be shared with other
+    /// tables.
+    cache: Arc<BlockCache>,
 
-    /// Sequential read indicator, when consistent seqread are performed, we
-    /// will rely on [`cache_lookaside`] more.
-    cache_seq_ind: f64,
+    /// Identifies the blocks of this table in the cache.
+    file_id: u64,
 }
 
 impl SSTableReader {
+    /// Opens a table, verifying the checksums of its metablocks. Data blocks
+    /// are verified as they are read, so that corrupt ones are reported by
+    /// lookups and iterators instead.
     pub fn new(handle: File) -> IoResult<Self> {
+        Self::with_cache(handle, BlockCache::global())
+    }
+
+    /// Opens a table whose decompressed blocks are kept in `cache`.
+    pub fn with_cache(handle: File, cache: Arc<BlockCache>) -> IoResult<Self> {
         // unzip file to a memory map
         let region = unsafe { MmapOptions::new().map(&handle)? };
 
-        // validate magic
-        let magic = Self::read_u64(&region[region.len() - 8..]);
-        if magic != 0x1145_1419_1981_fee1_u64 {
-            return Err(Error::new(ErrorKind::InvalidData, "invalid magic"));
-        }
+        // pick decoder by format version
+        let footer = Footer::decode(&region)?;
+        let meta = match footer.version {
+            1 => Self::load_v1(&region, &footer)?,
+            _ => Self::load_v2(&region, &footer)?,
+        };
 
-        // extract header block
-        let mut offset = Self::read_u64(&region[region.len() - 16..]) as usize;
-        let header_block_items = Self::read_varu64(&region, &mut offset);
+        Ok(Self {
+            _handle: handle,
+            region,
+            bloom: meta.bloom,
+            prefix_filter: meta.prefix_filter,
+            range_filter: meta.range_filter,
+            key_range: meta.key_range,
+            keys: meta.keys,
+            blocks: meta.blocks,
+            codecs: meta.codecs,
+            data_size: meta.data_size,
+            cache,
+            file_id: BlockCache::new_file_id(),
+        })
+    }
+
+    /// Loads the metablocks of a table with a single stream of records, which
+    /// is then treated as one data block.
+    fn load_v1(region: &Mmap, footer: &Footer) -> IoResult<TableMeta> {
+        // extract header block, which ends right before the footer
+        let mut offset = footer.header;
+        let header = &region[..region.len() - 16];
+        if offset >= header.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "header out of bounds"));
+        }
+        let header_block_items = Self::read_block_varu64(header, &mut offset)?;
 
         let mut header_block = BTreeMap::<MetaBlockType, usize>::new();
         for _ in 0..header_block_items {
-            let block_type = Self::read_varu64(&region, &mut offset);
-            let indice = Self::read_varu64(&region, &mut offset);
+            let block_type = Self::read_block_varu64(header, &mut offset)?;
+            let indice = Self::read_block_varu64(header, &mut offset)?;
             let block_type = match block_type {
                 1 => MetaBlockType::Index,
                 2 => MetaBlockType::BloomFilter,
@@ -77,35 +146,158 @@ impl SSTableReader {
             Some(val) => val,
             None => return Err(Error::new(ErrorKind::InvalidData, "missing index")),
         };
-        let keys = Self::get_index(&region, offset)?;
+        let keys = Self::get_index_v1(region, offset)?;
 
         // extract bloom block
         let offset = *match header_block.get(&MetaBlockType::BloomFilter) {
             Some(val) => val,
             None => return Err(Error::new(ErrorKind::InvalidData, "missing bloom filter")),
         };
-        let bloom = Self::get_bloom_filter(&region, offset)?;
+        let bloom = match region.get(offset..) {
+            Some(block) => Self::get_legacy_bloom_filter(block)?,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "bloom filter out of bounds",
+                ))
+            }
+        };
 
-        Ok(Self {
-            _handle: handle,
-            region,
+        // records are followed by an empty one marking the end
+        let data_size = *header_block.values().min().unwrap();
+        let handle = BlockHandle {
+            offset: 0,
+            size: data_size.saturating_sub(4),
+        };
+        if keys.iter().any(|(_, indice)| *indice >= handle.size) {
+            return Err(Error::new(ErrorKind::InvalidData, "index out of bounds"));
+        }
+        Ok(TableMeta {
+            keys,
+            blocks: vec![handle],
+            codecs: vec![OnceLock::from(Some(Compression::None))],
             bloom,
+            prefix_filter: None,
+            range_filter: None,
+            key_range: None,
+            data_size,
+        })
+    }
+
+    /// Loads the metablocks of a table made of checksummed blocks. Data blocks
+    /// are only checked to lie within the table.
+    fn load_v2(region: &Mmap, footer: &Footer) -> IoResult<TableMeta> {
+        // extract header block, which ends right before the footer
+        let offset = footer.header;
+        let header_end = region
+            .len()
+            .saturating_sub(Footer::SIZE + BLOCK_TRAILER_SIZE);
+        if offset > header_end {
+            return Err(Error::new(ErrorKind::InvalidData, "header out of bounds"));
+        }
+        let header = Self::read_block(
+            region,
+            BlockHandle {
+                offset,
+                size: header_end - offset,
+            },
+        )?;
+        let header = header.as_ref();
+        let mut offset = 0_usize;
+        let header_block_items = Self::read_block_varu64(header, &mut offset)?;
+
+        let mut header_block = BTreeMap::<MetaBlockType, BlockHandle>::new();
+        for _ in 0..header_block_items {
+            let block_type = Self::read_block_varu64(header, &mut offset)?;
+            let block_type = match block_type {
+                1 => MetaBlockType::Index,
+                3 => MetaBlockType::BlockedBloomFilter,
+                4 => MetaBlockType::XorFilter,
+                5 => MetaBlockType::PrefixFilter,
+                6 => MetaBlockType::RangeFilter,
+                7 => MetaBlockType::KeyRange,
+                8 => MetaBlockType::SizedBloomFilter,
+                _ => return Err(Error::new(ErrorKind::InvalidData, "invalid metablock type")),
+            };
+            let handle = BlockHandle {
+                offset: Self::read_block_varu64(header, &mut offset)? as usize,
+                size: Self::read_block_varu64(header, &mut offset)? as usize,
+            };
+            header_block.insert(block_type, handle);
+        }
+
+        // extract index block
+        let handle = *match header_block.get(&MetaBlockType::Index) {
+            Some(val) => val,
+            None => return Err(Error::new(ErrorKind::InvalidData, "missing index")),
+        };
+        let (keys, handles) = Self::get_index(&Self::read_block(region, handle)?)?;
+
+        // extract filter block, in whichever layout it was written
+        let filter_types = [
+            MetaBlockType::SizedBloomFilter,
+            MetaBlockType::BlockedBloomFilter,
+            MetaBlockType::XorFilter,
+        ];
+        let (block_type, handle) = match filter_types
+            .into_iter()
+            .find_map(|t| header_block.get(&t).map(|h| (t, *h)))
+        {
+            Some(val) => val,
+            None => return Err(Error::new(ErrorKind::InvalidData, "missing bloom filter")),
+        };
+        let block = Self::read_block(region, handle)?;
+        let bloom = match block_type {
+            MetaBlockType::XorFilter => Self::get_xor_filter(&block)?,
+            _ => Self::get_bloom_filter(&block, block_type)?,
+        };
+
+        // extract prefix filter block, which is optional
+        let prefix_filter = match header_block.get(&MetaBlockType::PrefixFilter) {
+            Some(handle) => Some(Self::get_prefix_filter(&Self::read_block(
+                region, *handle,
+            )?)?),
+            None => None,
+        };
+
+        // extract range filter and key range blocks, which are optional
+        let range_filter = match header_block.get(&MetaBlockType::RangeFilter) {
+            Some(handle) => Some(RangeFilter::decode(&Self::read_block(region, *handle)?)?),
+            None => None,
+        };
+        let key_range = match header_block.get(&MetaBlockType::KeyRange) {
+            Some(handle) => Some(Self::get_key_range(&Self::read_block(region, *handle)?)?),
+            None => None,
+        };
+
+        // data blocks precede all metablocks
+        let data_size = header_block.values().map(|h| h.offset).min().unwrap();
+        for handle in &handles {
+            let end = handle.offset.checked_add(handle.size);
+            if end.is_none_or(|end| data_size.saturating_sub(end) < BLOCK_TRAILER_SIZE) {
+                return Err(Error::new(ErrorKind::InvalidData, "block out of bounds"));
+            }
+        }
+        Ok(TableMeta {
             keys,
-            cache_lock: MutexSync::new(()),
-            cache_read: LruCache::new(2048),
-            cache_lookaside: LruCache::new(256),
-            cache_seq_ind: 16.0,
+            codecs: handles.iter().map(|_| OnceLock::new()).collect(),
+            blocks: handles,
+            bloom,
+            prefix_filter,
+            range_filter,
+            key_range,
+            data_size,
         })
     }
 
-    fn get_index(region: &Mmap, mut offset: usize) -> IoResult<Vec<(ByteStream, usize)>> {
+    fn get_index_v1(region: &Mmap, mut offset: usize) -> IoResult<Vec<(ByteStream, usize)>> {
         // read key offset values
-        let len = Self::read_varu64(region, &mut offset);
+        let len = Self::read_block_varu64(region, &mut offset)?;
         let mut indices = Vec::<usize>::new();
         let mut keys = Vec::<(ByteStream, usize)>::new();
 
         for _ in 0..len {
-            let indice = Self::read_varu64(region, &mut offset);
+            let indice = Self::read_block_varu64(region, &mut offset)?;
             indices.push(indice as usize);
         }
 
@@ -113,10 +305,10 @@ impl SSTableReader {
         for indice in indices {
             let mut ptr = indice;
 
-            let k_len = Self::read_varu64(region, &mut ptr) as usize;
-            let common_len = Self::read_varu64(region, &mut ptr);
-            let _v_len = Self::read_varu64(region, &mut ptr);
-            let _flags = Self::read_varu64(region, &mut ptr);
+            let k_len = Self::read_block_varu64(region, &mut ptr)? as usize;
+            let common_len = Self::read_block_varu64(region, &mut ptr)?;
+            let _v_len = Self::read_block_varu64(region, &mut ptr)?;
+            let _flags = Self::read_block_varu64(region, &mut ptr)?;
 
             // you shouldn't index a compressed key
             if common_len != 0 {
@@ -127,17 +319,44 @@ impl SSTableReader {
             }
 
             // read key and send it away
-            let key = &region[ptr..ptr + k_len];
-            let key = ByteStream::from_slice(key);
+            let key = match region.get(ptr..).and_then(|rest| rest.get(..k_len)) {
+                Some(key) => ByteStream::from_slice(key),
+                None => return Err(Error::new(ErrorKind::InvalidData, "index out of bounds")),
+            };
             keys.push((key, indice));
         }
         Ok(keys)
     }
 
-    fn get_bloom_filter(region: &Mmap, mut offset: usize) -> IoResult<BloomFilter> {
+    fn get_index(block: &[u8]) -> IoResult<BlockIndex> {
+        let mut offset = 0_usize;
+        let len = Self::read_block_varu64(block, &mut offset)?;
+        let mut keys = Vec::<(ByteStream, usize)>::new();
+        let mut blocks = Vec::<BlockHandle>::new();
+
+        // every data block is indexed by its first key
+        for _ in 0..len {
+            let k_len = Self::read_block_varu64(block, &mut offset)? as usize;
+            let key = match block.get(offset..).and_then(|rest| rest.get(..k_len)) {
+                Some(key) => ByteStream::from_slice(key),
+                None => return Err(Error::new(ErrorKind::InvalidData, "index out of bounds")),
+            };
+            offset += k_len;
+            let handle = BlockHandle {
+                offset: Self::read_block_varu64(block, &mut offset)? as usize,
+                size: Self::read_block_varu64(block, &mut offset)? as usize,
+            };
+            keys.push((key, handle.offset));
+            blocks.push(handle);
+        }
+        Ok((keys, blocks))
+    }
+
+    fn get_legacy_bloom_filter(block: &[u8]) -> IoResult<Box<dyn Filter>> {
         // validate filter size
-        let size = Self::read_varu64(region, &mut offset) as usize;
-        if size != BloomFilter::default_size() {
+        let mut offset = 0_usize;
+        let size = Self::read_block_varu64(block, &mut offset)? as usize;
+        if size != LegacyBloomFilter::default_size() {
             return Err(Error::new(
                 ErrorKind::InvalidData,
                 "improper bloom filter size",
@@ -145,258 +364,797 @@ impl SSTableReader {
         }
 
         // read a lot of bytes
-        let bloom = &region[offset..offset + size];
-        let bloom = BloomFilter::from_slice(bloom);
-        Ok(bloom)
-    }
-
-    /// Accesses u64 value in little-endian.
-    fn read_u64(region: &[u8]) -> u64 {
-        (region[0] as u64)
-            | ((region[1] as u64) << 8)
-            | ((region[2] as u64) << 16)
-            | ((region[3] as u64) << 24)
-            | ((region[4] as u64) << 32)
-            | ((region[5] as u64) << 40)
-            | ((region[6] as u64) << 48)
-            | ((region[7] as u64) << 56)
-    }
-
-    /// Accesses variable u64 value.
-    fn read_varu64(region: &Mmap, offset: &mut usize) -> u64 {
-        let (len, result) = VarUint64::read_offset_unchecked(&region[*offset..]);
-        *offset += len;
-        return result;
-    }
-
-    /// Access item from table.
-    pub fn get(&mut self, key: &[u8]) -> Option<KvData> {
-        // check for lru cache(s)
-        let key_bs = ByteStream::from(key);
-        '_chk_cache: {
-            let _lock = self.cache_lock.lock();
-            match self.cache_read.get(&key_bs) {
-                Some(entry) => return Some(entry.clone()),
-                None => (),
-            };
-            match self.cache_lookaside.get(&key_bs) {
-                Some(entry) => {
-                    self.cache_seq_ind = f64::min(self.cache_seq_ind * 1.12, 256.0);
-                    return Some(entry.clone());
-                }
-                None => {
-                    self.cache_seq_ind = f64::max(self.cache_seq_ind - 0.8, 1.0);
-                }
+        let bloom = match block.get(offset..).and_then(|rest| rest.get(..size)) {
+            Some(bloom) => bloom,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "bloom filter out of bounds",
+                ))
+            }
+        };
+        let bloom = LegacyBloomFilter::from_slice(bloom);
+        Ok(Box::new(bloom))
+    }
+
+    fn get_key_range(block: &[u8]) -> IoResult<(ByteStream, ByteStream)> {
+        // the smallest and the largest key, each with its length
+        let mut offset = 0_usize;
+        let mut bounds = Vec::<ByteStream>::with_capacity(2);
+        for _ in 0..2 {
+            let len = Self::read_block_varu64(block, &mut offset)? as usize;
+            match block.get(offset..).and_then(|rest| rest.get(..len)) {
+                Some(key) => bounds.push(ByteStream::from_slice(key)),
+                None => return Err(Error::new(ErrorKind::InvalidData, "key out of bounds")),
             };
+            offset += len;
+        }
+        let max = bounds.pop().unwrap();
+        Ok((bounds.pop().unwrap(), max))
+    }
+
+    fn get_prefix_filter(block: &[u8]) -> IoResult<PrefixFilter> {
+        // extractor kind and parameters precede a bloom filter
+        let mut offset = 0_usize;
+        let mut params = [0_u64; 3];
+        for param in params.iter_mut() {
+            *param = Self::read_block_varu64(block, &mut offset)?;
+        }
+        let extractor = match PrefixExtractor::decode(params) {
+            Some(extractor) => extractor,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "unknown prefix extractor",
+                ))
+            }
+        };
+        let bloom = Self::get_bloom_filter(&block[offset..], MetaBlockType::SizedBloomFilter)?;
+        Ok((extractor, bloom))
+    }
+
+    fn get_xor_filter(block: &[u8]) -> IoResult<Box<dyn Filter>> {
+        // validate filter size
+        let mut offset = 0_usize;
+        let seed = Self::read_block_varu64(block, &mut offset)?;
+        let size = Self::read_block_varu64(block, &mut offset)? as usize;
+        if size == 0 || !size.is_multiple_of(3) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "improper xor filter size",
+            ));
         }
 
-        // just to circumvent the blasted lifetime checker
-        unsafe {
-            let ptr = self as *mut Self;
+        // the filter takes the rest of the block
+        if block.len() - offset != size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "xor filter out of bounds",
+            ));
+        }
+        Ok(Box::new(XorFilter::from_slice(seed, &block[offset..])))
+    }
 
-            // locate item and clone
-            let (_index, mut iter) = match (*ptr).get_iter_internal(key) {
-                None => return None,
-                Some(it) => it,
-            };
-            let item = match iter.next() {
-                None => return None,
-                Some(it) => it,
-            };
-            let result = KvData::from(&item.value());
-
-            // update lru cache, flushing entire region into lru
-            let mut max_items = i32::max(1, ((*ptr).cache_seq_ind / 8.0) as i32 - 1);
-            '_upd_cache: {
-                let _lock = (*ptr).cache_lock.lock();
-                (*ptr).cache_read.put(key_bs, result.clone());
-                while max_items > 0 {
-                    max_items -= 1;
-                    let item = iter.next();
-                    if let None = &item {
-                        break;
-                    }
-                    let item = item.unwrap();
-                    let key = ByteStream::from(item.key());
-                    let value = KvData::from(&item.value());
-                    (*ptr).cache_lookaside.put(key, value);
+    fn get_bloom_filter(block: &[u8], block_type: MetaBlockType) -> IoResult<Box<dyn Filter>> {
+        // validate filter parameters
+        let mut offset = 0_usize;
+        let hashes = Self::read_block_varu64(block, &mut offset)? as u32;
+        let size = Self::read_block_varu64(block, &mut offset)? as usize;
+        if hashes == 0 || hashes > sized::MAX_HASHES || size == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "improper bloom filter size",
+            ));
+        }
+
+        // the filter takes the rest of the block
+        if block.len() - offset != size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "bloom filter out of bounds",
+            ));
+        }
+        let bloom = &block[offset..];
+        match block_type {
+            MetaBlockType::BlockedBloomFilter => {
+                if !size.is_multiple_of(blocked::BLOCK_BYTES) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "improper bloom filter size",
+                    ));
                 }
+                Ok(Box::new(BlockedBloomFilter::from_slice(hashes, bloom)))
             }
-            Some(result)
+            _ => Ok(Box::new(BloomFilter::from_slice(hashes, bloom))),
+        }
+    }
+
+    /// Checks a block against its trailer, returning the stored block and
+    /// the codec it is compressed with.
+    fn check_block(region: &Mmap, handle: BlockHandle) -> IoResult<(&[u8], Compression)> {
+        let end = match handle.offset.checked_add(handle.size) {
+            Some(end) if region.len().saturating_sub(end) >= BLOCK_TRAILER_SIZE => end,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "block out of bounds")),
+        };
+        let block = &region[handle.offset..end];
+        let kind = region[end];
+        let checksum = u32::from_le_bytes(region[end + 1..end + 5].try_into().unwrap());
+
+        if Crc32c::extend(Crc32c::checksum(block), &[kind]) != checksum {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "block checksum mismatch",
+            ));
+        }
+        match Compression::from_kind(kind) {
+            Some(codec) => Ok((block, codec)),
+            None => Err(Error::new(ErrorKind::InvalidData, "unknown block kind")),
+        }
+    }
+
+    /// Gets the contents of a block after checking them against the trailer.
+    fn read_block(region: &Mmap, handle: BlockHandle) -> IoResult<Cow<'_, [u8]>> {
+        match Self::check_block(region, handle)? {
+            (block, Compression::None) => Ok(Cow::Borrowed(block)),
+            (block, codec) => Ok(Cow::Owned(codec.decompress(block)?)),
         }
     }
 
+    /// Accesses variable u64 value within a block, failing on overflows.
+    fn read_block_varu64(block: &[u8], offset: &mut usize) -> IoResult<u64> {
+        match block.get(*offset..) {
+            Some(rest) => VarUint64::read_and_seek(rest, offset, rest.len()),
+            None => Err(Error::new(ErrorKind::InvalidData, "out of bounds")),
+        }
+    }
+
+    /// Access item from table. Fails if the block holding the key is corrupt.
+    pub fn get(&self, key: &[u8]) -> IoResult<Option<KvData>> {
+        let mut iter = match self.get_iter_internal(key)? {
+            None => return Ok(None),
+            Some((_index, iter)) => iter,
+        };
+        Ok(iter.next().map(|item| KvData::from(&item.value())))
+    }
+
     /// Access item from table, returning a partial-scan iterator from that
     /// location.
-    pub fn get_iter(&mut self, key: &[u8]) -> Option<Peekable<SSTableReaderIterator>> {
-        match self.get_iter_internal(key) {
-            None => None,
-            Some((_index, iter)) => Some(iter),
+    pub fn get_iter(&self, key: &[u8]) -> IoResult<Option<Peekable<SSTableReaderIterator<'_>>>> {
+        match self.get_iter_internal(key)? {
+            None => Ok(None),
+            Some((_index, iter)) => Ok(Some(iter)),
         }
     }
 
     /// Internal implementation of [`get_iter`].
     fn get_iter_internal(
-        &mut self,
+        &self,
         key: &[u8],
-    ) -> Option<(usize, Peekable<SSTableReaderIterator>)> {
+    ) -> IoResult<Option<(usize, Peekable<SSTableReaderIterator<'_>>)>> {
         // check if key might be non-existent in bloom filter
         if !self.bloom.query(key) {
-            return None;
+            return Ok(None);
         }
 
         // resolve index of lower-bound key and go through it
         let index = match self.get_iter_lower_bound(key) {
-            None => return None,
+            None => return Ok(None),
             Some(idx) => idx,
         };
-        let mut iter = self.iter_from_offset(self.keys[index].1).peekable();
-        loop {
-            match ByteStream::ref_2_partial_cmp(iter.peek().unwrap().key(), key) {
-                Some(Ordering::Greater) => return None,
-                Some(Ordering::Equal) => return Some((index, iter)),
-                Some(Ordering::Less) => _ = iter.next(),
-                _ => panic!("nothing compared"),
-            }
+        let mut iter = self.iter_from_offset(self.keys[index].1);
+        match iter.skip_less(key) {
+            Some(Ordering::Equal) => Ok(Some((index, iter.peekable()))),
+            Some(_) => Ok(None),
+            None => iter.status().map(|()| None),
         }
     }
 
     /// Get lower-bound key reference index.
     fn get_iter_lower_bound(&self, key: &[u8]) -> Option<usize> {
-        // binary search for some
-        let len = self.keys.len();
-        if len == 0 {
-            return None;
+        // the key lies after the last indexed key not greater than it
+        let index = self
+            .keys
+            .partition_point(|(indexed, _)| indexed.as_ref() <= key);
+        index.checked_sub(1)
+    }
+
+    /// Tells if the table might hold keys starting with `prefix`. Only the
+    /// prefix filter may rule them out, as long as its extractor finds a
+    /// prefix in `prefix`.
+    pub fn may_contain_prefix(&self, prefix: &[u8]) -> bool {
+        match &self.prefix_filter {
+            Some((extractor, bloom)) => match extractor.extract(prefix) {
+                Some(extracted) => bloom.query(extracted),
+                None => true,
+            },
+            None => true,
         }
-        let mut left = 0_usize;
-        let mut right = len;
+    }
 
-        while left < right {
-            let mid = left + ((right - left + 1) >> 1);
-            if mid >= len {
-                return None; // key > max(all_keys)
-            }
-            match self.keys[mid].0.ref_partial_cmp(key) {
-                Some(Ordering::Less) => left = mid,
-                Some(Ordering::Greater) => right = mid - 1,
-                Some(Ordering::Equal) => return Some(mid),
-                _ => panic!("expect ordering to return comparison"),
-            }
+    /// Create iterator over the keys starting with `prefix`, which skips the
+    /// table as a whole if its prefix filter rules them out.
+    pub fn prefix_scan<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = SSTableReaderPointer<'a>> + 'a {
+        let iter = match self.may_contain_prefix(prefix) {
+            true => Some(self.seek(prefix)),
+            false => None,
+        };
+        iter.into_iter()
+            .flatten()
+            .take_while(move |item| item.key().starts_with(prefix))
+    }
+
+    /// Bytes taken by records in the table, not counting metadata.
+    pub fn data_size(&self) -> usize {
+        self.data_size
+    }
+
+    /// Gets the smallest and the largest key in the table, or `None` if the
+    /// table is empty.
+    pub fn key_range(&self) -> Option<(ByteStream, ByteStream)> {
+        if let Some((min, max)) = &self.key_range {
+            return Some((ByteStream::from(min), ByteStream::from(max)));
         }
+        let (first, _) = self.keys.first()?;
+        // the last indexed key is close to the end of the table
+        let (_, offset) = self.keys.last()?;
+        let last = self.iter_from_offset(*offset).last()?;
+        Some((ByteStream::from(first), ByteStream::from_slice(last.key())))
+    }
 
-        match left {
-            0 => match self.keys[0].0.ref_partial_cmp(key) {
-                Some(Ordering::Greater) => None,
-                _ => Some(0),
-            },
-            rest => Some(rest),
+    /// Tells if the table might hold keys within the bounds, asking the key
+    /// range and the range filter of the table.
+    pub fn may_contain_range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+        if let Some((min, max)) = &self.key_range {
+            let below = match start {
+                Bound::Included(key) => max.as_ref() < key,
+                Bound::Excluded(key) => max.as_ref() <= key,
+                Bound::Unbounded => false,
+            };
+            let above = match end {
+                Bound::Included(key) => min.as_ref() > key,
+                Bound::Excluded(key) => min.as_ref() >= key,
+                Bound::Unbounded => false,
+            };
+            if below || above {
+                return false;
+            }
+        }
+        match &self.range_filter {
+            Some(filter) => filter.may_contain(start, end),
+            None => true,
         }
     }
 
     /// Create full-scan iterator.
     pub fn iter(&self) -> SSTableReaderIterator {
-        // the sstable file should contain some keys
-        // otherwise we should run into a (0, 0, 0, 0) termination file
-        let offset = match self.keys.len() {
-            0 => 0_usize,
-            _ => self.keys[0].1,
+        self.iter_from_offset(0)
+    }
+
+    /// Create iterator from the first key that is not less than `key`.
+    pub fn seek(&self, key: &[u8]) -> SSTableReaderIterator<'_> {
+        // the key lies right after the last indexed key less than it
+        let index = self
+            .keys
+            .partition_point(|(indexed, _)| indexed.as_ref() < key);
+        let mut iter = match index {
+            0 => self.iter(),
+            _ => self.iter_from_offset(self.keys[index - 1].1),
+        };
+        iter.skip_less(key);
+        iter
+    }
+
+    /// Create iterator over all keys in descending order.
+    pub fn iter_rev(&self) -> SSTableReaderReverseIterator<'_> {
+        SSTableReaderReverseIterator {
+            reader: self,
+            restart: self.keys.len(),
+            pending: Vec::new(),
+            corrupt: false,
+        }
+    }
+
+    /// Create descending iterator from the last key that is not greater than
+    /// `key`.
+    pub fn seek_for_prev(&self, key: &[u8]) -> SSTableReaderReverseIterator<'_> {
+        let mut iter = self.iter_rev();
+        let restart = match self.get_iter_lower_bound(key) {
+            None => {
+                iter.restart = 0;
+                return iter;
+            }
+            Some(restart) => restart,
+        };
+        // keys after the restart point are all greater than the key
+        iter.restart = restart;
+        iter.read_restart(restart);
+        while let Some(item) = iter.pending.last() {
+            match item.key() > key {
+                true => _ = iter.pending.pop(),
+                false => break,
+            }
+        }
+        iter
+    }
+
+    /// Create cursor at the first key that is not less than `key`, or off the
+    /// end after the last key without one.
+    pub fn cursor(&self, key: Option<&[u8]>) -> SSTableReaderCursor<'_> {
+        let mut cursor = SSTableReaderCursor {
+            reader: self,
+            restart: 0,
+            entries: Vec::new(),
+            index: 0,
+            corrupt: false,
+        };
+        if self.keys.is_empty() {
+            return cursor;
+        }
+        let key = match key {
+            Some(key) => key,
+            None => {
+                cursor.load(self.keys.len() - 1);
+                cursor.index = cursor.entries.len() as isize;
+                return cursor;
+            }
         };
-        self.iter_from_offset(offset)
+        // the key lies after the last indexed key less than it
+        let restart = self
+            .keys
+            .partition_point(|(indexed, _)| indexed.as_ref() < key);
+        cursor.load(restart.saturating_sub(1));
+        let index = cursor.entries.partition_point(|item| item.key() < key);
+        cursor.index = index as isize - 1;
+        cursor.next();
+        cursor
+    }
+
+    /// Reads all entries from a restart point up to the next one.
+    fn read_restart(&self, restart: usize) -> IoResult<Vec<SSTableReaderPointer<'_>>> {
+        let end = match self.keys.get(restart + 1) {
+            Some((_, offset)) => self.position_of(*offset),
+            None => (self.blocks.len(), 0),
+        };
+        let mut entries = Vec::new();
+        let mut iter = self.iter_from_offset(self.keys[restart].1);
+        while iter.position() < end {
+            match iter.next() {
+                Some(item) => entries.push(item),
+                None => break,
+            }
+        }
+        iter.status().map(|()| entries)
     }
 
     /// Create iterator from given offset.
     fn iter_from_offset(&self, offset: usize) -> SSTableReaderIterator {
-        SSTableReaderIterator {
-            region: &self.region,
-            offset,
-            last_key: Rc::from(vec![]),
+        let (block, offset) = self.position_of(offset);
+        let mut iter = SSTableReaderIterator {
+            reader: self,
+            block,
+            data: BlockData::Mapped(&[]),
+            offset: 0,
+            last_key: Arc::default(),
+            spare_key: Arc::default(),
+            corrupt: false,
+        };
+        if let Some(()) = iter.load_block() {
+            iter.offset = offset;
+        }
+        iter
+    }
+
+    /// Block of the given offset, and the offset within the block.
+    fn position_of(&self, offset: usize) -> (usize, usize) {
+        let block = self
+            .blocks
+            .partition_point(|block| block.offset <= offset)
+            .saturating_sub(1);
+        match self.blocks.get(block) {
+            Some(handle) => (block, offset - handle.offset),
+            None => (block, 0),
+        }
+    }
+
+    /// Reads the contents of a data block, decompressing them if needed. The
+    /// block is checked against its trailer when first read.
+    fn block_data(&self, block: usize) -> IoResult<BlockData<'_>> {
+        let handle = self.blocks[block];
+        let codec = self.codecs[block].get_or_init(|| self.check_data_block(handle));
+        let data = &self.region[handle.offset..handle.offset + handle.size];
+        match codec {
+            None => Err(Error::new(ErrorKind::InvalidData, "corrupt data block")),
+            Some(Compression::None) => Ok(BlockData::Mapped(data)),
+            Some(codec) => self
+                .cache
+                .get_or_load(self.file_id, handle.offset, || codec.decompress(data))
+                .map(BlockData::Decompressed),
+        }
+    }
+
+    /// Gets the codec of a data block if it is intact, which is once its
+    /// checksum matches and it decompresses. Decompressed blocks are cached.
+    fn check_data_block(&self, handle: BlockHandle) -> Option<Compression> {
+        let (data, codec) = Self::check_block(&self.region, handle).ok()?;
+        if codec != Compression::None {
+            let load = || codec.decompress(data);
+            self.cache
+                .get_or_load(self.file_id, handle.offset, load)
+                .ok()?;
+        }
+        Some(codec)
+    }
+}
+
+/// Contents of a data block, which are borrowed from the file unless they had
+/// to be decompressed. Decompressed blocks are shared with the block cache.
+#[derive(Clone)]
+enum BlockData<'a> {
+    Mapped(&'a [u8]),
+    Decompressed(Arc<Vec<u8>>),
+}
+
+impl<'a> Deref for BlockData<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(data) => data,
+            Self::Decompressed(data) => data,
         }
     }
 }
 
 /// SSTable reader iterator manager.
+///
+/// Iteration stops early at a corrupt block or entry, which is then reported
+/// by [`status`](Self::status).
+#[derive(Clone)]
 pub struct SSTableReaderIterator<'a> {
-    /// Reference to file as a memory region.
-    region: &'a Mmap,
+    /// Table being read.
+    reader: &'a SSTableReader,
 
-    /// Current iterator offset.
+    /// Index of the block being read.
+    block: usize,
+
+    /// Contents of the block being read.
+    data: BlockData<'a>,
+
+    /// Current iterator offset within the block.
     offset: usize,
 
-    /// Holds previous key (index compression).
-    last_key: Rc<Vec<u8>>,
+    /// Holds previous key (index compression). Keys are shared with the
+    /// pointers handed out, see [`read_key`](Self::read_key).
+    last_key: Arc<Vec<u8>>,
+
+    /// Buffer the next key is put together in while the previous one is still
+    /// held by a pointer.
+    spare_key: Arc<Vec<u8>>,
+
+    /// Whether iteration stopped at a corrupt block or entry.
+    corrupt: bool,
 }
 
 impl<'a> Iterator for SSTableReaderIterator<'a> {
     type Item = SSTableReaderPointer<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let the_offset = self.offset;
+        self.next_entry().map(|(_offset, item)| item)
+    }
+}
 
-        // read headers of k-v pair
-        let key_len = self.read_varu64() as usize;
-        let key_common_len = self.read_varu64() as usize;
-        let value_len = self.read_varu64() as usize;
-        let flags = self.read_varu64() as u8;
+impl<'a> SSTableReaderIterator<'a> {
+    /// Fails if iteration stopped at a corrupt block or entry, rather than at
+    /// the end of the table.
+    pub fn status(&self) -> IoResult<()> {
+        match self.corrupt {
+            true => Err(Error::new(ErrorKind::InvalidData, "corrupt data block")),
+            false => Ok(()),
+        }
+    }
+
+    /// Reads the next entry, along with its offset within the current block.
+    fn next_entry(&mut self) -> Option<(usize, SSTableReaderPointer<'a>)> {
+        if self.corrupt {
+            return None;
+        }
+        // move on to the next block once this one is used up
+        while self.offset >= self.data.len() {
+            self.block += 1;
+            self.load_block()?;
+        }
+        let offset = self.offset;
+        match self.read_entry() {
+            Ok(item) => Some((offset, item)),
+            Err(_) => {
+                self.corrupt = true;
+                None
+            }
+        }
+    }
+
+    /// Skips the entries with keys less than `key`, comparing the key of the
+    /// entry yielded next against it. Gives `None` once there is none.
+    fn skip_less(&mut self, key: &[u8]) -> Option<Ordering> {
+        loop {
+            let (offset, item) = self.next_entry()?;
+            let order = item.key().cmp(key);
+            if order != Ordering::Less {
+                // the entry is read again from the same block, taking no more
+                // of the last key than it shares with the entry itself
+                self.offset = offset;
+                return Some(order);
+            }
+        }
+    }
 
-        // terminated
-        if key_len == 0 && key_common_len == 0 && value_len == 0 {
+    /// Reads the contents of the current block, which stops the iteration if
+    /// there is none or if it is corrupt.
+    fn load_block(&mut self) -> Option<()> {
+        if self.block >= self.reader.blocks.len() {
             return None;
         }
+        match self.reader.block_data(self.block) {
+            Ok(data) => self.data = data,
+            Err(_) => {
+                self.corrupt = true;
+                return None;
+            }
+        }
+        self.offset = 0;
+        Some(())
+    }
 
-        // deflate new key
-        let mut key = Vec::<u8>::with_capacity(key_len);
-        let key_stored_len = key_len - key_common_len;
-        key.resize(key_len, 0_u8);
-        if key_common_len > 0 {
-            // assuring last_key won't be accessed on first iteration
-            key[0..key_common_len].copy_from_slice(&self.last_key[0..key_common_len]);
+    /// Reads the entry at the current offset, checking that it lies within
+    /// the block.
+    fn read_entry(&mut self) -> IoResult<SSTableReaderPointer<'a>> {
+        // read headers of k-v pair
+        let key_len = self.read_varu64()? as usize;
+        let key_common_len = self.read_varu64()? as usize;
+        let value_len = self.read_varu64()? as usize;
+        let flags = match self.read_varu64()? {
+            flags @ (0b00000000 | 0b00000001) => flags as u8,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "unrecognized flags")),
+        };
+
+        // deflate new key, the common part of which is empty on the first
+        // iteration
+        if key_common_len > key_len || key_common_len > self.last_key.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "key out of bounds"));
         }
-        key[key_common_len..key_len]
-            .copy_from_slice(&self.region[self.offset..self.offset + key_stored_len]);
-        self.offset += key_stored_len;
-        let key = Rc::new(key);
-        self.last_key = key.clone();
+        let stored = self.take(key_len - key_common_len)?;
+        self.read_key(key_common_len, stored);
+        let key = self.last_key.clone();
 
         // get reference to value
-        let value = &self.region[self.offset..self.offset + value_len];
-        self.offset += value_len;
+        let value = self.take(value_len)?;
 
         // construct pointer
-        Some(Self::Item {
+        Ok(SSTableReaderPointer {
             _key: key,
+            _block: self.data.clone(),
             _value: value,
             _flags: flags,
-            _offset: the_offset,
         })
     }
-}
 
-impl<'a> SSTableReaderIterator<'a> {
-    /// Access VarUint64 from memory region.
-    fn read_varu64(&mut self) -> u64 {
+    /// Puts the next key together from the first `common_len` bytes of the
+    /// last one and the `stored` part of the block.
+    ///
+    /// Buffers held by pointers are left alone, so keys are only allocated
+    /// while the caller keeps both of the last two keys.
+    fn read_key(&mut self, common_len: usize, stored: Range<usize>) {
+        if Arc::strong_count(&self.last_key) > 1 {
+            if Arc::strong_count(&self.spare_key) > 1 {
+                self.spare_key = Arc::default();
+            }
+            let spare = Arc::make_mut(&mut self.spare_key);
+            spare.clear();
+            spare.extend_from_slice(&self.last_key[..common_len]);
+            mem::swap(&mut self.last_key, &mut self.spare_key);
+        }
+        let key = Arc::make_mut(&mut self.last_key);
+        key.truncate(common_len);
+        key.extend_from_slice(&self.data[stored]);
+    }
+
+    /// Skips over the next `len` bytes of the block.
+    fn take(&mut self, len: usize) -> IoResult<Range<usize>> {
+        match self.offset.checked_add(len) {
+            Some(end) if end <= self.data.len() => {
+                let range = self.offset..end;
+                self.offset = end;
+                Ok(range)
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "entry out of bounds")),
+        }
+    }
+
+    /// Block and offset of the next entry, which is at the start of the next
+    /// block once this one is used up.
+    fn position(&self) -> (usize, usize) {
+        match self.offset >= self.data.len() {
+            true => (self.block + 1, 0),
+            false => (self.block, self.offset),
+        }
+    }
+
+    /// Access VarUint64 from current block.
+    fn read_varu64(&mut self) -> IoResult<u64> {
         let offset = self.offset;
         VarUint64::read_and_seek(
-            &self.region[offset..],
+            &self.data[offset..],
             &mut self.offset,
-            self.region.len() - offset,
+            self.data.len() - offset,
         )
-        .unwrap()
+    }
+}
+
+/// SSTable reader iterator over keys in descending order.
+///
+/// Keys are compressed against the key before them, so they can only be read
+/// forward from a restart point, one of which starts every indexed entry. The
+/// entries from a restart point up to the next one are read at once, and then
+/// handed out backwards.
+///
+/// Like the ascending iterator, iteration stops early at a corrupt block or
+/// entry, which is then reported by [`status`](Self::status).
+pub struct SSTableReaderReverseIterator<'a> {
+    reader: &'a SSTableReader,
+
+    /// Number of restart points not yet read, counting down from the last.
+    restart: usize,
+
+    /// Entries read from the last restart point, the last of which is next.
+    pending: Vec<SSTableReaderPointer<'a>>,
+
+    /// Whether iteration stopped at a corrupt block or entry.
+    corrupt: bool,
+}
+
+impl<'a> Iterator for SSTableReaderReverseIterator<'a> {
+    type Item = SSTableReaderPointer<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.corrupt {
+            self.restart = self.restart.checked_sub(1)?;
+            self.read_restart(self.restart);
+        }
+        self.pending.pop()
+    }
+}
+
+impl<'a> SSTableReaderReverseIterator<'a> {
+    /// Fails if iteration stopped at a corrupt block or entry, rather than at
+    /// the start of the table.
+    pub fn status(&self) -> IoResult<()> {
+        match self.corrupt {
+            true => Err(Error::new(ErrorKind::InvalidData, "corrupt data block")),
+            false => Ok(()),
+        }
+    }
+
+    /// Reads all entries from a restart point up to the next one. Nothing is
+    /// read if any of them is corrupt.
+    fn read_restart(&mut self, restart: usize) {
+        match self.reader.read_restart(restart) {
+            Ok(entries) => self.pending = entries,
+            Err(_) => self.corrupt = true,
+        }
+    }
+}
+
+/// Position in a table that moves both ways, which is either at an entry or
+/// off one end of the table.
+///
+/// Keys can only be read forward from a restart point, so the entries from
+/// the restart point of the position up to the next one are read at once. The
+/// cursor moves within them, and only reads more on crossing to a neighbouring
+/// restart point. Like the iterators, the cursor stops at a corrupt block or
+/// entry, which is then reported by [`status`](Self::status).
+pub struct SSTableReaderCursor<'a> {
+    reader: &'a SSTableReader,
+
+    /// Restart point whose entries are read.
+    restart: usize,
+
+    /// Entries from the restart point up to the next one.
+    entries: Vec<SSTableReaderPointer<'a>>,
+
+    /// Index of the entry the cursor is at, which is -1 off the start and the
+    /// number of entries off the end.
+    index: isize,
+
+    /// Whether the cursor stopped at a corrupt block or entry.
+    corrupt: bool,
+}
+
+impl<'a> SSTableReaderCursor<'a> {
+    /// Points to the entry the cursor is at.
+    pub fn current(&self) -> Option<&SSTableReaderPointer<'a>> {
+        self.entries.get(usize::try_from(self.index).ok()?)
+    }
+
+    /// Moves to the next entry. Off the start of the table, this is the first
+    /// entry.
+    pub fn next(&mut self) {
+        if self.corrupt {
+            return;
+        }
+        self.index += 1;
+        while self.index >= self.entries.len() as isize {
+            if self.restart + 1 >= self.reader.keys.len() {
+                self.index = self.entries.len() as isize;
+                return;
+            }
+            self.load(self.restart + 1);
+            if self.corrupt {
+                return;
+            }
+            self.index = 0;
+        }
+    }
+
+    /// Moves to the previous entry. Off the end of the table, this is the
+    /// last entry.
+    pub fn prev(&mut self) {
+        if self.corrupt {
+            return;
+        }
+        self.index -= 1;
+        while self.index < 0 {
+            if self.restart == 0 {
+                self.index = -1;
+                return;
+            }
+            self.load(self.restart - 1);
+            if self.corrupt {
+                return;
+            }
+            self.index = self.entries.len() as isize - 1;
+        }
+    }
+
+    /// Fails if the cursor stopped at a corrupt block or entry.
+    pub fn status(&self) -> IoResult<()> {
+        match self.corrupt {
+            true => Err(Error::new(ErrorKind::InvalidData, "corrupt data block")),
+            false => Ok(()),
+        }
+    }
+
+    /// Reads the entries of a restart point, leaving none if any of them is
+    /// corrupt.
+    fn load(&mut self, restart: usize) {
+        self.restart = restart;
+        match self.reader.read_restart(restart) {
+            Ok(entries) => self.entries = entries,
+            Err(_) => {
+                self.entries.clear();
+                self.corrupt = true;
+            }
+        }
     }
 }
 
 /// Reader iterator (pointer) interface.
+#[derive(Clone)]
 pub struct SSTableReaderPointer<'a> {
-    /// Reference to key.
-    _key: Rc<Vec<u8>>,
+    /// Key of the entry, which is only stored in parts in the block. The
+    /// buffer is shared with the iterator until it reads on.
+    _key: Arc<Vec<u8>>,
 
-    /// Reference to value.
-    _value: &'a [u8],
+    /// Block holding the value.
+    _block: BlockData<'a>,
+
+    /// Location of value in the block.
+    _value: Range<usize>,
 
     /// Item flags.
     _flags: u8,
-
-    /// Offset from file begin.
-    _offset: usize,
 }
 
 impl<'a> KvPointer for SSTableReaderPointer<'a> {
@@ -407,16 +1165,12 @@ impl<'a> KvPointer for SSTableReaderPointer<'a> {
 
     fn value(&self) -> KvDataRef {
         match self._flags {
+            // flags are checked when reading the entry
             0b00000001_u8 => KvDataRef::Tombstone { cached: true },
-            0b00000000_u8 => KvDataRef::Value {
+            _ => KvDataRef::Value {
                 cached: true,
-                value: unsafe { utils::reborrow_slice(self._value) },
+                value: unsafe { utils::reborrow_slice(&self._block[self._value.clone()]) },
             },
-            rest => panic!("unrecognized flag {rest}"),
         }
     }
-
-    fn value_mut(&self) -> &mut KvEntry {
-        unimplemented!("sstable cannot be opened as read-write");
-    }
 }
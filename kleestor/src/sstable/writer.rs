@@ -1,11 +1,64 @@
-use crate::bloom::BloomFilter;
+use crate::bloom::sized::SizedBloom;
+use crate::bloom::{BlockedBloomFilter, BloomFilter, XorFilter};
 use crate::record::{ByteStream, KvDataRef, KvPointer};
+use crate::utils::crc32c::Crc32c;
 use crate::utils::varint::VarUint64;
 use std::fs::File;
 use std::io::{Result, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
 
-use super::MetaBlockType;
+use super::compression::Compression;
+use super::prefix::PrefixExtractor;
+use super::range::RangeFilterBuilder;
+use super::{
+    BlockHandle, Footer, MetaBlockType, BLOCK_SIZE, CHECKSUM_CRC32C, FEATURE_COMPRESSION,
+    FORMAT_VERSION,
+};
+
+/// Layouts of the filter written along with a table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterPolicy {
+    /// A [`BloomFilter`], probing positions all over the filter.
+    Bloom,
+
+    /// A [`BlockedBloomFilter`], probing a single cache line per key at the
+    /// cost of slightly more false positives.
+    BlockedBloom,
+
+    /// A [`XorFilter`], which turns down more keys than a bloom filter of the
+    /// same size. It always takes about 9.84 bits per key.
+    Xor,
+}
+
+/// Tunables for writing a table.
+#[derive(Clone, Copy)]
+pub struct WriterOptions {
+    /// Codec that data blocks are compressed with.
+    pub compression: Compression,
+
+    /// Layout of the filter.
+    pub filter: FilterPolicy,
+
+    /// Builds a bloom filter of key prefixes as well, which lets prefix scans
+    /// skip the table.
+    pub prefix_extractor: Option<PrefixExtractor>,
+
+    /// Bits of the bloom filter spent on each key. With 10 bits per key, about
+    /// 1% of the keys not in the table pass the filter. Xor filters are not
+    /// affected.
+    pub bloom_bits_per_key: usize,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::None,
+            filter: FilterPolicy::Bloom,
+            prefix_extractor: None,
+            bloom_bits_per_key: 10,
+        }
+    }
+}
 
 pub struct SSTableWriter<Pointer, Iter>
 where
@@ -24,6 +77,11 @@ where
     /// Buffer should flush writes to handle after this many bytes.
     flush_interval: usize,
 
+    /// Tunables of the table.
+    options: WriterOptions,
+    /// Holds the compressed contents of a block.
+    compressed: Vec<u8>,
+
     _marker: PhantomData<Iter>,
 }
 
@@ -33,6 +91,11 @@ where
     Iter: Iterator<Item = Pointer>,
 {
     pub fn new(handle: File) -> Self {
+        Self::with_options(handle, WriterOptions::default())
+    }
+
+    /// Creates a writer with the given tunables.
+    pub fn with_options(handle: File, options: WriterOptions) -> Self {
         let flush_interval = 4194304_usize;
         let mut buffer = Vec::<u8>::new();
         buffer.resize(flush_interval * 2, 0_u8);
@@ -43,6 +106,8 @@ where
             buffer,
             buffer_pointer: 0_usize,
             flush_interval,
+            options,
+            compressed: Vec::new(),
             _marker: PhantomData,
         }
     }
@@ -51,15 +116,21 @@ where
     pub fn write(&mut self, iter: Iter) -> Result<()> {
         // reset pointer
         self.handle.seek(SeekFrom::Start(0))?;
-        let mut block_indices = Vec::<(MetaBlockType, usize)>::new();
-
-        // assure index distance
-        let mut prev_block = 0;
-        let min_block_size = 50; // save index for every 50 keys
-        let mut indices = Vec::<usize>::new();
-
-        // prepare bloom filter
-        let mut bloom = BloomFilter::new();
+        let mut block_indices = Vec::<(MetaBlockType, BlockHandle)>::new();
+
+        // first key and location of every data block
+        let mut indices = Vec::<(ByteStream, BlockHandle)>::new();
+        let mut block = Vec::<u8>::with_capacity(BLOCK_SIZE * 2);
+        let mut block_first_key = ByteStream::new();
+
+        // hashes of all keys, which the bloom filter is sized after
+        let mut hashes = Vec::<[u32; 2]>::new();
+        // hashes of distinct key prefixes, which sorted keys share in a row
+        let mut prefix_hashes = Vec::<[u32; 2]>::new();
+        let mut last_prefix: Option<&[u8]> = None;
+        // range filter and the smallest key
+        let mut range_filter = RangeFilterBuilder::new();
+        let mut first_key: Option<ByteStream> = None;
 
         // index prefix compression
         let the_null_key = ByteStream::from_vec(vec![]);
@@ -67,8 +138,7 @@ where
 
         // start writing keys
         let mut _last_item: Pointer; // hold lifetime
-        let mut iter = iter.peekable();
-        while let Some(item) = iter.next() {
+        for item in iter {
             // fetch values
             let k: &[u8] = unsafe { std::mem::transmute(item.key()) };
             let v: KvDataRef = unsafe { std::mem::transmute(item.value()) };
@@ -81,81 +151,166 @@ where
             };
 
             // maintain bloom filter
-            let is_last_value = if let None = iter.peek() { true } else { false };
-            bloom.insert(&k);
+            hashes.push(BloomFilter::hash(k));
+            range_filter.add(k);
+            if first_key.is_none() {
+                // the first item is not kept alive until the end
+                first_key = Some(ByteStream::from_slice(k));
+            }
+            if let Some(extractor) = self.options.prefix_extractor {
+                let prefix = extractor.extract(k);
+                match prefix {
+                    Some(prefix) if Some(prefix) != last_prefix => {
+                        prefix_hashes.push(BloomFilter::hash(prefix))
+                    }
+                    _ => (),
+                }
+                last_prefix = prefix;
+            }
 
-            // compress index prefixes
-            let offset = self.tell();
+            // compress key prefixes, except for the first key in each block
             let mut common_len = 0_usize;
-
-            if offset == 0 || prev_block >= min_block_size || is_last_value {
-                // when offset is 0 (start block) or block is big enough
-                // or is the last block
-                // don't perform compression and save index position
-                indices.push(offset);
-                prev_block = 0;
+            if block.is_empty() {
+                block_first_key = ByteStream::from_slice(k);
             } else {
-                // perform compression
                 let l_ref = last_key;
                 let r_ref = k;
                 let min_len = std::cmp::min(l_ref.len(), r_ref.len());
                 while common_len < min_len && l_ref[common_len] == r_ref[common_len] {
                     common_len += 1;
                 }
-                prev_block += 1;
             }
             last_key = k;
 
-            // write key
-            self.write_kv_pair(k, common_len, &v)?;
+            // write key, closing the block once it is large enough
+            Self::write_kv_pair(&mut block, k, common_len, &v);
+            if block.len() >= BLOCK_SIZE {
+                let handle = self.write_block(&block, self.options.compression)?;
+                indices.push((
+                    std::mem::replace(&mut block_first_key, ByteStream::new()),
+                    handle,
+                ));
+                block.clear();
+            }
 
             // keep last pointer alive
             _last_item = item;
         }
-        // largest key has been saved
-
-        // write empty key marking end of data section
-        self.write_varu64(0_u64);
-        self.write_varu64(0_u64);
-        self.write_varu64(0_u64);
-        self.write_varu64(0_u8 as u64);
+        if !block.is_empty() {
+            let handle = self.write_block(&block, self.options.compression)?;
+            indices.push((block_first_key, handle));
+        }
 
         // write index block
-        // starts with 1 counter and [counter] indices, all in varuint64
-        let offset = self.tell();
-        block_indices.push((MetaBlockType::Index, offset));
-
-        self.write_varu64(indices.len() as u64);
-        for indice in indices {
-            self.write_varu64(indice as u64);
-            self.flush_buffer_lazy()?;
+        // starts with 1 counter and [counter] entries, each made of the first
+        // key in a data block (length and bytes), the block offset and size
+        block.clear();
+        Self::write_varu64(&mut block, indices.len() as u64);
+        for (key, handle) in indices {
+            Self::write_varu64(&mut block, key.len() as u64);
+            block.extend_from_slice(key.as_ref());
+            Self::write_varu64(&mut block, handle.offset as u64);
+            Self::write_varu64(&mut block, handle.size as u64);
         }
-        self.flush_buffer()?;
+        let handle = self.write_block(&block, Compression::None)?;
+        block_indices.push((MetaBlockType::Index, handle));
 
         // write bloom filter block
-        // starts with 1 counter and [counter] subsequent bytes as the filter
-        let offset = self.tell();
-        block_indices.push((MetaBlockType::BloomFilter, offset));
+        // starts with the number of hashes and 1 counter, followed by
+        // [counter] subsequent bytes as the filter
+        let bits_per_key = self.options.bloom_bits_per_key;
+        block.clear();
+        let block_type = match self.options.filter {
+            FilterPolicy::Bloom => {
+                let mut bloom = BloomFilter::new(hashes.len(), bits_per_key);
+                for hash in hashes {
+                    bloom.insert_hash(hash);
+                }
+                Self::write_varu64(&mut block, bloom.hashes() as u64);
+                Self::write_varu64(&mut block, bloom.size() as u64);
+                block.extend_from_slice(bloom.as_slice());
+                MetaBlockType::SizedBloomFilter
+            }
+            FilterPolicy::BlockedBloom => {
+                let mut bloom = BlockedBloomFilter::new(hashes.len(), bits_per_key);
+                for hash in hashes {
+                    bloom.insert_hash(hash);
+                }
+                Self::write_varu64(&mut block, bloom.hashes() as u64);
+                Self::write_varu64(&mut block, bloom.size() as u64);
+                block.extend_from_slice(bloom.as_slice());
+                MetaBlockType::BlockedBloomFilter
+            }
+            FilterPolicy::Xor => {
+                // starts with the seed instead of the number of hashes
+                let filter = XorFilter::build(hashes);
+                Self::write_varu64(&mut block, filter.seed());
+                Self::write_varu64(&mut block, filter.size() as u64);
+                block.extend_from_slice(filter.as_slice());
+                MetaBlockType::XorFilter
+            }
+        };
+        let handle = self.write_block(&block, Compression::None)?;
+        block_indices.push((block_type, handle));
+
+        // write prefix filter block
+        // starts with the extractor kind and its 2 parameters, followed by a
+        // bloom filter block of all prefixes
+        if let Some(extractor) = self.options.prefix_extractor {
+            let mut bloom = BloomFilter::new(prefix_hashes.len(), bits_per_key);
+            for hash in prefix_hashes {
+                bloom.insert_hash(hash);
+            }
+            block.clear();
+            for param in extractor.encode() {
+                Self::write_varu64(&mut block, param);
+            }
+            Self::write_varu64(&mut block, bloom.hashes() as u64);
+            Self::write_varu64(&mut block, bloom.size() as u64);
+            block.extend_from_slice(bloom.as_slice());
+            let handle = self.write_block(&block, Compression::None)?;
+            block_indices.push((MetaBlockType::PrefixFilter, handle));
+        }
 
-        self.write_varu64(bloom.size() as u64);
-        self.flush_buffer()?;
-        self.handle_pointer += bloom.write(&mut self.handle)?;
+        // write range filter block
+        let handle = self.write_block(&range_filter.finish(), Compression::None)?;
+        block_indices.push((MetaBlockType::RangeFilter, handle));
+
+        // write key range block
+        // holds the smallest and the largest key, each with its length
+        if let Some(first_key) = first_key {
+            block.clear();
+            for key in [first_key.as_ref(), last_key] {
+                Self::write_varu64(&mut block, key.len() as u64);
+                block.extend_from_slice(key);
+            }
+            let handle = self.write_block(&block, Compression::None)?;
+            block_indices.push((MetaBlockType::KeyRange, handle));
+        }
 
         // write header block
-        // contains a entry counter for all metablock offsets
-        // contains [block type: varuint64, varuint64] for each metablock
-        let offset = self.tell();
-        self.write_varu64(block_indices.len() as u64);
-        for (block_type, indice) in block_indices {
-            self.write_varu64(block_type as u8 as u64);
-            self.write_varu64(indice as u64);
+        // contains a entry counter for all metablocks
+        // contains [block type, offset, size] in varuint64 for each metablock
+        block.clear();
+        Self::write_varu64(&mut block, block_indices.len() as u64);
+        for (block_type, handle) in block_indices {
+            Self::write_varu64(&mut block, block_type as u8 as u64);
+            Self::write_varu64(&mut block, handle.offset as u64);
+            Self::write_varu64(&mut block, handle.size as u64);
         }
-
-        // a header block offset in the end
-        self.write_u64(offset as u64);
-
-        // a magic in the end
-        self.write_u64(0x1145_1419_1981_fee1_u64);
+        let handle = self.write_block(&block, Compression::None)?;
+
+        // a footer telling how to read the table in the end
+        let footer = Footer {
+            header: handle.offset,
+            features: match self.options.compression {
+                Compression::None => 0,
+                _ => FEATURE_COMPRESSION,
+            },
+            checksum: CHECKSUM_CRC32C,
+            version: FORMAT_VERSION,
+        };
+        self.write_slice(&footer.encode())?;
 
         // all done
         self.flush_buffer()?;
@@ -163,58 +318,62 @@ where
     }
 
     // writes key-value pair
-    fn write_kv_pair(&mut self, k: &[u8], k_common_len: usize, v: &KvDataRef) -> Result<()> {
+    fn write_kv_pair(block: &mut Vec<u8>, k: &[u8], k_common_len: usize, v: &KvDataRef) {
         match &v {
             KvDataRef::Tombstone { .. } => {
                 // write lengths
-                self.write_varu64(k.len() as u64);
-                self.write_varu64(k_common_len as u64);
-                self.write_varu64(0_u64);
+                Self::write_varu64(block, k.len() as u64);
+                Self::write_varu64(block, k_common_len as u64);
+                Self::write_varu64(block, 0_u64);
 
                 // write flag
-                self.write_varu64(0b00000001_u8 as u64);
+                Self::write_varu64(block, 0b00000001_u8 as u64);
 
                 // write (compressed) key
-                self.write_slice(&k[k_common_len..])?;
+                block.extend_from_slice(&k[k_common_len..]);
             }
             KvDataRef::Value { value, .. } => {
                 // write lengths
-                self.write_varu64(k.len() as u64);
-                self.write_varu64(k_common_len as u64);
-                self.write_varu64(value.len() as u64);
+                Self::write_varu64(block, k.len() as u64);
+                Self::write_varu64(block, k_common_len as u64);
+                Self::write_varu64(block, value.len() as u64);
 
                 // write flag
-                self.write_varu64(0b00000000_u8 as u64);
+                Self::write_varu64(block, 0b00000000_u8 as u64);
 
                 // write (compressed) key and value
-                self.write_slice(&k[k_common_len..])?;
-                self.write_slice(value)?;
+                block.extend_from_slice(&k[k_common_len..]);
+                block.extend_from_slice(value);
             }
         };
-
-        Ok(())
-    }
-
-    /// Writes VarUint64 value. We're assuming that this will not overflow the
-    /// buffer.
-    fn write_varu64(&mut self, value: u64) -> () {
-        self.buffer_pointer += VarUint64::as_slice(value, &mut self.buffer[self.buffer_pointer..]);
     }
 
-    /// Writes uint64 value in exactly 8 bytes.
-    fn write_u64(&mut self, value: u64) -> () {
-        let ptr = &mut self.buffer[self.buffer_pointer..];
+    /// Writes a block followed by its trailer, returning where the block is.
+    /// The block is stored as is unless compressing makes it smaller.
+    fn write_block(&mut self, block: &[u8], compression: Compression) -> Result<BlockHandle> {
+        let mut compressed = std::mem::take(&mut self.compressed);
+        let (block, kind) = match compression.compress(block, &mut compressed) {
+            true => (compressed.as_slice(), [compression as u8]),
+            false => (block, [Compression::None as u8]),
+        };
+        let handle = BlockHandle {
+            offset: self.tell(),
+            size: block.len(),
+        };
+        let checksum = Crc32c::extend(Crc32c::checksum(block), &kind);
 
-        ptr[0] = (value & 0xff) as u8;
-        ptr[1] = ((value >> 8) & 0xff) as u8;
-        ptr[2] = ((value >> 16) & 0xff) as u8;
-        ptr[3] = ((value >> 24) & 0xff) as u8;
-        ptr[4] = ((value >> 32) & 0xff) as u8;
-        ptr[5] = ((value >> 40) & 0xff) as u8;
-        ptr[6] = ((value >> 48) & 0xff) as u8;
-        ptr[7] = (value >> 56) as u8;
+        self.write_slice(block)?;
+        self.write_slice(&kind)?;
+        self.write_slice(&checksum.to_le_bytes())?;
+        self.compressed = compressed;
+        Ok(handle)
+    }
 
-        self.buffer_pointer += 8;
+    /// Appends VarUint64 value to a block.
+    fn write_varu64(block: &mut Vec<u8>, value: u64) {
+        let mut bytes = [0_u8; 9];
+        let len = VarUint64::as_slice(value, &mut bytes);
+        block.extend_from_slice(&bytes[..len]);
     }
 
     /// Writes a large string slice. Will try and fit it into buffer. If buffer
@@ -224,7 +383,7 @@ where
 
         if self.buffer_pointer + len >= self.flush_interval * 2 - 1 {
             self.flush_buffer()?;
-            self.handle.write(slice)?;
+            self.handle.write_all(slice)?;
             self.handle_pointer += len;
         } else if self.buffer_pointer + len >= self.flush_interval {
             self.buffer[self.buffer_pointer..self.buffer_pointer + len].copy_from_slice(slice);
@@ -245,18 +404,11 @@ where
     /// Forcefully flush buffer.
     fn flush_buffer(&mut self) -> Result<()> {
         if self.buffer_pointer > 0 {
-            self.handle.write(&self.buffer[0..self.buffer_pointer])?;
+            self.handle
+                .write_all(&self.buffer[0..self.buffer_pointer])?;
             self.handle_pointer += self.buffer_pointer;
             self.buffer_pointer = 0;
         }
         Ok(())
     }
-
-    /// Flush buffer only if it'd exceeded size limit.
-    fn flush_buffer_lazy(&mut self) -> Result<()> {
-        if self.buffer_pointer > self.flush_interval {
-            self.flush_buffer()?;
-        }
-        Ok(())
-    }
 }
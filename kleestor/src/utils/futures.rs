@@ -1,5 +1,5 @@
 pub use futures::lock::Mutex;
 pub use futures_locks::RwLock;
-pub use std::sync::Mutex as MutexSync;
+pub use tokio::sync::oneshot;
 pub use tokio::sync::Notify;
 pub use tokio::sync::Semaphore;
@@ -60,6 +60,9 @@ impl VarUint64 {
         };
 
         // read byte #1
+        if length < 2 {
+            return Err(Error::new(ErrorKind::InvalidData, "out of bounds"));
+        }
         let byte1 = ptr[1] as u64;
         let len = byte1 >> 5;
         result |= (byte1 & 0b_00011111) << 7;